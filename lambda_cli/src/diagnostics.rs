@@ -0,0 +1,143 @@
+use lambda_note_lib::{Diagnostic, OriginName, Span};
+use std::borrow::Cow;
+use std::fs;
+
+// do not print more than this many lines of source for a single diagnostic
+const MAX_EXCERPT_LINES: usize = 5;
+
+/// Print all diagnostics to stderr, compiler style, with
+/// an excerpt of the source text when it is known.
+pub fn print_diagnostics(diagnostics: &[Diagnostic], source: &str, doc_name: &str) {
+    for diagnostic in diagnostics {
+        eprintln!("{}\n", render(diagnostic, source, doc_name));
+    }
+
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    let warnings = diagnostics.len() - errors;
+
+    if errors > 0 || warnings > 0 {
+        eprintln!(
+            "{} {}, {} {}",
+            errors,
+            if errors == 1 { "error" } else { "errors" },
+            warnings,
+            if warnings == 1 { "warning" } else { "warnings" }
+        );
+    }
+}
+
+/// Render a single diagnostic, e.g.
+/// ```text
+/// error[unknown-extension]: No extension found with the name of foo
+///  --> notes.ln:3:6
+///   |
+/// 3 | Some |foo| text
+///   |      ^^^^^
+///   = help: new extensions can be added with |define, name, command|
+/// ```
+/// Diagnostics from expansions also list the chain of
+/// extensions that led from the source file to the problem.
+pub fn render(diagnostic: &Diagnostic, source: &str, doc_name: &str) -> String {
+    // diagnostics from expansions are shown at the
+    // expression in the source file that started them
+    let origin = match &diagnostic.origin {
+        Some(origin) => origin.root(),
+        None => return diagnostic.to_string(),
+    };
+
+    // spans are only meaningful in the text they were parsed from,
+    // which is read again if it is an included file
+    let (source, doc_name) = match &origin.name {
+        OriginName::Filename(name) if name == doc_name => (Cow::Borrowed(source), doc_name),
+        OriginName::Filename(name) => match fs::read_to_string(name) {
+            Ok(included) => (Cow::Owned(included), name.as_str()),
+            Err(_) => return diagnostic.to_string(),
+        },
+        OriginName::Expansion(..) => return diagnostic.to_string(),
+    };
+    if origin.span.is_unknown() {
+        return diagnostic.to_string();
+    }
+
+    let excerpt = match excerpt(&source, &origin.span) {
+        Some(excerpt) => excerpt,
+        None => return diagnostic.to_string(),
+    };
+
+    let gutter = " ".repeat(origin.span.end.line.to_string().len());
+    let mut output = format!(
+        "{}\n{}--> {}:{}:{}\n{} |",
+        diagnostic.headline(),
+        gutter,
+        doc_name,
+        origin.span.start.line,
+        origin.span.start.column,
+        gutter
+    );
+
+    for (line_number, line, marker) in excerpt {
+        output.push_str(&format!(
+            "\n{:>width$} | {}",
+            line_number,
+            line,
+            width = gutter.len()
+        ));
+        if !marker.trim().is_empty() {
+            output.push_str(&format!("\n{} | {}", gutter, marker));
+        }
+    }
+
+    for note in diagnostic.backtrace() {
+        output.push_str(&format!("\n{} = note: {}", gutter, note));
+    }
+
+    if let Some(help) = &diagnostic.help {
+        output.push_str(&format!("\n{} = help: {}", gutter, help));
+    }
+
+    output
+}
+
+/// The lines that a span covers together with a marker line
+/// that underlines the part of each line that is included in the span
+fn excerpt<'a>(source: &'a str, span: &Span) -> Option<Vec<(usize, &'a str, String)>> {
+    let mut result = vec![];
+
+    for (index, line) in source.lines().enumerate().skip(span.start.line - 1) {
+        let line_number = index + 1;
+        if line_number > span.end.line || result.len() == MAX_EXCERPT_LINES {
+            break;
+        }
+
+        let line = line.trim_end_matches('\r');
+        let length = line.chars().count();
+
+        // columns start at 1 and the end column is exclusive
+        let first = if line_number == span.start.line {
+            span.start.column - 1
+        } else {
+            line.chars().take_while(|c| c.is_whitespace()).count()
+        };
+        let last = if line_number == span.end.line {
+            (span.end.column - 1).min(length)
+        } else {
+            length
+        };
+
+        // keep tabs in the padding so that the marker lines up with the text
+        let padding: String = line
+            .chars()
+            .take(first)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let marker = format!("{}{}", padding, "^".repeat(last.saturating_sub(first)));
+
+        result.push((line_number, line, marker));
+    }
+
+    if result.is_empty() {
+        None
+    } else {
+        Some(result)
+    }
+}
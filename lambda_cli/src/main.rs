@@ -1,148 +1,807 @@
-use lambda_note_lib::{DocumentState, Html, Latex, Translator, WebPreview};
+mod diagnostics;
+mod server;
+
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use diagnostics::print_diagnostics;
+use lambda_note_lib::{
+    format_document, import_markdown, print_document, Diagnostic, DocumentState, Html,
+    HtmlTemplate, LambdaNote, Latex, LatexTemplate, Markdown, Translator, WebPreview,
+};
 use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
-use std::process::Command;
+use server::{PreviewServer, PREVIEW_PORT};
+use std::io::{self, Read};
+use std::process::{self, Command};
 use std::sync::mpsc::channel;
 use std::{
     env, fs,
     path::{Path, PathBuf},
     time::Duration,
 };
-use tempfile::Builder;
 
-/// A simple CLI program to test lambda note
-/// Usage: lambda <INPUT FILE> <OUTPUT FILE>
-/// If only given one arg, a file watcher and a live updating
-/// server is started to live preview a html document.
+/// The name used for documents that are read from stdin
+const STDIN_NAME: &str = "<stdin>";
+
+/// Translate, preview and check λnote documents
+#[derive(Parser)]
+#[command(name = "lambda", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Translate a document to HTML, LaTeX, Markdown or λnote
+    Build {
+        /// The document to translate, use - to read from stdin
+        input: String,
+        /// Where to write the result, use - for stdout. Other formats
+        /// than the native ones are converted from LaTeX by pandoc.
+        #[arg(short, long)]
+        output: Option<String>,
+        /// The output format, by default it is picked from the output file
+        #[arg(short, long, value_enum)]
+        format: Option<Format>,
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Start a live updating preview of a document in the browser
+    Preview {
+        input: PathBuf,
+        /// The port to serve the preview at
+        #[arg(short, long, default_value_t = PREVIEW_PORT)]
+        port: u16,
+        /// A HTML template to use instead of the default one
+        #[arg(short, long)]
+        template: Option<PathBuf>,
+        /// Do not run extensions that are not trusted, such as shell commands
+        #[arg(long)]
+        safe: bool,
+        #[command(flatten)]
+        cache: CacheOptions,
+    },
+    /// Report all errors and warnings without writing any output
+    Check {
+        /// The documents to check, use - to read from stdin
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        /// Check the document as it would be translated to this format
+        #[arg(short, long, value_enum, default_value_t = Format::Html)]
+        format: Format,
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Pretty print λnote documents
+    Fmt {
+        #[arg(required = true)]
+        files: Vec<String>,
+        /// Only list the files that are not formatted
+        #[arg(long, conflicts_with = "in_place")]
+        check: bool,
+        /// Overwrite the files with the formatted result
+        #[arg(short, long)]
+        in_place: bool,
+    },
+    /// Convert a Markdown document to λnote
+    Import {
+        /// The Markdown document, use - to read from stdin
+        input: String,
+        /// Where to write the result, stdout by default
+        output: Option<String>,
+    },
+}
+
+#[derive(Args)]
+struct Options {
+    /// A HTML or LaTeX template to use instead of the default one, overrides
+    /// the template picked by the document with `:: template = file.html`
+    #[arg(short, long)]
+    template: Option<PathBuf>,
+    /// Do not run extensions that are not trusted, such as shell commands
+    #[arg(long)]
+    safe: bool,
+    /// Fail if there are any warnings, useful in CI
+    #[arg(long)]
+    deny_warnings: bool,
+    #[command(flatten)]
+    cache: CacheOptions,
+}
+
+#[derive(Args)]
+struct CacheOptions {
+    /// Where to cache the output of extensions defined with `define`
+    #[arg(long, value_name = "DIRECTORY")]
+    cache_dir: Option<PathBuf>,
+    /// Call the extensions every time instead of using their cached output
+    #[arg(long, conflicts_with = "cache_dir")]
+    no_cache: bool,
+}
+
+impl CacheOptions {
+    /// The directory given on the command line, or the cache directory
+    /// of the user, or of the system if there is no such directory
+    fn directory(&self) -> Option<PathBuf> {
+        if self.no_cache {
+            return None;
+        }
+        if let Some(directory) = &self.cache_dir {
+            return Some(directory.clone());
+        }
+
+        let home = env::var_os("HOME").map(PathBuf::from);
+        let user_cache = if cfg!(target_os = "windows") {
+            env::var_os("LOCALAPPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            home.map(|home| home.join("Library").join("Caches"))
+        } else {
+            env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| home.map(|home| home.join(".cache")))
+        };
+        Some(user_cache.unwrap_or_else(env::temp_dir).join("lambda_note"))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Html,
+    #[value(alias = "tex")]
+    Latex,
+    #[value(alias = "md")]
+    Markdown,
+    #[value(name = "lambdanote", alias = "ln")]
+    LambdaNote,
+}
+
+/// The different ways of exiting the program
+#[derive(Debug)]
+enum Failure {
+    /// The document had errors, or warnings that were denied
+    Diagnostics,
+    /// Something went wrong reading or writing files and so on
+    Io(String),
+}
+
+impl From<io::Error> for Failure {
+    fn from(error: io::Error) -> Self {
+        Failure::Io(error.to_string())
+    }
+}
+
 fn main() {
-    // collect cli args
-    let args: Vec<String> = env::args().skip(1).collect();
+    let cli = Cli::parse_from(legacy_arguments(env::args().collect()));
 
-    match args.len() {
-        1 => live_preview(args),
-        2 => single_run(args),
-        _ => {
-            println!("Usage: lambda <INPUT FILE> <OUTPUT FILE>");
-            println!("You can omit the output file to start a live html preview.");
+    let result = match cli.command {
+        Commands::Build {
+            input,
+            output,
+            format,
+            options,
+        } => build(&input, output.as_deref(), format, &options),
+        Commands::Preview {
+            input,
+            port,
+            template,
+            safe,
+            cache,
+        } => live_preview(&input, port, template.as_deref(), safe, &cache),
+        Commands::Check {
+            inputs,
+            format,
+            options,
+        } => check(&inputs, format, &options),
+        Commands::Fmt {
+            files,
+            check,
+            in_place,
+        } => format_files(&files, check, in_place),
+        Commands::Import { input, output } => import(&input, output.as_deref()),
+    };
+
+    match result {
+        Ok(()) => (),
+        Err(Failure::Diagnostics) => process::exit(1),
+        Err(Failure::Io(error)) => {
+            eprintln!("error: {}", error);
+            process::exit(1);
         }
     }
 }
 
-/// Read the file once, transpile it to the correct output format and write to
-/// the given output file.
-fn single_run(args: Vec<String>) {
-    let input_file: PathBuf = args.get(0).expect("No input file was provided").into();
-    let output_file: PathBuf = args.get(1).expect("no output file was provided").into();
+/// The cli used to be called as `lambda <INPUT FILE> [<OUTPUT FILE>]`,
+/// which is still supported by turning it into a build or preview command
+fn legacy_arguments(mut args: Vec<String>) -> Vec<String> {
+    let is_legacy = match args.get(1) {
+        Some(first) => {
+            let is_command = first == "help"
+                || Cli::command()
+                    .get_subcommands()
+                    .any(|command| command.get_name() == first);
+            !first.starts_with('-') && !is_command && args.len() <= 3
+        }
+        None => false,
+    };
 
-    match output_file.extension() {
-        None => {
-            println!("You need to have a file extension for the output file");
-            return;
+    if is_legacy {
+        match args.len() {
+            2 => args.insert(1, "preview".into()),
+            _ => {
+                args.insert(1, "build".into());
+                args.insert(3, "--output".into());
+            }
         }
-        Some(extension) => match extension.to_str() {
-            Some("tex") => translate(&input_file, &output_file, Latex),
-            Some("html") => translate(&input_file, &output_file, Html),
+    }
+
+    args
+}
+
+/// Translate a document once and write the result to the output, the format
+/// is given explicitly or picked from the extension of the output file.
+/// Formats without native support are generated by invoking pandoc on LaTeX.
+fn build(
+    input: &str,
+    output: Option<&str>,
+    format: Option<Format>,
+    options: &Options,
+) -> Result<(), Failure> {
+    let (content, doc_name) = read_input(input)?;
+    let output = output.unwrap_or("-");
 
-            // The program will try to resolve non native output formats
-            // by passing a tex file to pandoc.
-            Some(extension_str) => {
-                println!("No native support for .{} files", extension_str);
-                println!("Generating a .tex file and forwarding it to pandoc");
+    let extension = match output {
+        "-" => None,
+        output => Path::new(output)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase()),
+    };
 
-                let mut latex_file: PathBuf =
-                    output_file.clone().file_stem().expect("No filename").into();
+    let native_format = match extension.as_deref() {
+        Some("html") | Some("htm") => Some(Format::Html),
+        Some("tex") => Some(Format::Latex),
+        Some("md") | Some("markdown") => Some(Format::Markdown),
+        Some("ln") => Some(Format::LambdaNote),
+        _ => None,
+    };
 
-                latex_file.set_extension("tex");
+    let format = match (format, native_format, &extension) {
+        (Some(format), _, _) | (None, Some(format), _) => format,
+        (None, None, None) => Format::Html,
+        // The program will try to resolve non native output formats
+        // by passing a tex file to pandoc.
+        (None, None, Some(extension)) => {
+            println!("No native support for .{} files", extension);
+            println!("Generating a .tex file and forwarding it to pandoc");
 
-                translate(&input_file, &latex_file, Latex);
-                pandoc(&latex_file, &output_file);
+            let (latex, diagnostics) = translate(&content, &doc_name, Format::Latex, options)?;
+            report(&diagnostics, &content, &doc_name, options)?;
+
+            let mut latex_file: PathBuf = Path::new(output).file_stem().unwrap_or_default().into();
+            latex_file.set_extension("tex");
+            fs::write(&latex_file, latex)?;
+            return pandoc(&latex_file, Path::new(output));
+        }
+    };
+
+    let (result, diagnostics) = translate(&content, &doc_name, format, options)?;
+
+    // problems are reported before anything is written
+    let reported = report(&diagnostics, &content, &doc_name, options);
+    write_output(output, &result)?;
+    reported
+}
+
+/// Report the problems of documents without writing any output
+fn check(inputs: &[String], format: Format, options: &Options) -> Result<(), Failure> {
+    let mut failed = false;
+
+    for input in inputs {
+        let (content, doc_name) = read_input(input)?;
+        let (_, diagnostics) = translate(&content, &doc_name, format, options)?;
+        failed |= report(&diagnostics, &content, &doc_name, options).is_err();
+    }
+
+    if failed {
+        Err(Failure::Diagnostics)
+    } else {
+        Ok(())
+    }
+}
+
+/// Translate a document in the given format
+fn translate(
+    content: &str,
+    doc_name: &str,
+    format: Format,
+    options: &Options,
+) -> Result<(String, Vec<Diagnostic>), Failure> {
+    let mut doc = match format {
+        Format::Html => DocumentState::new(Html),
+        Format::Latex => DocumentState::new(Latex),
+        Format::Markdown => DocumentState::new(Markdown),
+        Format::LambdaNote => DocumentState::new(LambdaNote),
+    };
+    doc.set_safe_mode(options.safe);
+    doc.set_cache_directory(options.cache.directory());
+    let result = doc.translate_no_template(content, doc_name);
+
+    let has_templates = matches!(format, Format::Html | Format::Latex);
+    if !has_templates && options.template.is_some() {
+        return Err(Failure::Io(
+            "Templates can only be used with HTML and LaTeX".into(),
+        ));
+    }
+
+    let result = match template_file(options.template.as_deref(), &mut doc, doc_name, format) {
+        Some(template) => apply_custom_template(&doc, &result, &template, format, false)?,
+        None => doc.apply_template(&result),
+    };
+
+    Ok((result, doc.diagnostics))
+}
+
+/// The template given on the command line, or the one picked by the document
+/// with a metadata field, which is relative to the document. The fields
+/// `html_template` and `latex_template` are used for their formats,
+/// and `template` for LaTeX if it is a .tex file and HTML otherwise.
+/// In safe mode the document can not pick a template, since it could be any file.
+fn template_file(
+    flag: Option<&Path>,
+    doc: &mut DocumentState,
+    doc_name: &str,
+    format: Format,
+) -> Option<PathBuf> {
+    if let Some(template) = flag {
+        return Some(template.to_path_buf());
+    }
+
+    let (key, is_latex) = match format {
+        Format::Html => ("html_template", false),
+        Format::Latex => ("latex_template", true),
+        _ => return None,
+    };
+
+    let template = doc.metadata.get(key).or_else(|| {
+        let template = doc.metadata.get("template")?;
+        (template.trim().ends_with(".tex") == is_latex).then_some(template)
+    })?;
+
+    let template = PathBuf::from(template.trim());
+    if doc.safe_mode() {
+        doc.add_diagnostic(
+            Diagnostic::warning(&format!(
+                "The template {} is not used in safe mode",
+                template.display()
+            ))
+            .with_code("unsafe-template")
+            .with_help("templates can be given with --template instead"),
+        );
+        return None;
+    }
+
+    let directory = match doc_name {
+        STDIN_NAME => None,
+        doc_name => Path::new(doc_name).parent(),
+    };
+
+    Some(match directory {
+        Some(directory) => directory.join(template),
+        None => template,
+    })
+}
+
+/// Wrap translated content in a HTML or LaTeX template file
+fn apply_custom_template(
+    doc: &DocumentState,
+    content: &str,
+    template: &Path,
+    format: Format,
+    include_preview_code: bool,
+) -> Result<String, Failure> {
+    let source = fs::read_to_string(template).map_err(|error| {
+        Failure::Io(format!(
+            "Failed to read the template {}: {}",
+            template.display(),
+            error
+        ))
+    })?;
+
+    // partials are relative to the template
+    let directory = match template.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let (translator, checked): (Box<dyn Translator>, _) = match format {
+        Format::Latex => {
+            let translator = LatexTemplate::new(&source).with_partial_directory(directory);
+            let checked = translator.check();
+            (Box::new(translator), checked)
+        }
+        _ => {
+            let translator =
+                HtmlTemplate::new(&source, include_preview_code).with_partial_directory(directory);
+            let checked = translator.check();
+            (Box::new(translator), checked)
+        }
+    };
+
+    checked.map_err(|error| {
+        Failure::Io(format!(
+            "Invalid template {}, {}",
+            template.display(),
+            error
+        ))
+    })?;
+
+    Ok(translator.template(content, &doc.top, &doc.bottom, &doc.imports, &doc.metadata))
+}
+
+/// Print the diagnostics of a document, fails if there
+/// are errors or if there are warnings and they are denied
+fn report(
+    diagnostics: &[Diagnostic],
+    content: &str,
+    doc_name: &str,
+    options: &Options,
+) -> Result<(), Failure> {
+    print_diagnostics(diagnostics, content, doc_name);
+
+    let has_errors = diagnostics.iter().any(Diagnostic::is_error);
+    let has_warnings = diagnostics.iter().any(|d| !d.is_error());
+
+    if has_errors || (options.deny_warnings && has_warnings) {
+        Err(Failure::Diagnostics)
+    } else {
+        Ok(())
+    }
+}
+
+/// Read a file, or stdin if the path is -, along with the name of the document
+fn read_input(input: &str) -> Result<(String, String), Failure> {
+    if input == "-" {
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content)?;
+        return Ok((content, STDIN_NAME.to_string()));
+    }
+
+    fs::read_to_string(input)
+        .map(|content| (content, input.to_string()))
+        .map_err(|error| Failure::Io(format!("Failed to read {}: {}", input, error)))
+}
+
+/// Write to a file, or stdout if the path is -
+fn write_output(output: &str, content: &str) -> Result<(), Failure> {
+    if output == "-" {
+        print!("{}", content);
+        return Ok(());
+    }
+
+    fs::write(output, content)
+        .map_err(|error| Failure::Io(format!("Failed to write {}: {}", output, error)))
+}
+
+/// Pretty print λnote documents. By default the result is written to stdout,
+/// `--in-place` overwrites the files and `--check` only reports the files
+/// that are not formatted. Fails if any file is not formatted
+/// (in check mode) or can not be formatted.
+fn format_files(files: &[String], check: bool, in_place: bool) -> Result<(), Failure> {
+    let mut failed = false;
+    for file in files {
+        let content = match fs::read_to_string(file) {
+            Ok(content) => content,
+            Err(error) => {
+                eprintln!("Failed to read {}: {}", file, error);
+                failed = true;
+                continue;
+            }
+        };
+
+        let formatted = match format_document(&content, file) {
+            Ok(formatted) => formatted,
+            Err(diagnostic) => {
+                print_diagnostics(&[*diagnostic], &content, file);
+                failed = true;
+                continue;
             }
+        };
 
-            None => {
-                println!("You need to have a file extension for the output file");
-                return;
+        if check {
+            if formatted != content {
+                println!("{} is not formatted", file);
+                failed = true;
             }
-        },
+        } else if in_place {
+            if formatted != content {
+                if let Err(error) = fs::write(file, formatted) {
+                    eprintln!("Failed to write {}: {}", file, error);
+                    failed = true;
+                }
+            }
+        } else {
+            print!("{}", formatted);
+        }
+    }
+
+    if failed {
+        Err(Failure::Diagnostics)
+    } else {
+        Ok(())
     }
 }
 
-/// Given a translator and input write to an output file.
-fn translate<T: Translator + 'static>(input_file: &Path, output_file: &Path, translator: T) {
-    let content = fs::read_to_string(input_file).expect("Something went wrong reading the file");
-    let mut doc = DocumentState::new(translator);
-    let result = doc.translate(&content, "preview");
+/// Convert a Markdown document to λnote, the result is written to the output
+/// file if one is given and otherwise to stdout. Everything that could
+/// not be converted is reported as warnings.
+fn import(input: &str, output: Option<&str>) -> Result<(), Failure> {
+    let (content, doc_name) = read_input(input)?;
 
-    println!(
-        "errors:\n{}\nwarnings:{}",
-        doc.errors.join("\n"),
-        doc.warnings.join("\n")
-    );
+    let (blocks, diagnostics) = import_markdown(&content, &doc_name);
+    print_diagnostics(&diagnostics, &content, &doc_name);
 
-    fs::write(output_file, result).expect("Unable to write file");
+    write_output(output.unwrap_or("-"), &print_document(&blocks))
 }
 
 /// Invoke pandoc to translate between two formats
-fn pandoc(input_file: &PathBuf, output_file: &PathBuf) {
-    match Command::new("pandoc")
+fn pandoc(input_file: &Path, output_file: &Path) -> Result<(), Failure> {
+    let status = Command::new("pandoc")
         .arg(input_file)
-        .arg(format!("-o {}", output_file.to_string_lossy()))
-        .output()
-    {
-        Err(error) => {
-            println!("Failed to invoke pandoc. Error: {}", error);
-        }
-        Ok(_) => (),
+        .arg("-o")
+        .arg(output_file)
+        .status()
+        .map_err(|error| Failure::Io(format!("Failed to invoke pandoc: {}", error)))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Failure::Io(format!("pandoc failed with {}", status)))
     }
 }
 
-/// Start a HTML live preview.
-/// Invokes the external node program "liveserver" and autoupdates  
-fn live_preview(args: Vec<String>) {
-    let input_file: PathBuf = args.get(0).expect("No input file was provided").into();
-
-    println!("Starting a live updating version");
-
-    let tempfile = Builder::new()
-        .prefix("preview")
-        .suffix(".html")
-        .rand_bytes(10)
-        .tempfile_in("")
-        .expect("Failed to create a temp file");
+/// Start a HTML live preview. The document is served by a small
+/// built-in server that reloads the page whenever the file is changed.
+fn live_preview(
+    input_file: &Path,
+    port: u16,
+    template: Option<&Path>,
+    safe: bool,
+    cache: &CacheOptions,
+) -> Result<(), Failure> {
+    // local assets are resolved relative to the document
+    let root = match input_file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
 
-    println!("tempfile path: {}", tempfile.path().to_string_lossy());
+    let server = PreviewServer::start(&root, port)
+        .map_err(|error| Failure::Io(format!("Failed to start the preview server: {}", error)))?;
 
     let (tx_watcher, rx_watcher) = channel();
-    let mut watcher: RecommendedWatcher = Watcher::new(tx_watcher, Duration::from_secs(1)).unwrap();
+    let mut watcher: RecommendedWatcher = Watcher::new(tx_watcher, Duration::from_secs(1))
+        .map_err(|error| Failure::Io(error.to_string()))?;
 
     watcher
-        .watch(&input_file, RecursiveMode::Recursive)
-        .unwrap();
+        .watch(input_file, RecursiveMode::Recursive)
+        .map_err(|error| Failure::Io(format!("Failed to watch the file: {}", error)))?;
+
+    // the template and included files are watched as well, once we know which they are
+    let mut watched: Vec<PathBuf> = vec![];
+    let cache_directory = cache.directory();
+    let mut rerender = |watcher: &mut RecommendedWatcher| match render_preview(
+        input_file,
+        template,
+        safe,
+        cache_directory.clone(),
+    ) {
+        Ok((page, dependencies)) => {
+            server.update(page);
+            for file in dependencies.iter().filter(|file| !watched.contains(file)) {
+                let _ = watcher.watch(file, RecursiveMode::NonRecursive);
+            }
+            for file in watched.iter().filter(|file| !dependencies.contains(file)) {
+                let _ = watcher.unwatch(file);
+            }
+            watched = dependencies;
+        }
+        Err(Failure::Io(error)) => eprintln!("error: {}", error),
+        Err(Failure::Diagnostics) => (),
+    };
 
-    translate(&input_file, tempfile.path(), WebPreview::new());
+    rerender(&mut watcher);
 
-    if let Err(error) = Command::new("cmd")
-        .arg("/C")
-        .arg("live-server")
-        .arg(tempfile.path())
-        .spawn()
-    {
-        println!("Failed to start the live-server, error: {}", error);
-        return;
-    }
+    let url = format!("http://localhost:{}", port);
+    println!("Serving a live preview at {}", url);
+    open_browser(&url);
 
     let (tx_exit, rx_exit) = channel();
     ctrlc::set_handler(move || tx_exit.send(()).expect("Failed to send ctrl+c signal"))
         .expect("Error setting Ctrl-C handler");
 
     loop {
-        if let Ok(DebouncedEvent::Write(_)) = rx_watcher.try_recv() {
-            println!("\n\n=== The file was rerendered ===");
-            translate(&input_file, tempfile.path(), Html);
+        // many editors save by replacing the file rather than writing to it
+        match rx_watcher.recv_timeout(Duration::from_millis(100)) {
+            Ok(DebouncedEvent::Write(_)) | Ok(DebouncedEvent::Create(_)) => {
+                println!("\n\n=== The file was rerendered ===");
+                rerender(&mut watcher);
+            }
+            Ok(DebouncedEvent::Remove(file)) => {
+                // the file is watched again once it has been replaced
+                let _ = watcher.watch(file, RecursiveMode::NonRecursive);
+            }
+            _ => (),
         }
 
         // check if the user wants to exit the program
-        if let Ok(_) = rx_exit.try_recv() {
-            println!("");
-            break;
+        if rx_exit.try_recv().is_ok() {
+            println!();
+            return Ok(());
         }
     }
 }
+
+/// Translate a file for the preview and report any problems to stderr,
+/// returns the page together with the other files that it was made from,
+/// i.e. the template and the included files
+fn render_preview(
+    input_file: &Path,
+    template: Option<&Path>,
+    safe: bool,
+    cache_directory: Option<PathBuf>,
+) -> Result<(String, Vec<PathBuf>), Failure> {
+    let content = fs::read_to_string(input_file)?;
+    let doc_name = input_file.to_string_lossy().to_string();
+
+    let mut doc = DocumentState::new(WebPreview::new());
+    doc.set_safe_mode(safe);
+    doc.set_cache_directory(cache_directory);
+    let result = doc.translate_no_template(&content, doc_name.as_str());
+    print_diagnostics(&doc.diagnostics, &content, &doc_name);
+
+    let template = template_file(template, &mut doc, &doc_name, Format::Html);
+    let page = match &template {
+        Some(template) => apply_custom_template(&doc, &result, template, Format::Html, true)?,
+        None => doc.apply_template(&result),
+    };
+
+    let mut dependencies = doc.included_files;
+    dependencies.extend(template);
+    Ok((page, dependencies))
+}
+
+/// Try to open a url in the default browser
+fn open_browser(url: &str) {
+    let result = if cfg!(target_os = "windows") {
+        Command::new("cmd").args(["/C", "start", url]).spawn()
+    } else if cfg!(target_os = "macos") {
+        Command::new("open").arg(url).spawn()
+    } else {
+        Command::new("xdg-open").arg(url).spawn()
+    };
+
+    if result.is_err() {
+        println!("Open {} in your browser to see the preview", url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(safe: bool, template: Option<PathBuf>) -> Options {
+        Options {
+            template,
+            safe,
+            deny_warnings: false,
+            cache: CacheOptions {
+                cache_dir: None,
+                no_cache: true,
+            },
+        }
+    }
+
+    #[test]
+    fn legacy_commands() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            legacy_arguments(args(&["lambda", "doc.ln"])),
+            args(&["lambda", "preview", "doc.ln"]),
+            "Testing that a single file is previewed"
+        );
+        assert_eq!(
+            legacy_arguments(args(&["lambda", "doc.ln", "doc.html"])),
+            args(&["lambda", "build", "doc.ln", "--output", "doc.html"]),
+            "Testing that a file and an output file are built"
+        );
+        for unchanged in [
+            &["lambda"][..],
+            &["lambda", "build", "doc.ln"],
+            &["lambda", "check", "a.ln", "b.ln"],
+            &["lambda", "help"],
+            &["lambda", "--version"],
+            &["lambda", "a.ln", "b.ln", "c.ln"],
+        ] {
+            assert_eq!(
+                legacy_arguments(args(unchanged)),
+                args(unchanged),
+                "Testing that commands are left as they are"
+            );
+        }
+
+        let cli = Cli::try_parse_from(legacy_arguments(args(&["lambda", "doc.ln", "doc.md"])));
+        assert!(
+            matches!(
+                cli.map(|cli| cli.command),
+                Ok(Commands::Build { input, output: Some(output), format: None, .. })
+                    if input == "doc.ln" && output == "doc.md"
+            ),
+            "Testing that the legacy form is parsed as a build command"
+        );
+    }
+
+    #[test]
+    fn denied_warnings() {
+        let mut doc = DocumentState::new(Html);
+        doc.translate_no_template("|ref, x|", "doc");
+        let warnings = doc.diagnostics;
+        let mut doc = DocumentState::new(Html);
+        doc.translate_no_template("|nope|", "doc");
+        let errors = doc.diagnostics;
+
+        let mut options = options(false, None);
+        assert!(
+            report(&warnings, "", "doc", &options).is_ok(),
+            "Testing that warnings do not fail by default"
+        );
+        assert!(
+            matches!(
+                report(&errors, "", "doc", &options),
+                Err(Failure::Diagnostics)
+            ),
+            "Testing that errors fail"
+        );
+        options.deny_warnings = true;
+        assert!(
+            matches!(
+                report(&warnings, "", "doc", &options),
+                Err(Failure::Diagnostics)
+            ),
+            "Testing that denied warnings fail"
+        );
+    }
+
+    #[test]
+    fn safe_templates() {
+        let directory = env::temp_dir().join(format!("lambda_cli_templates_{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("secret.txt"), "secret").unwrap();
+        fs::write(directory.join("page.html"), "<main>{{content}}</main>").unwrap();
+        let doc_name = directory.join("doc.ln").display().to_string();
+
+        for key in ["template", "html_template"] {
+            let source = format!(":: {} = secret.txt\n\nHi", key);
+            let (result, diagnostics) =
+                translate(&source, &doc_name, Format::Html, &options(true, None)).unwrap();
+            assert!(
+                !result.contains("secret"),
+                "Testing that the document can not pick a template in safe mode"
+            );
+            assert_eq!(
+                diagnostics[0].code.as_deref(),
+                Some("unsafe-template"),
+                "Testing that ignored templates are reported"
+            );
+
+            let (result, _) =
+                translate(&source, &doc_name, Format::Html, &options(false, None)).unwrap();
+            assert_eq!(result, "secret", "Testing templates picked by the document");
+        }
+
+        let flag = Some(directory.join("page.html"));
+        let (result, diagnostics) =
+            translate("Hi", &doc_name, Format::Html, &options(true, flag)).unwrap();
+        assert_eq!(
+            (result.as_str(), diagnostics.len()),
+            ("<main><p>Hi</p>\n</main>", 0),
+            "Testing that templates given on the command line are used in safe mode"
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
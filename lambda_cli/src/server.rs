@@ -0,0 +1,222 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::{fs, io, thread};
+
+/// The WebPreview template expects local files to be served at this port
+pub const PREVIEW_PORT: u16 = 5432;
+
+// added to the end of the page so that it reloads when the document changes
+const RELOAD_SCRIPT: &str = r#"<script>
+    new EventSource("/events").addEventListener("message", event => {
+        if (event.data === "reload")
+            window.location.reload()
+    });
+</script>"#;
+
+/// A small HTTP server for previewing a rendered document. The document is
+/// served at `/`, other paths are read from the directory of the source file
+/// so that images and other local assets work, and `/events` is a stream of
+/// server-sent events that tells the page to reload.
+#[derive(Clone)]
+pub struct PreviewServer {
+    page: Arc<RwLock<String>>,
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+    root: PathBuf,
+}
+
+impl PreviewServer {
+    /// Start serving on localhost in a background thread
+    pub fn start(root: &Path, port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        let server = PreviewServer {
+            page: Arc::new(RwLock::new(String::new())),
+            clients: Arc::new(Mutex::new(vec![])),
+            root: root.canonicalize()?,
+        };
+
+        let handle = server.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let server = handle.clone();
+                thread::spawn(move || {
+                    if let Err(error) = server.respond(stream) {
+                        eprintln!("Preview server error: {}", error);
+                    }
+                });
+            }
+        });
+
+        Ok(server)
+    }
+
+    /// Replace the served page and tell all open pages to reload
+    pub fn update(&self, page: String) {
+        *self.page.write().unwrap() = page;
+
+        // pages that have been closed are dropped
+        self.clients
+            .lock()
+            .unwrap()
+            .retain(|mut client| client.write_all(b"data: reload\n\n").is_ok());
+    }
+
+    fn respond(&self, mut stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+
+        // the headers are not needed, but they have to be read
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 2 {
+            header.clear();
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (method, target) = match (parts.next(), parts.next()) {
+            (Some(method), Some(target)) => (method, target),
+            _ => return write_response(&mut stream, "400 Bad Request", "text/plain", b""),
+        };
+
+        if method != "GET" {
+            return write_response(&mut stream, "405 Method Not Allowed", "text/plain", b"");
+        }
+
+        let path = percent_decode(target.split(['?', '#']).next().unwrap_or("/"));
+
+        match path.as_str() {
+            "/" | "/index.html" => {
+                let page = self.page.read().unwrap().clone();
+                let page = match page.rfind("</body>") {
+                    Some(index) => {
+                        format!("{}{}\n{}", &page[..index], RELOAD_SCRIPT, &page[index..])
+                    }
+                    None => format!("{}\n{}", page, RELOAD_SCRIPT),
+                };
+                write_response(
+                    &mut stream,
+                    "200 OK",
+                    "text/html; charset=utf-8",
+                    page.as_bytes(),
+                )
+            }
+            "/events" => {
+                stream.write_all(
+                    b"HTTP/1.1 200 OK\r\n\
+                    Content-Type: text/event-stream\r\n\
+                    Cache-Control: no-cache\r\n\
+                    Access-Control-Allow-Origin: *\r\n\r\n",
+                )?;
+                self.clients.lock().unwrap().push(stream);
+                Ok(())
+            }
+            path => match self.asset(path) {
+                Some(file) => match fs::read(&file) {
+                    Ok(content) => {
+                        write_response(&mut stream, "200 OK", content_type(&file), &content)
+                    }
+                    Err(_) => write_response(&mut stream, "404 Not Found", "text/plain", b""),
+                },
+                None => write_response(&mut stream, "403 Forbidden", "text/plain", b""),
+            },
+        }
+    }
+
+    /// The file that a request path refers to, paths outside
+    /// of the directory of the document are not allowed
+    fn asset(&self, path: &str) -> Option<PathBuf> {
+        // the WebPreview template rewrites absolute file paths to
+        // point at the server, e.g. `/home/me/notes/image.png`
+        let absolute = Path::new(path).canonicalize().ok();
+        if let Some(file) = absolute.filter(|file| file.starts_with(&self.root)) {
+            return Some(file);
+        }
+
+        let relative = Path::new(path.trim_start_matches('/'));
+        let is_inside = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+
+        if is_inside {
+            Some(self.root.join(relative))
+        } else {
+            None
+        }
+    }
+}
+
+fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n\
+        Content-Type: {}\r\n\
+        Content-Length: {}\r\n\
+        Cache-Control: no-cache\r\n\
+        Access-Control-Allow-Origin: *\r\n\
+        Connection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+fn content_type(file: &Path) -> &'static str {
+    let extension = file
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+
+    match extension.as_deref() {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt") | Some("ln") => "text/plain; charset=utf-8",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("pdf") => "application/pdf",
+        Some("mp4") => "video/mp4",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Decode %XX escapes in a request path, e.g. spaces in file names
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
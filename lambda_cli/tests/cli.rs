@@ -0,0 +1,96 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::{self, Command, Output, Stdio};
+
+/// Run the cli with a document on stdin, without a cache
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lambda_cli"))
+        .args(args)
+        .arg("--no-cache")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn build() {
+    let output = run(
+        &["build", "-", "--output", "-", "--format", "markdown"],
+        "# Hi",
+    );
+    assert_eq!(
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stdout).trim()
+        ),
+        (true, "# Hi"),
+        "Testing that stdin is translated to stdout"
+    );
+
+    let output = run(&["build", "-", "--output", "-"], "|nope|");
+    assert_eq!(
+        output.status.code(),
+        Some(1),
+        "Testing that errors fail the build"
+    );
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("unknown-extension"),
+        "Testing that errors are reported"
+    );
+}
+
+#[test]
+fn check() {
+    let warning = "|ref, x|";
+    assert!(
+        run(&["check", "-"], warning).status.success(),
+        "Testing that warnings do not fail by default"
+    );
+    assert_eq!(
+        run(&["check", "-", "--deny-warnings"], warning)
+            .status
+            .code(),
+        Some(1),
+        "Testing that denied warnings fail"
+    );
+    assert_eq!(
+        run(&["check", "-"], "|nope|").status.code(),
+        Some(1),
+        "Testing that errors fail"
+    );
+}
+
+#[test]
+fn legacy_build() {
+    let directory = env::temp_dir().join(format!("lambda_cli_legacy_{}", process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let input = directory.join("doc.ln");
+    let output = directory.join("doc.md");
+    fs::write(&input, "# Hi").unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_lambda_cli"))
+        .arg(&input)
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(
+        status.success(),
+        "Testing the legacy form of the build command"
+    );
+    assert_eq!(
+        fs::read_to_string(&output).unwrap().trim(),
+        "# Hi",
+        "Testing that the legacy form writes the output file"
+    );
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,528 @@
+use lambda_note_lib::{
+    parse_doc, Block, Context, Diagnostic, DocumentState, Extension, Html, Inline, List,
+    OriginName, Severity, Span,
+};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+/// Metadata fields that are read by the translators rather than an extension
+const TRANSLATOR_METADATA: [&str; 6] = [
+    "title",
+    "author",
+    "date",
+    "language",
+    "link_color",
+    "section_numbers",
+];
+
+/// Everything that the language server knows about an open document
+pub struct Analysis {
+    lines: Vec<String>,
+    diagnostics: Vec<Diagnostic>,
+    /// The extensions that can be used in the document and their documentation
+    extensions: BTreeMap<String, ExtensionInfo>,
+    metadata_keys: BTreeSet<String>,
+    headings: Vec<(String, u8, Span)>,
+}
+
+struct ExtensionInfo {
+    name: String,
+    description: String,
+    version: String,
+    block: bool,
+    inline: bool,
+}
+
+impl Analysis {
+    /// Translate the document to find all diagnostics. Foreign extensions
+    /// are never run by the language server, since that would mean running
+    /// arbitrary commands while typing, so the document is translated in
+    /// safe mode and the extensions it defines are replaced by placeholders.
+    pub fn new(source: &str, doc_name: &str) -> Self {
+        let blocks = parse_doc(source, doc_name);
+
+        let mut defined = BTreeMap::new();
+        for block in &blocks {
+            find_definitions(block, &mut defined);
+        }
+
+        let mut doc = DocumentState::new(Html);
+        doc.set_safe_mode(true);
+        for (name, command) in &defined {
+            let placeholder = Placeholder {
+                name: name.clone(),
+                command: command.clone(),
+            };
+            doc.extensions.insert(name.clone(), Rc::new(placeholder));
+        }
+
+        // a bug in an extension should not bring down the whole server
+        let translated = panic::catch_unwind(AssertUnwindSafe(|| {
+            doc.translate(source, doc_name);
+        }));
+        if translated.is_err() {
+            doc.add_diagnostic(Diagnostic::error(
+                "λnote crashed while translating the document",
+            ));
+        }
+
+        let diagnostics = doc
+            .diagnostics
+            .iter()
+            .filter(|d| d.code.as_deref() != Some("unsafe-extension"))
+            .cloned()
+            .collect();
+
+        let extensions = doc
+            .extensions
+            .iter()
+            .map(|(key, extension)| {
+                let info = ExtensionInfo {
+                    name: extension.name(),
+                    description: extension.description(),
+                    version: extension.version(),
+                    block: extension.supports_block(),
+                    inline: extension.supports_inline(),
+                };
+                (key.clone(), info)
+            })
+            .collect();
+
+        let mut metadata_keys: BTreeSet<String> = doc.metadata.keys().cloned().collect();
+        metadata_keys.extend(TRANSLATOR_METADATA.iter().map(|key| key.to_string()));
+        // patterns such as `calc_*` are not complete keys
+        for extension in doc.extensions.values() {
+            let interests = extension.interests().into_iter();
+            metadata_keys.extend(interests.filter(|key| !key.contains('*')));
+        }
+
+        let headings = blocks
+            .iter()
+            .filter_map(|block| match block {
+                Block::Heading(text, level, origin) => {
+                    Some((plain_text(text), *level, origin.span))
+                }
+                _ => None,
+            })
+            .collect();
+
+        Analysis {
+            lines: source.lines().map(String::from).collect(),
+            diagnostics,
+            extensions,
+            metadata_keys,
+            headings,
+        }
+    }
+
+    /// The diagnostics found in the document, as LSP diagnostics
+    pub fn diagnostics(&self, doc_name: &str) -> Vec<Value> {
+        self.diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let mut message = String::new();
+                if let Some(extension) = &diagnostic.extension {
+                    message.push_str(&format!("{}: ", extension));
+                }
+                message.push_str(&diagnostic.message);
+                for note in diagnostic.backtrace() {
+                    message.push_str(&format!("\nnote: {}", note));
+                }
+                if let Some(help) = &diagnostic.help {
+                    message.push_str(&format!("\nhelp: {}", help));
+                }
+
+                let mut result = json!({
+                    "range": self.diagnostic_range(diagnostic, doc_name),
+                    "severity": match diagnostic.severity {
+                        Severity::Error => 1,
+                        Severity::Warning => 2,
+                    },
+                    "source": "λnote",
+                    "message": message,
+                });
+                if let Some(code) = &diagnostic.code {
+                    result["code"] = json!(code);
+                }
+                result
+            })
+            .collect()
+    }
+
+    /// Diagnostics from expansions are shown at the call in the source text,
+    /// the whole line is used when the exact location is not known
+    fn diagnostic_range(&self, diagnostic: &Diagnostic, doc_name: &str) -> Value {
+        let origin = match &diagnostic.origin {
+            Some(origin) => origin.root(),
+            None => return self.line_range(1),
+        };
+
+        let is_source = matches!(&origin.name, OriginName::Filename(name) if name == doc_name);
+        if is_source && !origin.span.is_unknown() {
+            self.span_range(&origin.span)
+        } else {
+            self.line_range(origin.line_number.max(1))
+        }
+    }
+
+    /// Completions of extension names after a `|` or a block divider,
+    /// and of metadata keys after `::`
+    pub fn completions(&self, line: usize, character: usize) -> Vec<Value> {
+        let before = self.text_before(line, character);
+        let trimmed = before.trim_start();
+
+        if let Some(key) = trimmed.strip_prefix("::") {
+            if !key.contains('=') {
+                return self
+                    .metadata_keys
+                    .iter()
+                    .map(|key| json!({ "label": key, "kind": 10 }))
+                    .collect();
+            }
+            return vec![];
+        }
+
+        let is_block = trimmed.starts_with("---")
+            && trimmed
+                .trim_start_matches('-')
+                .chars()
+                .all(|c| c.is_whitespace() || is_name_char(c));
+
+        // inside of an inline extension before the first comma
+        let is_inline = match unescaped_bars(&before).last() {
+            Some(bar) => {
+                unescaped_bars(&before).len() % 2 == 1
+                    && before[bar + 1..].chars().all(is_name_char)
+            }
+            None => false,
+        };
+
+        if !is_block && !is_inline {
+            return vec![];
+        }
+
+        self.extensions
+            .iter()
+            .filter(|(_, info)| if is_block { info.block } else { info.inline })
+            .map(|(key, info)| {
+                json!({
+                    "label": key,
+                    "kind": 3,
+                    "detail": format!("{} (version {})", info.name, info.version),
+                    "documentation": { "kind": "markdown", "value": info.description },
+                })
+            })
+            .collect()
+    }
+
+    /// The description of the extension that is called at the given position
+    pub fn hover(&self, line: usize, character: usize) -> Option<Value> {
+        let text = self.lines.get(line)?;
+        let chars: Vec<char> = text.chars().collect();
+        let cursor = utf16_to_char(text, character);
+
+        let start = (0..cursor)
+            .rev()
+            .take_while(|i| is_name_char(chars[*i]))
+            .last()
+            .unwrap_or(cursor);
+        let end = (cursor..chars.len())
+            .take_while(|i| is_name_char(chars[*i]))
+            .last()
+            .map_or(cursor, |i| i + 1);
+
+        if start == end {
+            return None;
+        }
+
+        // only names in the position of an extension call are described
+        let before: String = chars[..start].iter().collect();
+        let before = before.trim_end();
+        let is_call = before.ends_with('|') && !before.ends_with("\\|")
+            || before.trim_start().chars().all(|c| c == '-') && before.trim().len() >= 3;
+        if !is_call {
+            return None;
+        }
+
+        let name: String = chars[start..end].iter().collect();
+        let info = self.extensions.get(&name)?;
+
+        Some(json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!(
+                    "**{}** (version {})\n\n{}",
+                    info.name, info.version, info.description
+                ),
+            },
+            "range": {
+                "start": { "line": line, "character": char_to_utf16(text, start) },
+                "end": { "line": line, "character": char_to_utf16(text, end) },
+            },
+        }))
+    }
+
+    /// The headings of the document, nested by their level
+    pub fn symbols(&self) -> Vec<Value> {
+        let mut stack: Vec<(u8, Value)> = vec![];
+        let mut roots = vec![];
+
+        for (text, level, span) in &self.headings {
+            let symbol = json!({
+                "name": if text.is_empty() { "(empty heading)" } else { text },
+                "kind": 15,
+                "range": self.span_range(span),
+                "selectionRange": self.span_range(span),
+                "children": [],
+            });
+
+            // close the sections that this heading ends
+            while stack.last().is_some_and(|(l, _)| l >= level) {
+                let (_, finished) = stack.pop().unwrap();
+                add_symbol(&mut stack, &mut roots, finished);
+            }
+            stack.push((*level, symbol));
+        }
+
+        while let Some((_, finished)) = stack.pop() {
+            add_symbol(&mut stack, &mut roots, finished);
+        }
+
+        roots
+    }
+
+    fn text_before(&self, line: usize, character: usize) -> String {
+        match self.lines.get(line) {
+            Some(text) => text.chars().take(utf16_to_char(text, character)).collect(),
+            None => String::new(),
+        }
+    }
+
+    /// Convert a span to a LSP range, which counts lines from
+    /// zero and characters in UTF-16 code units
+    fn span_range(&self, span: &Span) -> Value {
+        let position = |line: usize, column: usize| {
+            let text = self.lines.get(line - 1).map_or("", String::as_str);
+            json!({ "line": line - 1, "character": char_to_utf16(text, column - 1) })
+        };
+
+        json!({
+            "start": position(span.start.line, span.start.column),
+            "end": position(span.end.line, span.end.column),
+        })
+    }
+
+    fn line_range(&self, line: usize) -> Value {
+        let text = self.lines.get(line - 1).map_or("", String::as_str);
+        json!({
+            "start": { "line": line - 1, "character": 0 },
+            "end": { "line": line - 1, "character": char_to_utf16(text, usize::MAX) },
+        })
+    }
+}
+
+fn add_symbol(stack: &mut [(u8, Value)], roots: &mut Vec<Value>, symbol: Value) {
+    match stack.last_mut() {
+        Some((_, parent)) => parent["children"].as_array_mut().unwrap().push(symbol),
+        None => roots.push(symbol),
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// The byte offsets of all bars that are not escaped
+fn unescaped_bars(text: &str) -> Vec<usize> {
+    let mut bars = vec![];
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '|' if !escaped => bars.push(i),
+            _ => escaped = false,
+        }
+    }
+    bars
+}
+
+fn utf16_to_char(text: &str, utf16: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in text.chars().enumerate() {
+        if units >= utf16 {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    text.chars().count()
+}
+
+fn char_to_utf16(text: &str, index: usize) -> usize {
+    text.chars().take(index).map(char::len_utf16).sum()
+}
+
+fn plain_text(text: &[Inline]) -> String {
+    let text: String = text
+        .iter()
+        .map(|inline| match inline {
+            Inline::Text(text, _) => text.clone(),
+            Inline::Escaped(escaped, _) => escaped.to_string(),
+            _ => String::new(),
+        })
+        .collect();
+    text.trim().to_string()
+}
+
+/// Collect the `|define, name, command|` calls of a block
+fn find_definitions(block: &Block, defined: &mut BTreeMap<String, String>) {
+    let mut add = |text: &[Inline]| {
+        for inline in text {
+            if let Inline::Extension(name, arguments, _) = inline {
+                if let (true, Some(extension), Some(command)) =
+                    (name == "define", arguments.first(), arguments.get(1))
+                {
+                    defined.insert(extension.trim().to_string(), command.trim().to_string());
+                }
+            }
+        }
+    };
+
+    match block {
+        Block::Paragraph(text, _) | Block::Heading(text, _, _) => add(text),
+        Block::List(list, _) => list_items(list).into_iter().for_each(add),
+        Block::Table(table, _) => table
+            .header
+            .iter()
+            .chain(table.rows.iter())
+            .flatten()
+            .for_each(|cell| add(cell)),
+        _ => (),
+    }
+}
+
+fn list_items(list: &List) -> Vec<&[Inline]> {
+    let mut items = vec![];
+    for item in &list.items {
+        items.push(item.text.as_slice());
+        for child in &item.children {
+            items.extend(list_items(child));
+        }
+    }
+    items
+}
+
+/// Stands in for an extension that the document defines
+/// with a command, which the language server does not run
+struct Placeholder {
+    name: String,
+    command: String,
+}
+
+impl Extension for Placeholder {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Defined in this document by running `{}`.\n\n\
+            Foreign extensions are not run by the language server.",
+            self.command
+        )
+    }
+
+    fn version(&self) -> String {
+        "unknown".to_string()
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+
+    fn call(&self, _context: Context) -> Option<String> {
+        Some(String::new())
+    }
+
+    fn supports_block(&self) -> bool {
+        true
+    }
+
+    fn supports_inline(&self) -> bool {
+        true
+    }
+
+    fn interests(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(completions: Vec<Value>) -> Vec<String> {
+        completions
+            .iter()
+            .map(|completion| completion["label"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn utf16_positions() {
+        let text = "a😀b";
+        assert_eq!(utf16_to_char(text, 1), 1, "Testing before a surrogate pair");
+        assert_eq!(utf16_to_char(text, 3), 2, "Testing after a surrogate pair");
+        assert_eq!(utf16_to_char(text, 9), 3, "Testing past the end");
+        assert_eq!(char_to_utf16(text, 2), 3, "Testing after a surrogate pair");
+        assert_eq!(char_to_utf16(text, 3), 4, "Testing the end");
+    }
+
+    #[test]
+    fn symbols() {
+        let analysis = Analysis::new("# A\n## B\n### C\n## D\n# E", "test");
+        let names = |symbols: &Value| -> Vec<String> {
+            symbols
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|symbol| symbol["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let roots = Value::from(analysis.symbols());
+        assert_eq!(names(&roots), ["A", "E"], "Testing top level symbols");
+        assert_eq!(
+            names(&roots[0]["children"]),
+            ["B", "D"],
+            "Testing nested symbols"
+        );
+        assert_eq!(
+            names(&roots[0]["children"][0]["children"]),
+            ["C"],
+            "Testing deeply nested symbols"
+        );
+    }
+
+    #[test]
+    fn completions() {
+        let analysis = Analysis::new("Text |im\nText |img, cat.png| and\n:: ", "test");
+        assert!(
+            labels(analysis.completions(0, 8)).contains(&"img".to_string()),
+            "Testing completions inside of an inline call"
+        );
+        assert!(
+            labels(analysis.completions(1, 12)).is_empty(),
+            "Testing completions after the name of an inline call"
+        );
+        assert!(
+            labels(analysis.completions(1, 23)).is_empty(),
+            "Testing completions after an inline call"
+        );
+        assert!(
+            labels(analysis.completions(2, 3)).contains(&"title".to_string()),
+            "Testing completions of metadata keys"
+        );
+    }
+}
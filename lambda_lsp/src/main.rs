@@ -0,0 +1,156 @@
+mod analysis;
+mod rpc;
+
+use analysis::Analysis;
+use rpc::{read_message, write_message};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufReader, Write};
+use std::process;
+
+/// A language server for λnote documents that communicates over stdio.
+/// It publishes diagnostics, completes extension names and metadata keys,
+/// describes extensions on hover and lists the headings of a document.
+fn main() {
+    let stdin = io::stdin();
+    let mut input = BufReader::new(stdin.lock());
+    let stdout = io::stdout();
+    let mut server = Server {
+        output: stdout.lock(),
+        documents: HashMap::new(),
+        shutdown: false,
+    };
+
+    loop {
+        match read_message(&mut input) {
+            Ok(Some(message)) => server.handle(message),
+            Ok(None) => break,
+            Err(error) => eprintln!("Failed to read a message: {}", error),
+        }
+    }
+
+    process::exit(if server.shutdown { 0 } else { 1 });
+}
+
+struct Server<W: Write> {
+    output: W,
+    /// The open documents and what is known about them, by their uri
+    documents: HashMap<String, Analysis>,
+    shutdown: bool,
+}
+
+impl<W: Write> Server<W> {
+    fn handle(&mut self, message: Value) {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+
+        // requests have an id and expect a response, notifications do not
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => {
+                self.notification(method, params);
+                return;
+            }
+        };
+
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "completionProvider": { "triggerCharacters": ["|", ":", " "] },
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": {
+                    "name": "lambda_lsp",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/completion" => Ok(self
+                .at_position(params, |analysis, line, character| {
+                    json!(analysis.completions(line, character))
+                })),
+            "textDocument/hover" => Ok(self.at_position(params, |analysis, line, character| {
+                json!(analysis.hover(line, character))
+            })),
+            "textDocument/documentSymbol" => Ok(self
+                .document(params)
+                .map_or(Value::Null, |analysis| json!(analysis.symbols()))),
+            _ => Err(json!({
+                "code": -32601,
+                "message": format!("Unsupported method {}", method),
+            })),
+        };
+
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+        };
+        self.send(&response);
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.update(uri, text.to_string());
+            }
+            // the server asks for full document updates,
+            // so the last change contains the whole text
+            "textDocument/didChange" => {
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes.and_then(|c| c.last()?["text"].as_str()) {
+                    self.update(uri, text.to_string());
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.publish_diagnostics(&uri, vec![]);
+            }
+            "exit" => process::exit(if self.shutdown { 0 } else { 1 }),
+            _ => (),
+        }
+    }
+
+    fn update(&mut self, uri: String, text: String) {
+        let analysis = Analysis::new(&text, &uri);
+        let diagnostics = analysis.diagnostics(&uri);
+        self.documents.insert(uri.clone(), analysis);
+        self.publish_diagnostics(&uri, diagnostics);
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str, diagnostics: Vec<Value>) {
+        self.send(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        }));
+    }
+
+    fn document(&self, params: &Value) -> Option<&Analysis> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        self.documents.get(uri)
+    }
+
+    fn at_position(&self, params: &Value, f: impl Fn(&Analysis, usize, usize) -> Value) -> Value {
+        let line = params["position"]["line"].as_u64().unwrap_or_default() as usize;
+        let character = params["position"]["character"].as_u64().unwrap_or_default() as usize;
+        self.document(params)
+            .map_or(Value::Null, |analysis| f(analysis, line, character))
+    }
+
+    fn send(&mut self, message: &Value) {
+        if let Err(error) = write_message(&mut self.output, message) {
+            eprintln!("Failed to send a message: {}", error);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use serde_json::Value;
+use std::io::{self, BufRead, Write};
+
+/// Read a single JSON-RPC message, framed by a Content-Length header.
+/// Returns None when the input has been closed.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+
+    let mut content = vec![0; length];
+    input.read_exact(&mut content)?;
+
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// Write a single JSON-RPC message with a Content-Length header
+pub fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let content = message.to_string();
+    write!(
+        output,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::Cursor;
+
+    #[test]
+    fn framing() {
+        let mut output = vec![];
+        write_message(&mut output, &json!({ "text": "λ😀" })).unwrap();
+        write_message(&mut output, &json!({ "id": 2 })).unwrap();
+
+        let mut input = Cursor::new(output);
+        assert_eq!(
+            read_message(&mut input).unwrap(),
+            Some(json!({ "text": "λ😀" })),
+            "Testing that the length is counted in bytes"
+        );
+        assert_eq!(
+            read_message(&mut input).unwrap(),
+            Some(json!({ "id": 2 })),
+            "Testing consecutive messages"
+        );
+        assert_eq!(
+            read_message(&mut input).unwrap(),
+            None,
+            "Testing closed input"
+        );
+
+        let mut input = Cursor::new("Content-Type: x\r\ncontent-length: 2\r\n\r\n{}");
+        assert_eq!(
+            read_message(&mut input).unwrap(),
+            Some(json!({})),
+            "Testing other headers and lowercase names"
+        );
+    }
+
+    #[test]
+    fn invalid_headers() {
+        for message in [
+            "Content-Type: x\r\n\r\n{}",
+            "Content-Length: two\r\n\r\n{}",
+            "Content-Length: 10\r\n\r\n{}",
+            "Content-Length: 2\r\n\r\nno",
+        ] {
+            let error = read_message(&mut Cursor::new(message));
+            assert!(error.is_err(), "Testing the invalid message {:?}", message);
+        }
+    }
+}
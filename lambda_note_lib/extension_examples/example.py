@@ -40,9 +40,19 @@ if request['type'] == 'info':
         'inlineSupport': True,
         'errors': [],           # Optional
         'warnings': [],         # Optional
-        'interests': []}        # Optional, a list of strings for each
+        'interests': [],        # Optional, a list of strings for each
                                 # metadata field that the extension wants
                                 # to see
+        'pure': True,           # Optional, the output only depends on the
+                                # request so calls can run at the same time
+        'cache': True,          # Optional, false if responses can not be
+                                # reused for the same request
+        'cacheVersion': '1',    # Optional, change it to invalidate the
+                                # cached responses
+        'protocol': 2}          # Optional, the version of the protocol, with
+                                # version 2 action requests also contain
+                                # "variant", "origin", "documentPath",
+                                # "documentDirectory" and "safeMode"
 
 elif request['type'] == 'action':
     # every field except "content" is optional
@@ -0,0 +1,40 @@
+"""
+An example of an extension that is started once per document,
+instead of once for every request, by defining it in persistent mode:
+
+| define, upper, python persistent.py, persistent |
+
+Every line on stdin is a JSON-RPC request with a method ("info" or
+"action"), an id and the same params as in the other examples. Each
+request is answered with a line on stdout that has the same id.
+Since the extension is pure, several requests may be sent before
+the first one is answered.
+A "shutdown" notification, without an id, is sent when the document
+is done, and stdin is closed after it.
+"""
+
+from sys import stdin, stdout
+from json import dumps, loads
+
+for line in stdin:
+    message = loads(line)
+
+    if message['method'] == 'shutdown':
+        break
+
+    request = message['params']
+    if message['method'] == 'info':
+        result = {
+            "name": "upper",
+            "version": "0.1",
+            "description": "Convert text to uppercase",
+            "supportedFormats": ["html", "latex"],
+            "blockSupport": True,
+            "inlineSupport": True,
+            "pure": True}
+    else:
+        result = {"content": [request['arguments'][0].upper()]}
+
+    # errors are sent as {"jsonrpc": "2.0", "id": ..., "error": {"code": 1, "message": "..."}}
+    stdout.write(dumps({"jsonrpc": "2.0", "id": message['id'], "result": result}) + "\n")
+    stdout.flush()
@@ -28,6 +28,7 @@ if request['type'] == 'info':
         "description": "Convert text to uppercase",
         "supportedFormats": ["html", "latex"],
         "blockSupport": True,
-        "inlineSupport": True }))
+        "inlineSupport": True,
+        "pure": True }))
 else:
     print(dumps({"content": [request['arguments'][0].upper()]}))
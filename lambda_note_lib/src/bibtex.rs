@@ -0,0 +1,640 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::translator::{DocumentState, OutputFormat};
+use crate::{Diagnostic, Origin};
+
+/// An entry of a BibTeX file, like `@article{key, author = {...}, ...}`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Entry {
+    pub kind: String,
+    pub key: String,
+    /// The field names are lowercase, the values are kept as they are written
+    pub fields: HashMap<String, String>,
+}
+
+/// A person in an author or editor field
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Name {
+    pub first: String,
+    pub last: String,
+}
+
+/// How citations and the reference list look in HTML and Markdown
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Style {
+    /// `[1]`, numbered in the order they are first cited
+    Numeric,
+    /// `(Knuth 1984)`, sorted by author and year
+    AuthorYear,
+}
+
+/// The bibliography of a document, read from the file in the `bibliography` metadata field
+pub(crate) struct Bibliography {
+    /// None if the file could not be read, which has already been reported
+    pub entries: Option<HashMap<String, Entry>>,
+    pub style: Style,
+}
+
+/// The bibliography of the document, read the first time it is needed.
+/// The file is relative to `directory` and any problems are reported at `origin`.
+pub(crate) fn load(
+    document: &mut DocumentState,
+    directory: &Path,
+    origin: &Origin,
+) -> Rc<Bibliography> {
+    if let Some(bibliography) = &document.bibliography {
+        return bibliography.clone();
+    }
+
+    let style = match document.metadata.get("citation_style").map(|s| s.trim()) {
+        None | Some("numeric") => Style::Numeric,
+        Some("author-year") | Some("authoryear") => Style::AuthorYear,
+        Some(style) => {
+            let message = format!("Unknown citation style {}, using numeric", style);
+            document.add_diagnostic(
+                Diagnostic::warning(&message)
+                    .with_origin(origin)
+                    .with_code("unknown-citation-style")
+                    .with_help("the styles are numeric and author-year"),
+            );
+            Style::Numeric
+        }
+    };
+
+    let entries = match document
+        .metadata
+        .get("bibliography")
+        .map(|file| file.trim())
+    {
+        Some(file) if !file.is_empty() => {
+            let path = directory.join(file);
+            // cite and bibliography are safe extensions, so in safe
+            // mode the document can not make them read any file
+            if document.safe_mode() && is_outside(directory, &path) {
+                let message = format!(
+                    "The bibliography {} is outside the directory of the document \
+                    and is not read in safe mode",
+                    path.display()
+                );
+                document.add_diagnostic(
+                    Diagnostic::error(&message)
+                        .with_origin(origin)
+                        .with_code("unsafe-bibliography")
+                        .with_help("move the bibliography next to the document"),
+                );
+                let bibliography = Rc::new(Bibliography {
+                    entries: None,
+                    style,
+                });
+                document.bibliography = Some(bibliography.clone());
+                return bibliography;
+            }
+
+            let entries = fs::read_to_string(&path)
+                .map_err(|error| error.to_string())
+                .and_then(|source| parse_bibtex(&source));
+            match entries {
+                Ok(entries) => Some(
+                    entries
+                        .into_iter()
+                        .map(|entry| (entry.key.clone(), entry))
+                        .collect(),
+                ),
+                Err(error) => {
+                    let message = format!("Failed to read {}: {}", path.display(), error);
+                    document.add_diagnostic(
+                        Diagnostic::error(&message)
+                            .with_origin(origin)
+                            .with_code("bibliography-failed"),
+                    );
+                    None
+                }
+            }
+        }
+        _ => {
+            document.add_diagnostic(
+                Diagnostic::error("There is no bibliography to cite from")
+                    .with_origin(origin)
+                    .with_code("no-bibliography")
+                    .with_help("add the metadata field `:: bibliography = references.bib`"),
+            );
+            None
+        }
+    };
+
+    let bibliography = Rc::new(Bibliography { entries, style });
+    document.bibliography = Some(bibliography.clone());
+    bibliography
+}
+
+/// The directory of the document itself, also when cited from an included file
+pub(crate) fn document_directory(document: &DocumentState, origin: &Origin) -> PathBuf {
+    let file = document.document_file(origin);
+    file.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+/// If a file is outside of a directory, also through `..` and symbolic links.
+/// Files that do not exist are not, they are reported when they can not be read.
+fn is_outside(directory: &Path, path: &Path) -> bool {
+    let directory = if directory.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        directory
+    };
+    match (fs::canonicalize(directory), fs::canonicalize(path)) {
+        (Ok(directory), Ok(path)) => !path.starts_with(directory),
+        (Err(_), Ok(_)) => true,
+        (_, Err(_)) => false,
+    }
+}
+
+/// Let biblatex handle the citations in LaTeX
+pub(crate) fn import_biblatex(document: &mut DocumentState, style: Style) {
+    let style = match style {
+        Style::Numeric => "numeric",
+        Style::AuthorYear => "authoryear",
+    };
+    let file = document
+        .metadata
+        .get("bibliography")
+        .map_or("", |file| file.trim())
+        .to_string();
+    // both lines in one import, since \addbibresource has to come after the package
+    document.import(&format!(
+        "\\usepackage[style={}]{{biblatex}}\n\\addbibresource{{{}}}",
+        style, file
+    ));
+}
+
+/// The cited entries, in the order of the reference list
+pub(crate) fn cited_entries<'a>(
+    document: &DocumentState,
+    entries: &'a HashMap<String, Entry>,
+    style: Style,
+) -> Vec<&'a Entry> {
+    let mut cited: Vec<&Entry> = document
+        .citations
+        .iter()
+        .filter_map(|key| entries.get(key))
+        .collect();
+    if style == Style::AuthorYear {
+        cited.sort_by_cached_key(|entry| {
+            let names: Vec<String> = entry.names().into_iter().map(|name| name.last).collect();
+            (names, entry.year(), entry.field("title"))
+        });
+    }
+    cited
+}
+
+/// Parse the entries of a BibTeX file. `@string` abbreviations are
+/// expanded and `@comment` and `@preamble` are skipped.
+pub(crate) fn parse_bibtex(source: &str) -> Result<Vec<Entry>, String> {
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        position: 0,
+        strings: HashMap::new(),
+    };
+    let mut entries = vec![];
+
+    // everything outside of entries is a comment
+    while parser.skip_until('@') {
+        parser.position += 1;
+        let kind = parser.identifier().to_lowercase();
+        parser.skip_whitespace();
+        let close = match parser.next() {
+            Some('{') => '}',
+            Some('(') => ')',
+            _ => return Err(parser.error("expected { after the entry type")),
+        };
+
+        match kind.as_str() {
+            "comment" | "preamble" => {
+                parser.position -= 1;
+                parser.skip_group()?;
+            }
+            "string" => {
+                let (name, value) = parser.field()?;
+                parser.strings.insert(name, value);
+                parser.expect(close)?;
+            }
+            _ => {
+                parser.skip_whitespace();
+                let key = parser.until(&[',', close]).trim().to_string();
+                let mut fields = HashMap::new();
+                while parser.peek() == Some(',') {
+                    parser.position += 1;
+                    parser.skip_whitespace();
+                    // a trailing comma is allowed
+                    if parser.peek() == Some(close) {
+                        break;
+                    }
+                    let (name, value) = parser.field()?;
+                    fields.insert(name, value);
+                }
+                parser.expect(close)?;
+                entries.push(Entry { kind, key, fields });
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    strings: HashMap<String, String>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.position += 1;
+        c
+    }
+
+    fn error(&self, message: &str) -> String {
+        let line = self.chars[..self.position.min(self.chars.len())]
+            .iter()
+            .filter(|&&c| c == '\n')
+            .count();
+        format!("{} on line {}", message, line + 1)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("expected {}", expected))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    /// Move to the next `c`, returns false if there is none
+    fn skip_until(&mut self, c: char) -> bool {
+        while let Some(next) = self.peek() {
+            if next == c {
+                return true;
+            }
+            self.position += 1;
+        }
+        false
+    }
+
+    fn until(&mut self, ends: &[char]) -> String {
+        let mut text = String::new();
+        while let Some(c) = self.peek().filter(|c| !ends.contains(c)) {
+            text.push(c);
+            self.position += 1;
+        }
+        text
+    }
+
+    fn identifier(&mut self) -> String {
+        let mut text = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_alphanumeric() || "_-:.+/".contains(*c))
+        {
+            text.push(c);
+            self.position += 1;
+        }
+        text
+    }
+
+    /// The text of a group in balanced braces or parentheses, without the outer ones
+    fn skip_group(&mut self) -> Result<String, String> {
+        let (open, close) = match self.next() {
+            Some('(') => ('(', ')'),
+            _ => ('{', '}'),
+        };
+        let start = self.position;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Some(c) if c == open => depth += 1,
+                Some(c) if c == close => depth -= 1,
+                Some(_) => (),
+                None => return Err(self.error(&format!("missing {}", close))),
+            }
+        }
+        Ok(self.chars[start..self.position - 1].iter().collect())
+    }
+
+    /// A field like `title = {A title} # " and more"`
+    fn field(&mut self) -> Result<(String, String), String> {
+        self.skip_whitespace();
+        let name = self.identifier().to_lowercase();
+        if name.is_empty() {
+            return Err(self.error("expected the name of a field"));
+        }
+        self.expect('=')?;
+
+        let mut value = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('{') => value.push_str(&self.skip_group()?),
+                Some('"') => {
+                    self.position += 1;
+                    let start = self.position;
+                    let mut depth = 0;
+                    loop {
+                        match self.next() {
+                            Some('{') => depth += 1,
+                            Some('}') => depth -= 1,
+                            Some('"') if depth == 0 => break,
+                            Some(_) => (),
+                            None => return Err(self.error("missing \"")),
+                        }
+                    }
+                    value.extend(&self.chars[start..self.position - 1]);
+                }
+                _ => {
+                    let word = self.identifier();
+                    if word.is_empty() {
+                        return Err(self.error(&format!("expected a value for {}", name)));
+                    }
+                    match self.strings.get(&word.to_lowercase()) {
+                        Some(string) => value.push_str(string),
+                        None => value.push_str(&word),
+                    }
+                }
+            }
+
+            self.skip_whitespace();
+            if self.peek() != Some('#') {
+                break;
+            }
+            self.position += 1;
+        }
+
+        Ok((name, value))
+    }
+}
+
+impl Entry {
+    /// A field as plain text
+    pub fn field(&self, name: &str) -> Option<String> {
+        self.fields
+            .get(name)
+            .map(|value| plain_text(value))
+            .filter(|value| !value.is_empty())
+    }
+
+    /// The authors, or the editors if there are no authors
+    pub fn names(&self) -> Vec<Name> {
+        let names = self
+            .fields
+            .get("author")
+            .or_else(|| self.fields.get("editor"));
+        match names {
+            Some(names) => split_names(names)
+                .iter()
+                .map(|name| parse_name(name))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    pub fn year(&self) -> String {
+        self.field("year")
+            .or_else(|| {
+                self.field("date")
+                    .map(|date| date.chars().take(4).collect())
+            })
+            .unwrap_or_else(|| "n.d.".to_string())
+    }
+
+    /// The short form used in author-year citations, like `Knuth et al. 1984`
+    pub fn author_year(&self) -> String {
+        let names = self.names();
+        let authors = match names.as_slice() {
+            [] => self.field("title").unwrap_or_else(|| self.key.clone()),
+            [name] => name.last.clone(),
+            [first, second] => format!("{} and {}", first.last, second.last),
+            [first, ..] => format!("{} et al.", first.last),
+        };
+        format!("{} {}", authors, self.year())
+    }
+
+    /// The entry as it is written in a reference list, like
+    /// `Knuth, D. E. (1984). Literate programming. *The Computer Journal*, 27(2), 97–111.`
+    pub fn format(&self, document: &DocumentState) -> String {
+        let escape = |text: &str| document.escape_str(text);
+        let emphasize = |text: &str| match document.get_output_format() {
+            OutputFormat::Html => format!("<em>{}</em>", escape(text)),
+            _ => format!("*{}*", escape(text)),
+        };
+
+        let names: Vec<String> = self
+            .names()
+            .iter()
+            .map(|name| match initials(&name.first) {
+                initials if initials.is_empty() => name.last.clone(),
+                initials => format!("{}, {}", name.last, initials),
+            })
+            .collect();
+        let mut output = match names.split_last() {
+            None => String::new(),
+            Some((last, [])) => format!("{} ", escape(last)),
+            Some((last, names)) => format!("{} and {} ", escape(&names.join(", ")), escape(last)),
+        };
+        output.push_str(&format!("({}). ", escape(&self.year())));
+
+        let title = self.field("title").unwrap_or_else(|| self.key.clone());
+        let container = self.field("journal").or_else(|| self.field("booktitle"));
+        match &container {
+            Some(container) => {
+                output.push_str(&format!("{}. {}", escape(&title), emphasize(container)));
+                if let Some(volume) = self.field("volume") {
+                    output.push_str(&format!(", {}", escape(&volume)));
+                }
+                if let Some(number) = self.field("number") {
+                    output.push_str(&format!("({})", escape(&number)));
+                }
+                if let Some(pages) = self.field("pages") {
+                    output.push_str(&format!(", {}", escape(&pages)));
+                }
+                output.push('.');
+            }
+            None => output.push_str(&format!("{}.", emphasize(&title))),
+        }
+
+        if let Some(publisher) = self.field("publisher").or_else(|| self.field("school")) {
+            output.push_str(&format!(" {}.", escape(&publisher)));
+        }
+
+        let link = match self.field("doi") {
+            Some(doi) => Some(format!("https://doi.org/{}", doi)),
+            None => self.field("url"),
+        };
+        if let Some(link) = link {
+            output.push_str(&match document.get_output_format() {
+                OutputFormat::Html => format!(" <a href=\"{0}\">{0}</a>", escape(&link)),
+                _ => format!(" <{}>", link),
+            });
+        }
+
+        output
+    }
+}
+
+/// Split a list of names on the `and`s that are not inside of braces
+fn split_names(names: &str) -> Vec<String> {
+    let mut result = vec![];
+    let mut current = vec![];
+    let mut depth = 0;
+    for word in names.split_whitespace() {
+        if depth == 0 && word.eq_ignore_ascii_case("and") {
+            result.push(current.join(" "));
+            current.clear();
+            continue;
+        }
+        depth += word.matches('{').count() as isize - word.matches('}').count() as isize;
+        current.push(word);
+    }
+    result.push(current.join(" "));
+    result.into_iter().filter(|name| !name.is_empty()).collect()
+}
+
+/// Names are written as `Last, First` or `First Last`, braces keep several words together
+fn parse_name(name: &str) -> Name {
+    if let Some((last, first)) = name.split_once(',') {
+        // `Last, Jr, First`
+        let first = first.rsplit(',').next().unwrap_or(first);
+        return Name {
+            first: plain_text(first),
+            last: plain_text(last),
+        };
+    }
+
+    let mut words: Vec<String> = vec![];
+    let mut depth = 0;
+    for word in name.split_whitespace() {
+        match words.last_mut() {
+            Some(last) if depth > 0 => {
+                last.push(' ');
+                last.push_str(word);
+            }
+            _ => words.push(word.to_string()),
+        }
+        depth += word.matches('{').count() as isize - word.matches('}').count() as isize;
+    }
+
+    let last = words.pop().unwrap_or_default();
+    Name {
+        first: plain_text(&words.join(" ")),
+        last: plain_text(&last),
+    }
+}
+
+/// `Donald Ervin` becomes `D. E.`
+fn initials(first: &str) -> String {
+    first
+        .split_whitespace()
+        .filter_map(|name| name.chars().next())
+        .map(|initial| format!("{}.", initial))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Remove the LaTeX markup from a value, accents are kept as combining characters
+fn plain_text(value: &str) -> String {
+    let mut output = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => (),
+            '~' => output.push('\u{a0}'),
+            '-' if chars.peek() == Some(&'-') => {
+                chars.next();
+                if chars.peek() == Some(&'-') {
+                    chars.next();
+                    output.push('—');
+                } else {
+                    output.push('–');
+                }
+            }
+            '\\' => {
+                let command = match chars.next() {
+                    Some(c) if c.is_alphabetic() => {
+                        let mut command = c.to_string();
+                        while let Some(c) = chars.peek().filter(|c| c.is_alphabetic()) {
+                            command.push(*c);
+                            chars.next();
+                        }
+                        command
+                    }
+                    Some(c) => c.to_string(),
+                    None => break,
+                };
+
+                let accent = match command.as_str() {
+                    "\"" => Some('\u{308}'),
+                    "'" => Some('\u{301}'),
+                    "`" => Some('\u{300}'),
+                    "^" => Some('\u{302}'),
+                    "~" => Some('\u{303}'),
+                    "c" => Some('\u{327}'),
+                    "r" => Some('\u{30a}'),
+                    _ => None,
+                };
+                match (accent, command.as_str()) {
+                    (Some(accent), _) => {
+                        while matches!(chars.peek(), Some(c) if *c == '{' || c.is_whitespace()) {
+                            chars.next();
+                        }
+                        if let Some(letter) = chars.next() {
+                            output.push(letter);
+                            output.push(accent);
+                        }
+                    }
+                    (None, "ss") => output.push('ß'),
+                    (None, "o") => output.push('ø'),
+                    (None, "O") => output.push('Ø'),
+                    (None, "aa") => output.push('å'),
+                    (None, "AA") => output.push('Å'),
+                    (None, "ae") => output.push('æ'),
+                    (None, "AE") => output.push('Æ'),
+                    // font switches like {\em text}
+                    (None, "em" | "it" | "bf" | "sc" | "rm" | "sf" | "tt") => (),
+                    // commands like \textit only keep their argument
+                    (None, command) if command.chars().all(char::is_alphabetic) => {
+                        let has_argument = chars.peek() == Some(&'{');
+                        if has_argument {
+                            chars.next();
+                        }
+                        // while \LaTeX and \LaTeX{} are written as their name
+                        match chars.peek() {
+                            Some('}') if has_argument => output.push_str(command),
+                            _ if has_argument => (),
+                            _ => output.push_str(command),
+                        }
+                    }
+                    // escaped characters like \&
+                    (None, command) => output.push_str(command),
+                }
+            }
+            c if c.is_whitespace() => {
+                if !output.ends_with(' ') {
+                    output.push(' ');
+                }
+            }
+            c => output.push(c),
+        }
+    }
+    output.trim().to_string()
+}
@@ -0,0 +1,121 @@
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// How long an entry is kept without being used
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+lazy_static! {
+    /// The directories that have been pruned by this process
+    static ref PRUNED: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+}
+
+/// A response that is stored on disk, in a file named after the hash of its key.
+/// Everything that the response depends on is part of the key, so an entry is
+/// never updated, a change gives a new key and the old entry is left unused
+/// until it is removed by `prune`.
+pub(crate) struct Entry {
+    path: PathBuf,
+    key: String,
+}
+
+/// The contents of an entry file, the key is kept to tell apart keys with the same hash
+#[derive(Serialize, Deserialize)]
+struct Stored {
+    key: String,
+    response: String,
+}
+
+impl Entry {
+    pub fn new(directory: &Path, key: String) -> Self {
+        Entry {
+            path: directory.join(format!("{:016x}.json", hash(&key))),
+            key,
+        }
+    }
+
+    /// The stored response, if there is one
+    pub fn load(&self) -> Option<String> {
+        let contents = fs::read_to_string(&self.path).ok()?;
+        let stored: Stored = serde_json::from_str(&contents).ok()?;
+        if stored.key != self.key {
+            return None;
+        }
+
+        // the modification time tells when the entry was last used
+        let _ = fs::File::options()
+            .write(true)
+            .open(&self.path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        Some(stored.response)
+    }
+
+    /// Store a response, the cache is only there to save time
+    /// so it is not a problem if it can not be written
+    pub fn store(&self, response: &str) {
+        let stored = Stored {
+            key: self.key.clone(),
+            response: response.to_string(),
+        };
+        let contents = match serde_json::to_string(&stored) {
+            Ok(contents) => contents,
+            Err(_) => return,
+        };
+
+        // written to another file first, so that an entry is never read half written
+        let temporary = self
+            .path
+            .with_extension(format!("{}.tmp", std::process::id()));
+        let written = self
+            .path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&temporary, contents))
+            .and_then(|_| fs::rename(&temporary, &self.path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+    }
+}
+
+/// Remove the entries, and files left by failed writes, that have not been used for
+/// `MAX_AGE`. A directory is only pruned once by each process, since the cli may
+/// translate a document many times.
+pub(crate) fn prune(directory: &Path) {
+    if !PRUNED.lock().unwrap().insert(directory.to_path_buf()) {
+        return;
+    }
+
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let is_entry = matches!(
+            path.extension().and_then(|extension| extension.to_str()),
+            Some("json") | Some("tmp")
+        );
+        let is_unused = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > MAX_AGE);
+        if is_entry && is_unused {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// The 64 bit FNV-1a hash, which unlike the hasher of the standard
+/// library stays the same between versions of Rust
+fn hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
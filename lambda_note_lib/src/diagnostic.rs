@@ -0,0 +1,160 @@
+use crate::parser::{Origin, OriginName};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// An error or a warning produced while translating a document,
+/// e.g. by an extension or when an unknown extension is used.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// Where in the source text the problem was found, if known
+    pub origin: Option<Origin>,
+    /// The name of the extension that reported the problem
+    pub extension: Option<String>,
+    /// A short machine readable identifier, like "unknown-extension"
+    pub code: Option<String>,
+    /// A suggestion on how to fix the problem
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: &str) -> Self {
+        Diagnostic {
+            severity,
+            message: message.to_string(),
+            origin: None,
+            extension: None,
+            code: None,
+            help: None,
+        }
+    }
+
+    pub fn error(message: &str) -> Self {
+        Diagnostic::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: &str) -> Self {
+        Diagnostic::new(Severity::Warning, message)
+    }
+
+    pub fn with_origin(mut self, origin: &Origin) -> Self {
+        self.origin = Some(origin.clone());
+        self
+    }
+
+    pub fn with_extension(mut self, extension: &str) -> Self {
+        self.extension = Some(extension.to_string());
+        self
+    }
+
+    pub fn with_code(mut self, code: &str) -> Self {
+        self.code = Some(code.to_string());
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.help = Some(help.to_string());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// The first line of the diagnostic, i.e the severity, code and message
+    pub fn headline(&self) -> String {
+        let mut headline = self.severity.to_string();
+        if let Some(code) = &self.code {
+            headline.push_str(&format!("[{}]", code));
+        }
+        headline.push_str(": ");
+        if let Some(extension) = &self.extension {
+            headline.push_str(&format!("{}: ", extension));
+        }
+        headline.push_str(&self.message);
+        headline
+    }
+
+    /// Describes the chain of extensions that produced the text where the
+    /// problem was found, starting with the one called from the source file
+    pub fn backtrace(&self) -> Vec<String> {
+        let origin = match &self.origin {
+            Some(origin) => origin,
+            None => return vec![],
+        };
+
+        origin
+            .backtrace()
+            .into_iter()
+            .map(|(extension, origin)| {
+                let mut note = format!("in the expansion of {}", extension);
+                // foreign extensions do not tell us where their output came from
+                if origin.line_number > 0 {
+                    note.push_str(&format!(", line {}", origin.line_number));
+                }
+                if !origin.span.is_unknown() {
+                    note.push_str(&format!(", column {}", origin.span.start.column));
+                }
+                note
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for OriginName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OriginName::Filename(name) => write!(f, "{}", name),
+            OriginName::Expansion(extension, _) => write!(f, "expansion of {}", extension),
+        }
+    }
+}
+
+/// Formats the diagnostic on a few lines without any source excerpt, e.g.
+/// ```text
+/// error[unknown-extension]: No extension found with the name of foo
+///  --> notes.ln:3:5
+///   = note: in the expansion of Link, line 1, column 3
+///   = help: ...
+/// ```
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.headline())?;
+
+        if let Some(origin) = &self.origin {
+            let root = origin.root();
+            write!(f, "\n --> {}:{}", root.name, root.line_number)?;
+            if !root.span.is_unknown() {
+                write!(f, ":{}", root.span.start.column)?;
+            }
+        }
+
+        for note in self.backtrace() {
+            write!(f, "\n  = note: {}", note)?;
+        }
+
+        if let Some(help) = &self.help {
+            write!(f, "\n  = help: {}", help)?;
+        }
+
+        Ok(())
+    }
+}
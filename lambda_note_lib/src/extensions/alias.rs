@@ -1,4 +1,5 @@
 use crate::extensions::{Context, Extension};
+use crate::Diagnostic;
 
 #[derive(Clone)]
 pub struct Alias;
@@ -32,7 +33,7 @@ impl Extension for Alias {
                 &mut ctx,
             );
         }
-        let original = ctx.arguments.get(0)?.trim();
+        let original = ctx.arguments.first()?.trim();
         let new = ctx.arguments.get(1)?.trim();
 
         match ctx.document.extensions.get(original) {
@@ -40,8 +41,9 @@ impl Extension for Alias {
                 let extension = extension.clone();
                 ctx.document.extensions.insert(new.into(), extension)?;
             }
-            None => self.add_error(
-                &format!("No extension found with the name of {}", original),
+            None => self.add_diagnostic(
+                Diagnostic::error(&format!("No extension found with the name of {}", original))
+                    .with_code("unknown-extension"),
                 &mut ctx,
             ),
         };
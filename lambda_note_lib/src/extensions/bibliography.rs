@@ -0,0 +1,92 @@
+use crate::bibtex::{self, Style};
+use crate::extensions::{Context, Extension};
+use crate::translator::OutputFormat;
+
+/// **Native extension**: the list of the entries cited with the cite extension
+#[derive(Clone)]
+pub struct Bibliography;
+
+impl Extension for Bibliography {
+    fn name(&self) -> String {
+        "Bibliography".to_string()
+    }
+
+    fn description(&self) -> String {
+        "List the cited entries of the bibliography, with `\\printbibliography` in LaTeX.\n\
+        \n```\n--- bibliography\n---\n```"
+            .to_string()
+    }
+
+    fn version(&self) -> String {
+        "1".to_string()
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+
+    fn call(&self, ctx: Context) -> Option<String> {
+        if ctx.output_format == OutputFormat::LambdaNote {
+            return Some(ctx.to_source("bibliography"));
+        }
+
+        // the citations further down are not known yet,
+        // so the list is made once the whole document is translated
+        let directory = bibtex::document_directory(ctx.document, &ctx.origin);
+        let origin = ctx.origin.clone();
+        let format = ctx.output_format;
+        Some(ctx.document.defer(move |document| {
+            let bibliography = bibtex::load(document, &directory, &origin);
+            let style = bibliography.style;
+
+            if format == OutputFormat::Latex {
+                bibtex::import_biblatex(document, style);
+                return "\\printbibliography".to_string();
+            }
+
+            let cited = match &bibliography.entries {
+                Some(entries) => bibtex::cited_entries(document, entries, style),
+                None => return String::new(),
+            };
+            if cited.is_empty() {
+                return String::new();
+            }
+
+            let items: Vec<String> = cited
+                .iter()
+                .enumerate()
+                .map(|(index, entry)| match (format, style) {
+                    (OutputFormat::Html, _) => format!(
+                        "<li id=\"ref-{}\">{}</li>\n",
+                        document.escape_str(&entry.key),
+                        entry.format(document)
+                    ),
+                    (_, Style::Numeric) => format!("{}. {}\n", index + 1, entry.format(document)),
+                    (_, Style::AuthorYear) => format!("- {}\n", entry.format(document)),
+                })
+                .collect();
+
+            match (format, style) {
+                (OutputFormat::Html, Style::Numeric) => {
+                    format!("<ol class=\"bibliography\">\n{}</ol>", items.concat())
+                }
+                (OutputFormat::Html, Style::AuthorYear) => {
+                    format!("<ul class=\"bibliography\">\n{}</ul>", items.concat())
+                }
+                _ => items.concat(),
+            }
+        }))
+    }
+
+    fn supports_block(&self) -> bool {
+        true
+    }
+
+    fn supports_inline(&self) -> bool {
+        false
+    }
+
+    fn interests(&self) -> Vec<String> {
+        vec!["bibliography".to_string(), "citation_style".to_string()]
+    }
+}
@@ -53,12 +53,12 @@ impl Extension for Calc {
 
     fn call(&self, mut context: Context) -> Option<String> {
         let prefix = context.arguments.get(1).cloned();
-        let expression = match context.arguments.get(0) {
+        let expression = match context.arguments.first() {
             None => {
                 self.add_error("No expression to evaluate was provided", &mut context);
                 return None;
             }
-            Some(text) => text,
+            Some(text) => text.clone(),
         };
 
         // if a third argument is given and is "display" the entire
@@ -74,11 +74,12 @@ impl Extension for Calc {
         if display {
             output.push_str(&context.document.translate_no_template(
                 &format!("--------- code\n{}\n---------", expression),
-                "calc expression",
+                context.origin.expand(&self.name()),
             ))
         }
 
         let mut eval_context = HashMapContext::new();
+        let mut invalid_entries = vec![];
 
         // first we get all the relevent metadata entries.
         // This is not really optimal since metadata is stored in a hashmap and
@@ -95,26 +96,25 @@ impl Extension for Calc {
             let value = match eval(v) {
                 Ok(value) => value,
                 Err(_) => {
-                    // FIXME: use self.add_error when it has been improved
-                    // (should not take the entire context, only &mut document)
-                    context.document.warnings.push(format!(
-                        "Failed to parse the value in the meta data entry:\n :: {} = {}",
-                        k, v
-                    ));
+                    invalid_entries.push(format!(":: {} = {}", k, v));
                     continue;
                 }
             };
 
             if eval_context.set_value(variable_name.into(), value).is_err() {
-                context.document.warnings.push(format!(
-                    "Failed to parse the value in the meta data entry:\n :: {} = {}",
-                    k, v
-                ));
+                invalid_entries.push(format!(":: {} = {}", k, v));
             }
         }
 
+        for entry in invalid_entries {
+            self.add_warning(
+                &format!("Failed to parse the value in the meta data entry {}", entry),
+                &mut context,
+            );
+        }
+
         // actually evaluate the expression
-        match eval_with_context_mut(expression, &mut eval_context) {
+        match eval_with_context_mut(&expression, &mut eval_context) {
             Ok(value) => {
                 let value_str = match value {
                     Value::Empty => "".into(),
@@ -130,7 +130,9 @@ impl Extension for Calc {
                 // if we have a block extension we can translate the contents of the
                 // prefix and result without causing issues too.
                 if context.variant == ExtensionVariant::Block {
-                    result = context.document.translate_no_template(&result, "calc extension");
+                    result = context
+                        .document
+                        .translate_no_template(&result, context.origin.expand(&self.name()));
                 }
 
                 output.push_str(&result);
@@ -0,0 +1,128 @@
+use crate::bibtex::{self, Style};
+use crate::extensions::{Context, Extension};
+use crate::translator::OutputFormat;
+use crate::Diagnostic;
+
+/// **Native extension**: cite entries of the bibliography
+#[derive(Clone)]
+pub struct Cite;
+
+impl Extension for Cite {
+    fn name(&self) -> String {
+        "Cite".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Cite one or more entries from the BibTeX file in the `bibliography` metadata field.\n\
+        Set `citation_style` to `numeric` (the default) or `author-year`.\n\
+        \n```\n:: bibliography = references.bib\n\n\
+        As shown by |cite, knuth1984, lamport1994|\n```"
+            .to_string()
+    }
+
+    fn version(&self) -> String {
+        "1".to_string()
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+
+    fn call(&self, mut ctx: Context) -> Option<String> {
+        let keys: Vec<String> = ctx
+            .arguments
+            .iter()
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty())
+            .collect();
+        if keys.is_empty() {
+            self.add_error("No key provided. Like this: |cite, knuth1984|", &mut ctx);
+            return None;
+        }
+
+        if ctx.output_format == OutputFormat::LambdaNote {
+            return Some(ctx.to_source("cite"));
+        }
+
+        for key in &keys {
+            if !ctx.document.citations.contains(key) {
+                ctx.document.citations.push(key.clone());
+            }
+        }
+
+        // the metadata may be set further down, so the citation
+        // is made once the whole document is translated
+        let directory = bibtex::document_directory(ctx.document, &ctx.origin);
+        let origin = ctx.origin.clone();
+        let name = self.name();
+        let format = ctx.output_format;
+        Some(ctx.document.defer(move |document| {
+            let bibliography = bibtex::load(document, &directory, &origin);
+            let style = bibliography.style;
+
+            if let Some(entries) = &bibliography.entries {
+                for key in keys.iter().filter(|key| !entries.contains_key(*key)) {
+                    document.add_diagnostic(
+                        Diagnostic::warning(&format!(
+                            "There is no entry {} in the bibliography",
+                            key
+                        ))
+                        .with_origin(&origin)
+                        .with_extension(&name)
+                        .with_code("unknown-citation"),
+                    );
+                }
+            }
+
+            if format == OutputFormat::Latex {
+                bibtex::import_biblatex(document, style);
+                return match style {
+                    Style::Numeric => format!("\\cite{{{}}}", keys.join(",")),
+                    Style::AuthorYear => format!("\\parencite{{{}}}", keys.join(",")),
+                };
+            }
+
+            let cited = match &bibliography.entries {
+                Some(entries) => bibtex::cited_entries(document, entries, style),
+                None => vec![],
+            };
+            let citations: Vec<String> = keys
+                .iter()
+                .map(|key| {
+                    let position = cited.iter().position(|entry| entry.key == *key);
+                    let text = match (position, style) {
+                        (Some(position), Style::Numeric) => (position + 1).to_string(),
+                        (Some(position), Style::AuthorYear) => cited[position].author_year(),
+                        // unknown keys are written as they are, like LaTeX does
+                        (None, _) => key.clone(),
+                    };
+                    match (format, position) {
+                        (OutputFormat::Html, Some(_)) => format!(
+                            "<a href=\"#ref-{}\">{}</a>",
+                            document.escape_str(key),
+                            document.escape_str(&text)
+                        ),
+                        _ => document.escape_str(&text),
+                    }
+                })
+                .collect();
+
+            match style {
+                Style::Numeric => format!("[{}]", citations.join(", ")),
+                Style::AuthorYear => format!("({})", citations.join("; ")),
+            }
+        }))
+    }
+
+    fn supports_block(&self) -> bool {
+        false
+    }
+
+    fn supports_inline(&self) -> bool {
+        true
+    }
+
+    fn interests(&self) -> Vec<String> {
+        vec!["bibliography".to_string(), "citation_style".to_string()]
+    }
+}
@@ -40,7 +40,8 @@ impl Extension for Code {
         match ctx.output_format {
             OutputFormat::Html => html(&ctx),
             OutputFormat::Latex => latex(&mut ctx),
-            _ => None,
+            OutputFormat::LambdaNote => Some(ctx.to_source("code")),
+            OutputFormat::Markdown => markdown(&ctx),
         }
     }
 
@@ -76,7 +77,7 @@ fn html(ctx: &Context) -> Option<String> {
         PS.find_syntax_plain_text()
     };
 
-    let code = match ctx.arguments.get(0) {
+    let code = match ctx.arguments.first() {
         Some(value) => value.to_string(),
         None => "".into(),
     };
@@ -95,7 +96,7 @@ fn html(ctx: &Context) -> Option<String> {
 }
 
 fn latex(ctx: &mut Context) -> Option<String> {
-    let code = match ctx.arguments.get(0) {
+    let code = match ctx.arguments.first() {
         Some(value) => value.to_string(),
         None => "".into(),
     };
@@ -121,3 +122,32 @@ fn latex(ctx: &mut Context) -> Option<String> {
         }
     })
 }
+
+fn markdown(ctx: &Context) -> Option<String> {
+    let code = ctx.arguments.first().map_or("", |code| code);
+
+    // the fence has to be longer than any sequence of backticks in the code
+    let longest_backticks = code
+        .split(|c| c != '`')
+        .map(|backticks| backticks.len())
+        .max()
+        .unwrap_or(0);
+
+    Some(match ctx.variant {
+        ExtensionVariant::Block => {
+            let fence = "`".repeat((longest_backticks + 1).max(3));
+            let language = ctx.arguments.get(1).map_or("", |language| language.trim());
+            format!("{fence}{}\n{}\n{fence}", language, code, fence = fence)
+        }
+        ExtensionVariant::Inline => {
+            let fence = "`".repeat(longest_backticks + 1);
+            let code = code.trim();
+            // a space is needed to separate backticks in the code from the fence
+            if code.starts_with('`') || code.ends_with('`') {
+                format!("{fence} {} {fence}", code, fence = fence)
+            } else {
+                format!("{fence}{}{fence}", code, fence = fence)
+            }
+        }
+    })
+}
@@ -9,7 +9,7 @@ use regex::Regex;
 
 //possible expressions:
 //      platform/os = macos/mac/windows/win/linux/unix/web/wasm (unix includes mac)
-//      target/output/file/type/extension = html/latex/tex/lambdanote/λnote
+//      target/output/file/type/extension = html/latex/tex/lambdanote/λnote/markdown/md
 //      conditional_XXX = str
 
 //boolean expressions are key/value pairs separated by =, ==, !=, is, is not, isn't
@@ -88,14 +88,14 @@ impl Extension for Conditional {
             }
             Some(
                 ctx.document
-                    .translate_no_template(&body, "Conditinal extension"),
+                    .translate_no_template(&body, ctx.origin.expand(&self.name())),
             )
         } else {
             for expr in exprs {
                 if expr.check(&mut ctx, self, platform) {
                     return Some(
                         ctx.document
-                            .translate_no_template(&body, "Conditinal extension"),
+                            .translate_no_template(&body, ctx.origin.expand(&self.name())),
                     );
                 }
             }
@@ -213,9 +213,12 @@ impl Conditional {
                         "lambdanote" | "λnote" => {
                             Some(Expression::OutputEquality(OutputFormat::LambdaNote, xnor))
                         }
+                        "markdown" | "md" => {
+                            Some(Expression::OutputEquality(OutputFormat::Markdown, xnor))
+                        }
                         _ => {
                             self.add_error(&format!(
-                            "Unknown file type: {}\nAllowed values: HTML, LaTeX/TeX, Lambdanote/λnote",
+                            "Unknown file type: {}\nAllowed values: HTML, LaTeX/TeX, Lambdanote/λnote, Markdown/md",
                             val
                         ), ctx);
                             None
@@ -261,6 +264,8 @@ enum ExpressionRule {
     BooleanAnd,
     BooleanOr,
 }
+
+#[allow(clippy::enum_variant_names)]
 enum Expression {
     PlatformEquality(Platform, bool),
     OutputEquality(OutputFormat, bool),
@@ -1,16 +1,29 @@
 use super::ExtensionVariant;
+use crate::cache::Entry;
 use crate::extensions::{Context, Extension};
 use crate::translator::OutputFormat;
-use crate::Origin;
+use crate::{Origin, OriginName};
+use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::fmt;
-use std::io::{Read, Write};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::num::NonZeroUsize;
 #[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
-use std::process::{Command, Stdio, Child};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio, Child, ChildStdin};
 use std::rc::Rc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
 use wait_timeout::ChildExt;
 
 #[derive(Clone)]
@@ -28,6 +41,13 @@ impl Extension for Define {
         |define, name, shell command|\n\
         ```\n\
         Like this for example `|define, uppercase, python main.py|`.\n\n\
+        Add `persistent` as a third argument to start the command once and send it\n\
+        one JSON-RPC request per line, instead of starting it for every request.\n\
+        Extensions that answer `\"pure\": true` to the info request are called\n\
+        concurrently, their output is put in place once the document is translated.\n\
+        Responses are cached if a cache directory is set, unless the extension\n\
+        answers `\"cache\": false`. The origin of a call is not part of the cache key,\n\
+        so extensions whose output depends on it should not be cached.\n\n\
         You can also specify the timeout (in seconds) by using the metadata\n\
         field 'timeout'."
             .to_string()
@@ -42,7 +62,7 @@ impl Extension for Define {
     }
 
     fn call(&self, mut ctx: Context) -> Option<String> {
-        if ctx.arguments.get(0).is_none() {
+        if ctx.arguments.is_empty() {
             self.add_error("No name provided as first argument", &mut ctx);
         }
 
@@ -53,11 +73,23 @@ impl Extension for Define {
             );
         }
 
-        let name = ctx.arguments.get(0)?.trim().to_string();
-        let command = ctx.arguments.get(1)?;
+        let name = ctx.arguments.first()?.trim().to_string();
+        let command = ctx.arguments.get(1)?.clone();
         let timeout = get_timeout(&ctx);
 
-        match get_extension(command, timeout) {
+        let mode = match ctx.arguments.get(2).map(|mode| mode.trim()) {
+            None | Some("") => Mode::OneShot,
+            Some("persistent") => Mode::Persistent,
+            Some(mode) => {
+                self.add_error(
+                    &format!("Unknown mode {}, the only mode is persistent", mode),
+                    &mut ctx,
+                );
+                return None;
+            }
+        };
+
+        match get_extension(&command, timeout, mode) {
             Ok(extension) => {
                 // log all the provided errors and warnings
                 for error in &extension.errors {
@@ -111,8 +143,7 @@ fn get_timeout(ctx: &Context) -> f32 {
     ctx.document
         .metadata
         .get("timeout")
-        .map(|x| x.replace("second", "").replace("s", "").parse::<f32>().ok())
-        .flatten()
+        .and_then(|x| x.replace("second", "").replace("s", "").parse::<f32>().ok())
         .unwrap_or(2.0)
 }
 #[cfg(target_os = "windows")]
@@ -129,7 +160,7 @@ fn spawn_windows(command_str: &str) -> Result<Child, Error> {
         .map_err(|_| Error::ProcessFailure)
 }
 
-#[cfg(not(target_os = "window"))]
+#[cfg(not(target_os = "windows"))]
 fn spawn_non_windows(command_str: &str) -> Result<Child, Error> {
     Command::new("sh")
         .arg("-c")
@@ -150,51 +181,288 @@ fn send<T: Serialize>(command: &str, timeout: f32, req: T) -> Result<String, Err
 
     let contents = serde_json::to_string(&req).unwrap();
 
-    // write the request object to the childs stdin
-    if child
+    // stdout is read by a separate thread, since a child with a response that does
+    // not fit in the pipe would otherwise never exit, and so that reading can time out
+    let mut stdout = child.stdout.take().ok_or(Error::ProcessFailure)?;
+    let (sender, output) = mpsc::channel();
+    thread::spawn(move || {
+        let mut result = String::new();
+        let read = stdout.read_to_string(&mut result).map(|_| result);
+        let _ = sender.send(read);
+    });
+
+    // write the request object to the childs stdin, and close it
+    let written = child
         .stdin
-        .as_mut()
-        .ok_or(Error::ProcessFailure)?
-        .write_all(contents.as_bytes())
-        .is_err()
-    {
-        return Err(Error::ProcessFailure);
-    }
+        .take()
+        .ok_or(Error::ProcessFailure)
+        .and_then(|mut stdin| {
+            stdin
+                .write_all(contents.as_bytes())
+                .map_err(|_| Error::ProcessFailure)
+        });
+
+    let result = match written {
+        Ok(()) => match output.recv_timeout(Duration::from_secs_f32(timeout)) {
+            Ok(read) => read.map_err(|_| Error::ProcessFailure),
+            Err(RecvTimeoutError::Timeout) => Err(Error::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::ProcessFailure),
+        },
+        Err(error) => Err(error),
+    };
 
-    let secs = Duration::from_secs_f32(timeout);
+    // kill the child process if it has not exited by itself
+    if result.is_err() {
+        child.kill().map_err(|_| Error::ProcessFailure)?;
+    }
+    child.wait().map_err(|_| Error::ProcessFailure)?;
+    result
+}
 
-    // kill the child process if we timeout
-    if child.wait_timeout(secs).ok().flatten().is_none() {
-        child.kill().expect("Failed to kill process");
-        return Err(Error::Timeout);
+type Job = Box<dyn FnOnce() + Send>;
+
+lazy_static! {
+    /// Threads that send the requests to pure extensions in one-shot mode, one for
+    /// every core but at least a few, since commands often wait for more than the cpu
+    static ref WORKERS: Mutex<Sender<Job>> = {
+        let (sender, jobs) = mpsc::channel::<Job>();
+        let jobs = Arc::new(Mutex::new(jobs));
+        let count = thread::available_parallelism().map_or(1, NonZeroUsize::get).max(4);
+        for _ in 0..count {
+            let jobs = Arc::clone(&jobs);
+            thread::spawn(move || loop {
+                let job = match jobs.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+                // a failed job only drops its sender, the worker keeps going
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+            });
+        }
+        Mutex::new(sender)
     };
+}
 
-    match child.stdout.take() {
-        Some(mut stdout) => {
-            let mut result = String::new();
-            stdout.read_to_string(&mut result).unwrap();
-            Ok(result)
-        }
-        None => Err(Error::ProcessFailure),
-    }
+/// Send a request on one of the worker threads, the response is received once it is done
+fn send_in_background(
+    command: &str,
+    timeout: f32,
+    req: ActionRequest,
+) -> Receiver<Result<String, Error>> {
+    let (sender, receiver) = mpsc::channel();
+    let command = command.to_string();
+    let job: Job = Box::new(move || {
+        let _ = sender.send(send(&command, timeout, req));
+    });
+    // the receiver reports a failure if the job never runs
+    let _ = WORKERS.lock().unwrap().send(job);
+    receiver
 }
 
 /// Get a extension struct given a shell command and timeout
-fn get_extension(command: &str, timeout: f32) -> Result<ForeignExtension, Error> {
-    let response = send(command, timeout, InfoRequest::new())?;
+fn get_extension(command: &str, timeout: f32, mode: Mode) -> Result<ForeignExtension, Error> {
+    let mut process = None;
+    let response = match mode {
+        Mode::OneShot => send(command, timeout, InfoRequest::new())?,
+        Mode::Persistent => {
+            let mut started = Process::start(command)?;
+            let response = started.request("info", InfoRequest::new(), timeout)?;
+            process = Some(started);
+            response
+        }
+    };
+
     serde_json::from_str(&response)
         .map_err(|_| Error::JsonParsingFailure(response))
         .map(|mut extension: ForeignExtension| {
             // set the command field
             extension.command = command.to_string();
+            extension.mode = mode;
+            extension.process = Rc::new(RefCell::new(process));
             extension
         })
 }
 
+/// How requests are sent to the command of a foreign extension
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum Mode {
+    /// Start the command for every request, which is written to its stdin
+    /// and answered on its stdout
+    #[default]
+    OneShot,
+    /// Start the command once per document and send it one JSON-RPC request
+    /// per line, each answered with a line that has the same id
+    Persistent,
+}
+
+/// A command that keeps running and answers line-delimited JSON-RPC requests
+#[derive(Debug)]
+struct Process {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    /// The lines of stdout, read by a separate thread so that reading can time out
+    lines: Receiver<String>,
+    /// Requests with a lower id were sent to an earlier process
+    first_id: u64,
+    /// The requests that have not been waited for yet,
+    /// with their response if it has been read already
+    pending: HashMap<u64, Option<Result<String, Error>>>,
+}
+
+/// Request ids are unique across processes, so that a restarted
+/// process never answers a request that was sent to the one before it
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+impl Process {
+    fn start(command: &str) -> Result<Self, Error> {
+        #[cfg(target_os = "windows")]
+        let mut child = spawn_windows(command)?;
+        #[cfg(not(target_os = "windows"))]
+        let mut child = spawn_non_windows(command)?;
+
+        let stdin = child.stdin.take();
+        let stdout = child.stdout.take().ok_or(Error::ProcessFailure)?;
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Process {
+            child,
+            stdin,
+            lines,
+            first_id: NEXT_ID.load(Ordering::Relaxed),
+            pending: HashMap::new(),
+        })
+    }
+
+    /// Send a request and wait for the response with the same id,
+    /// returns the result as a JSON string
+    fn request<T: Serialize>(
+        &mut self,
+        method: &str,
+        params: T,
+        timeout: f32,
+    ) -> Result<String, Error> {
+        let id = self.send_request(method, params)?;
+        self.wait(id, timeout)
+    }
+
+    /// Send a request without waiting for its response, returns its id
+    fn send_request<T: Serialize>(&mut self, method: &str, params: T) -> Result<u64, Error> {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        self.send(&RpcRequest {
+            jsonrpc: "2.0",
+            id: Some(id),
+            method,
+            params: Some(params),
+        })?;
+        self.pending.insert(id, None);
+        Ok(id)
+    }
+
+    /// Wait for the response to a request that has been sent,
+    /// responses to other pending requests are kept until they are waited for
+    fn wait(&mut self, id: u64, timeout: f32) -> Result<String, Error> {
+        let secs = Duration::from_secs_f32(timeout);
+        loop {
+            if let Some(result) = self.pending.get_mut(&id).and_then(Option::take) {
+                self.pending.remove(&id);
+                return result;
+            }
+
+            let line = match self.lines.recv_timeout(secs) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => {
+                    self.pending.remove(&id);
+                    return Err(Error::Timeout);
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(Error::ProcessFailure),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response: RpcResponse =
+                serde_json::from_str(&line).map_err(|_| Error::JsonParsingFailure(line.clone()))?;
+            let result = match (response.result, response.error) {
+                (_, Some(error)) => Err(Error::Response(error.message)),
+                (Some(result), None) => Ok(result.to_string()),
+                (None, None) => Err(Error::JsonParsingFailure(line)),
+            };
+            // responses to requests that timed out earlier are skipped
+            if let Some(slot) = response.id.and_then(|id| self.pending.get_mut(&id)) {
+                *slot = Some(result);
+            }
+        }
+    }
+
+    /// Write a message as a single line to stdin
+    fn send<T: Serialize>(&mut self, message: &RpcRequest<T>) -> Result<(), Error> {
+        let mut line = serde_json::to_string(message).unwrap();
+        line.push('\n');
+        let stdin = self.stdin.as_mut().ok_or(Error::ProcessFailure)?;
+        stdin
+            .write_all(line.as_bytes())
+            .and_then(|_| stdin.flush())
+            .map_err(|_| Error::ProcessFailure)
+    }
+}
+
+impl Drop for Process {
+    /// Ask the process to shut down and close its stdin,
+    /// it is killed if it has not exited within a second
+    fn drop(&mut self) {
+        let _ = self.send::<Value>(&RpcRequest {
+            jsonrpc: "2.0",
+            id: None,
+            method: "shutdown",
+            params: None,
+        });
+        self.stdin.take();
+
+        if let Ok(None) = self.child.wait_timeout(Duration::from_secs(1)) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RpcRequest<'a, T> {
+    jsonrpc: &'a str,
+    /// Notifications, like shutdown, have no id and get no response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    method: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    params: Option<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse {
+    #[serde(default)]
+    id: Option<u64>,
+    #[serde(default)]
+    result: Option<Value>,
+    #[serde(default)]
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    message: String,
+}
+
 #[derive(Debug, PartialEq)]
 enum Error {
     JsonParsingFailure(String),
     ProcessFailure,
+    Response(String),
     Timeout,
 }
 
@@ -203,18 +471,32 @@ impl fmt::Display for Error {
         let text = match self {
             Error::JsonParsingFailure(msg) => format!("failing to parse the json response \"{}\"", &msg),
             Error::ProcessFailure => "failing to spawn and communicate with child process".into(),
+            Error::Response(msg) => format!("the error \"{}\" in its response", &msg),
             Error::Timeout => "timeout. If you want to give the the process more time, specify the \"timeout\" metadata field".into()
         };
         write!(f, "{}", text)
     }
 }
 
+/// The newest version of the protocol. Extensions answer the info request with
+/// the version they use, the requests they get do not change unless they do so.
+///
+/// 1. The first version, which is used if an extension does not answer with a version
+/// 2. Action requests tell how the extension was called, where and from which document
+const PROTOCOL_VERSION: u32 = 2;
+
+fn first_protocol_version() -> u32 {
+    1
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct InfoRequest {
     #[serde(rename = "type")]
     request_type: String,
     version: String,
+    /// The newest version of the protocol that can be used
+    protocol: u32,
 }
 
 impl InfoRequest {
@@ -222,6 +504,7 @@ impl InfoRequest {
         InfoRequest {
             request_type: "info".to_string(),
             version: env!("CARGO_PKG_VERSION").to_string(),
+            protocol: PROTOCOL_VERSION,
         }
     }
 }
@@ -234,7 +517,63 @@ struct ActionRequest {
     version: String,
     output_format: OutputFormat,
     arguments: Vec<String>,
-    metadata: HashMap<String, String>,
+    /// Sorted, so that the same request is always serialized the same way
+    metadata: BTreeMap<String, String>,
+    /// Left out for extensions that use the first version of the protocol
+    #[serde(flatten)]
+    call: Option<CallInfo>,
+}
+
+/// How and where an extension was called, added in version 2 of the protocol
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CallInfo {
+    protocol: u32,
+    variant: ExtensionVariant,
+    origin: CallOrigin,
+    /// None if the document is not a file, such as when it is read from stdin
+    document_path: Option<PathBuf>,
+    /// Relative paths in the document are relative to this directory
+    document_directory: PathBuf,
+    safe_mode: bool,
+}
+
+/// The place in a file where an extension was called, or where the
+/// extension that it was expanded from was called
+#[derive(Debug, Serialize, Deserialize)]
+struct CallOrigin {
+    file: String,
+    line: usize,
+}
+
+impl CallInfo {
+    fn new(protocol: u32, ctx: &Context) -> Self {
+        let root = ctx.origin.root();
+        let file = match &root.name {
+            OriginName::Filename(name) => name.clone(),
+            OriginName::Expansion(..) => String::new(),
+        };
+
+        let document_path = fs::canonicalize(ctx.document.document_file(&ctx.origin))
+            .ok()
+            .filter(|path| path.is_file());
+        let document_directory = match &document_path {
+            Some(path) => path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            None => env::current_dir().unwrap_or_default(),
+        };
+
+        CallInfo {
+            protocol,
+            variant: ctx.variant,
+            origin: CallOrigin {
+                file,
+                line: root.line_number,
+            },
+            document_path,
+            document_directory,
+            safe_mode: ctx.document.safe_mode(),
+        }
+    }
 }
 
 impl ActionRequest {
@@ -253,12 +592,18 @@ impl ActionRequest {
             })
             .collect();
 
+        let protocol = extension.protocol.min(PROTOCOL_VERSION);
         ActionRequest {
             request_type: "action".to_string(),
             version: env!("CARGO_PKG_VERSION").to_string(),
             output_format: ctx.output_format,
             arguments: ctx.arguments.clone(),
             metadata,
+            call: if protocol >= 2 {
+                Some(CallInfo::new(protocol, ctx))
+            } else {
+                None
+            },
         }
     }
 }
@@ -290,7 +635,14 @@ enum Action {
     },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A request to a pure extension that is answered while the document is translated
+enum Pending {
+    Background(Receiver<Result<String, Error>>),
+    /// The id of a request to the running process
+    Process(u64),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ForeignExtension {
     name: String,
@@ -307,46 +659,140 @@ struct ForeignExtension {
     interests: Vec<String>,
     block_support: bool,
     inline_support: bool,
+    /// The output only depends on the request, so the calls can run concurrently
+    #[serde(default)]
+    pure: bool,
+    /// Whether responses may be cached, extensions whose output changes between calls opt out
+    #[serde(default = "cache_by_default")]
+    cache: bool,
+    /// Changed by the extension to invalidate its cached responses
+    #[serde(default)]
+    cache_version: Option<String>,
+    /// The version of the protocol that the extension uses
+    #[serde(default = "first_protocol_version")]
+    protocol: u32,
+    #[serde(skip)]
+    mode: Mode,
+    /// The running command in persistent mode, started again if it has stopped
+    #[serde(skip)]
+    process: Rc<RefCell<Option<Process>>>,
 }
 
-impl Extension for ForeignExtension {
-    fn name(&self) -> String {
-        self.name.clone()
-    }
+fn cache_by_default() -> bool {
+    true
+}
 
-    fn description(&self) -> String {
-        self.description.clone()
+/// Everything that a cached response depends on
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheKey<'a> {
+    command: &'a str,
+    /// The modification time and size of the files in the command, such as scripts
+    files: Vec<(&'a str, u128, u64)>,
+    version: &'a str,
+    cache_version: &'a Option<String>,
+    request: Value,
+}
+
+impl ForeignExtension {
+    /// The cache entry for a request, if the document and the extension use the cache
+    fn cache_entry(&self, ctx: &Context, req: &ActionRequest) -> Option<Entry> {
+        let directory = ctx
+            .document
+            .cache_directory
+            .as_ref()
+            .filter(|_| self.cache)?;
+        let files = self
+            .command
+            .split_whitespace()
+            .filter_map(|word| {
+                let metadata = Path::new(word).metadata().ok().filter(|m| m.is_file())?;
+                let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+                Some((word, modified.as_nanos(), metadata.len()))
+            })
+            .collect();
+
+        // the line of a call changes whenever the text above it does,
+        // which would make most responses impossible to reuse
+        let mut request = serde_json::to_value(req).ok()?;
+        if let Some(request) = request.as_object_mut() {
+            request.remove("origin");
+        }
+
+        let key = CacheKey {
+            command: &self.command,
+            files,
+            version: &self.version,
+            cache_version: &self.cache_version,
+            request,
+        };
+        Some(Entry::new(directory, serde_json::to_string(&key).ok()?))
     }
 
-    fn version(&self) -> String {
-        self.version.clone()
+    /// Send a request to the running process and wait for the response
+    fn request<T: Serialize>(
+        &self,
+        method: &str,
+        params: T,
+        timeout: f32,
+    ) -> Result<String, Error> {
+        let id = self.send_request(method, params)?;
+        self.wait(id, timeout)
     }
 
-    fn is_safe(&self) -> bool {
-        false
+    /// Send a request to the running process, which is started if it is not running
+    fn send_request<T: Serialize>(&self, method: &str, params: T) -> Result<u64, Error> {
+        let mut process = self.process.borrow_mut();
+        if process.is_none() {
+            *process = Some(Process::start(&self.command)?);
+        }
+
+        let id = process.as_mut().unwrap().send_request(method, params);
+        stop_on_failure(&mut process, &id);
+        id
     }
 
-    fn call(&self, mut ctx: Context) -> Option<String> {
-        let timeout = get_timeout(&ctx);
-        let req = ActionRequest::from(self, &ctx);
+    /// Wait for the response to a request, a process that times out
+    /// or fails is stopped and started again by the next request
+    fn wait(&self, id: u64, timeout: f32) -> Result<String, Error> {
+        let mut process = self.process.borrow_mut();
+        let response = match process.as_mut() {
+            Some(running) if id >= running.first_id => running.wait(id, timeout),
+            // the process that got the request has been stopped
+            _ => return Err(Error::ProcessFailure),
+        };
+        stop_on_failure(&mut process, &response);
+        response
+    }
 
-        let raw_response = send(&self.command, timeout, req)
-            .map_err(|error| {
+    /// Handle the response to an action request and return the content,
+    /// a response without errors is stored in the cache entry
+    fn respond(
+        &self,
+        raw_response: Result<String, Error>,
+        cache: Option<Entry>,
+        mut ctx: Context,
+    ) -> Option<String> {
+        let raw_response = raw_response
+            .inspect_err(|error| {
                 self.add_error(&error.to_string(), &mut ctx);
-                error
             })
             .ok()?;
 
         let response: ActionResponse = serde_json::from_str(&raw_response)
             .map_err(|_| {
                 self.add_error(
-                    &Error::JsonParsingFailure(raw_response).to_string(),
+                    &Error::JsonParsingFailure(raw_response.clone()).to_string(),
                     &mut ctx,
                 );
                 Error::JsonParsingFailure
             })
             .ok()?;
 
+        if let Some(cache) = cache.filter(|_| response.errors.is_empty()) {
+            cache.store(&raw_response);
+        }
+
         ctx.document.top.push_str(&response.top);
         ctx.document.bottom.push_str(&response.bottom);
         response.imports.iter().for_each(|i| ctx.document.import(i));
@@ -381,7 +827,7 @@ impl Extension for ForeignExtension {
                             &name,
                             arguments,
                             variant,
-                            &Origin::new(0, &self.name()),
+                            &Origin::new(0, ctx.origin.expand(&self.name())),
                         )
                         .unwrap_or_else(|| String::from(""))
                 }
@@ -390,6 +836,77 @@ impl Extension for ForeignExtension {
 
         Some(content)
     }
+}
+
+/// There is no point in asking a process that does not answer to shut down
+fn stop_on_failure<T>(process: &mut Option<Process>, result: &Result<T, Error>) {
+    if matches!(result, Err(Error::Timeout) | Err(Error::ProcessFailure)) {
+        if let Some(mut process) = process.take() {
+            let _ = process.child.kill();
+        }
+    }
+}
+
+impl Extension for ForeignExtension {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    fn version(&self) -> String {
+        self.version.clone()
+    }
+
+    fn is_safe(&self) -> bool {
+        false
+    }
+
+    fn call(&self, ctx: Context) -> Option<String> {
+        let timeout = get_timeout(&ctx);
+        let req = ActionRequest::from(self, &ctx);
+
+        let cache = self.cache_entry(&ctx, &req);
+        if let Some(cached) = cache.as_ref().and_then(Entry::load) {
+            return self.respond(Ok(cached), None, ctx);
+        }
+
+        if !self.pure {
+            let raw_response = match self.mode {
+                Mode::OneShot => send(&self.command, timeout, req),
+                Mode::Persistent => self.request("action", req, timeout),
+            };
+            return self.respond(raw_response, cache, ctx);
+        }
+
+        // pure extensions are answered while the rest of the document is
+        // translated, the responses are handled in order once it is done
+        let pending = match self.mode {
+            Mode::OneShot => Pending::Background(send_in_background(&self.command, timeout, req)),
+            Mode::Persistent => match self.send_request("action", req) {
+                Ok(id) => Pending::Process(id),
+                Err(error) => return self.respond(Err(error), None, ctx),
+            },
+        };
+
+        let extension = self.clone();
+        let arguments = ctx.arguments.clone();
+        let (variant, origin) = (ctx.variant, ctx.origin.clone());
+        Some(ctx.document.defer(move |document| {
+            let raw_response = match pending {
+                Pending::Background(receiver) => {
+                    receiver.recv().unwrap_or(Err(Error::ProcessFailure))
+                }
+                Pending::Process(id) => extension.wait(id, timeout),
+            };
+            let ctx = Context::new(arguments, variant, document, origin);
+            extension
+                .respond(raw_response, cache, ctx)
+                .unwrap_or_default()
+        }))
+    }
 
     fn supports_block(&self) -> bool {
         self.block_support
@@ -27,7 +27,7 @@ impl Extension for Escape {
             self.add_warning(&format!("Got {} arguments, expected 1.", nr_args), &mut ctx);
         }
 
-        let input = ctx.arguments.get(0)?;
+        let input = ctx.arguments.first()?;
         Some(ctx.document.escape_str(input))
     }
 
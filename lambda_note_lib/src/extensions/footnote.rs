@@ -0,0 +1,70 @@
+use crate::extensions::{Context, Extension};
+use crate::translator::OutputFormat;
+
+/// **Native extension**: a numbered footnote
+#[derive(Clone)]
+pub struct Footnote;
+
+impl Extension for Footnote {
+    fn name(&self) -> String {
+        "Footnote".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Add a numbered footnote, the text can use any inline markup.\n\
+        In HTML the footnotes are listed at the end of the document,\n\
+        or where the footnotes extension is used.\n\
+        \n```\nλnote has extensions|footnote, Like **this** one.|\n```"
+            .to_string()
+    }
+
+    fn version(&self) -> String {
+        "1".to_string()
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+
+    fn call(&self, mut ctx: Context) -> Option<String> {
+        if ctx.output_format == OutputFormat::LambdaNote {
+            return Some(ctx.to_source("footnote"));
+        }
+
+        // commas are part of the text rather than separating arguments
+        let text = ctx.arguments.join(",");
+        if text.trim().is_empty() {
+            self.add_error("No text provided. Like this: |footnote, A note|", &mut ctx);
+            return None;
+        }
+
+        let text = ctx
+            .document
+            .translate_inline_text(text.trim(), ctx.origin.expand(&self.name()));
+
+        Some(match ctx.output_format {
+            OutputFormat::Latex => format!("\\footnote{{{}}}", text),
+            OutputFormat::Markdown => format!("[^{}]", ctx.document.add_footnote(text)),
+            _ => {
+                let number = ctx.document.add_footnote(text);
+                format!(
+                    "<sup class=\"footnote-ref\">\
+                    <a id=\"fnref-{0}\" href=\"#fn-{0}\">{0}</a></sup>",
+                    number
+                )
+            }
+        })
+    }
+
+    fn supports_block(&self) -> bool {
+        false
+    }
+
+    fn supports_inline(&self) -> bool {
+        true
+    }
+
+    fn interests(&self) -> Vec<String> {
+        vec![]
+    }
+}
@@ -0,0 +1,47 @@
+use crate::extensions::{Context, Extension};
+use crate::translator::OutputFormat;
+
+/// **Native extension**: the list of the footnotes above
+#[derive(Clone)]
+pub struct Footnotes;
+
+impl Extension for Footnotes {
+    fn name(&self) -> String {
+        "Footnotes".to_string()
+    }
+
+    fn description(&self) -> String {
+        "List the footnotes that have not been listed yet, for example at the end of a section.\n\
+        The rest of the footnotes are listed at the end of the document.\n\
+        LaTeX places footnotes at the bottom of the page instead.\n\
+        \n```\n--- footnotes\n---\n```"
+            .to_string()
+    }
+
+    fn version(&self) -> String {
+        "1".to_string()
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+
+    fn call(&self, ctx: Context) -> Option<String> {
+        match ctx.output_format {
+            OutputFormat::LambdaNote => Some(ctx.to_source("footnotes")),
+            _ => Some(ctx.document.footnote_list()),
+        }
+    }
+
+    fn supports_block(&self) -> bool {
+        true
+    }
+
+    fn supports_inline(&self) -> bool {
+        false
+    }
+
+    fn interests(&self) -> Vec<String> {
+        vec![]
+    }
+}
@@ -21,10 +21,10 @@ impl Extension for Id {
     }
 
     fn call(&self, ctx: Context) -> Option<String> {
-        let text = ctx.arguments.get(0)?;
+        let text = ctx.arguments.first()?;
         // FIME: create and use a translate inline function if
         // this is called inline.
-        Some(ctx.document.translate_no_template(text, "identity"))
+        Some(ctx.document.translate_no_template(text, ctx.origin.expand(&self.name())))
     }
 
     fn supports_block(&self) -> bool {
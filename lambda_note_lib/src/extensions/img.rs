@@ -1,5 +1,5 @@
 use crate::extensions::{Context, Extension, ExtensionVariant};
-use crate::translator::OutputFormat;
+use crate::translator::{LabelKind, OutputFormat};
 
 /// **Native extension**: add an image
 #[derive(Clone)]
@@ -23,7 +23,9 @@ impl Extension for Img {
         ```\n\
         ---- img, filepath [width, label] ----\n\
         alt text\n\
-        ----\n```".to_string()
+        ----\n```\n\
+        Images with a caption or a label are numbered as figures."
+            .to_string()
     }
 
     fn version(&self) -> String {
@@ -35,10 +37,29 @@ impl Extension for Img {
     }
 
     fn call(&self, mut ctx: Context) -> Option<String> {
+        // like in LaTeX, the figures with a caption are numbered
+        let origin = ctx.origin.clone();
+        let label = label(&ctx).filter(|label| ctx.document.check_label(label, &origin));
+        let is_captioned = match ctx.variant {
+            ExtensionVariant::Block => true,
+            ExtensionVariant::Inline => ctx.arguments.get(1).is_some(),
+        };
+        let number = if is_captioned || label.is_some() {
+            let number = ctx.document.next_number(LabelKind::Figure);
+            if let Some(label) = &label {
+                ctx.document
+                    .add_label(label, LabelKind::Figure, &number.to_string(), &origin);
+            }
+            Some(number)
+        } else {
+            None
+        };
+
         match ctx.output_format {
-            OutputFormat::LambdaNote => todo!(),
-            OutputFormat::Html => self.html(&mut ctx),
-            OutputFormat::Latex => self.latex(&mut ctx),
+            OutputFormat::LambdaNote => Some(ctx.to_source("img")),
+            OutputFormat::Html => self.html(&mut ctx, number, label),
+            OutputFormat::Latex => self.latex(&mut ctx, label),
+            OutputFormat::Markdown => self.markdown(&mut ctx),
         }
     }
 
@@ -55,36 +76,60 @@ impl Extension for Img {
     }
 }
 impl Img {
-    fn html(&self, ctx: &mut Context) -> Option<String> {
-        let alt: Option<&String>;
-        let src: Option<&String>;
-
-        match ctx.variant {
-            ExtensionVariant::Block => {
-                alt = ctx.arguments.get(0);
-                src = ctx.arguments.get(1);
-            }
-
-            ExtensionVariant::Inline => {
-                src = ctx.arguments.get(0);
-                alt = ctx.arguments.get(1);
+    fn html(
+        &self,
+        ctx: &mut Context,
+        number: Option<usize>,
+        label: Option<String>,
+    ) -> Option<String> {
+        let (src, alt) = match ctx.variant {
+            ExtensionVariant::Block => (ctx.arguments.get(1), ctx.arguments.first()),
+            ExtensionVariant::Inline => (ctx.arguments.first(), ctx.arguments.get(1)),
+        };
+
+        let src = match src {
+            Some(src) => ctx.document.escape_str(src.trim()),
+            None => {
+                self.add_error("No path to the image was given", ctx);
+                return None;
             }
-        }
-        if src.is_none() {
-            self.add_error("Img: no path to the image was given", ctx);
-            return None;
-        }
-
-        Some(format!(
-            "<img src=\"{filename}\" {alt} style=\"max-width:{width}%\">",
-            filename = src.map_or_else(|| String::from(""), |s| s.to_owned()),
-            width = ctx.arguments.get(2).unwrap_or(&String::from("100")),
-            alt = alt.map_or_else(|| String::from(""), |s| format!("alt=\"{}\"", s)),
-        ))
+        };
+        let alt = alt.map(|alt| ctx.document.escape_str(alt.trim()));
+
+        let img = format!(
+            "<img src=\"{src}\" {alt} style=\"max-width:{width}%\">",
+            src = src,
+            width = ctx.arguments.get(2).map_or("100", |width| width.trim()),
+            alt = alt
+                .as_ref()
+                .map_or_else(String::new, |alt| format!("alt=\"{}\"", alt)),
+        );
+
+        let number = match number {
+            Some(number) => number,
+            None => return Some(img),
+        };
+
+        // numbered images are shown as figures, like in LaTeX. Inline images are in
+        // the middle of a paragraph, which can not contain a <figure>
+        let id = label.map_or_else(String::new, |label| {
+            format!(" id=\"{}\"", ctx.document.escape_str(&label))
+        });
+        let caption = format!("Figure {}: {}", number, alt.unwrap_or_default());
+        Some(match ctx.variant {
+            ExtensionVariant::Block => format!(
+                "<figure{}>{}<figcaption>{}</figcaption></figure>",
+                id, img, caption
+            ),
+            ExtensionVariant::Inline => format!(
+                "<span class=\"figure\"{}>{}<span class=\"figcaption\">{}</span></span>",
+                id, img, caption
+            ),
+        })
     }
 
     // | src, [alt, width, label] |
-    fn latex(&self, ctx: &mut Context) -> Option<String> {
+    fn latex(&self, ctx: &mut Context, label: Option<String>) -> Option<String> {
         ctx.document.import("\\usepackage{graphicx}");
 
         let alt: Option<&String>;
@@ -92,12 +137,12 @@ impl Img {
 
         match ctx.variant {
             ExtensionVariant::Block => {
-                alt = ctx.arguments.get(0);
+                alt = ctx.arguments.first();
                 src = ctx.arguments.get(1);
             }
 
             ExtensionVariant::Inline => {
-                src = ctx.arguments.get(0);
+                src = ctx.arguments.first();
                 alt = ctx.arguments.get(1);
             }
         }
@@ -110,15 +155,53 @@ impl Img {
 \\includegraphics[width={width}\\textwidth]{{{src}}}
 \\end{{figure}}",
             src = src.unwrap_or(&String::from("")),
+            // a label without a caption would refer to the section instead
             caption = alt.map_or_else(
-                || String::from(""),
+                || String::from(if label.is_some() { "\\caption{}" } else { "" }),
                 |text| format!("\\caption{{{}}}", text.trim().replace("\n", r#"\\"#))
             ),
             width = ctx.arguments.get(2).unwrap_or(&String::from("1")),
-            label = ctx.arguments.get(3).map_or_else(
-                || String::from(""),
-                |label| format!("\\label{{{}}}", label.trim())
-            )
+            label = label.map_or_else(|| String::from(""), |label| format!("\\label{{{}}}", label))
         ))
     }
+
+    // ![alt](src), or a html image if a width is given since Markdown lacks one
+    fn markdown(&self, ctx: &mut Context) -> Option<String> {
+        let (src, alt) = match ctx.variant {
+            ExtensionVariant::Block => (ctx.arguments.get(1), ctx.arguments.first()),
+            ExtensionVariant::Inline => (ctx.arguments.first(), ctx.arguments.get(1)),
+        };
+
+        let src = match src {
+            Some(src) => src.trim().to_string(),
+            None => {
+                self.add_error("No path to the image was given", ctx);
+                return None;
+            }
+        };
+        let alt = alt.map_or("", |alt| alt.trim());
+
+        Some(match ctx.arguments.get(2) {
+            Some(width) => format!(
+                "<img src=\"{}\" alt=\"{}\" style=\"max-width:{}%\">",
+                src,
+                alt,
+                width.trim()
+            ),
+            None => format!(
+                "![{}]({})",
+                alt.replace('[', "\\[").replace(']', "\\]"),
+                src
+            ),
+        })
+    }
+}
+
+/// The label is the fourth argument of both variants
+fn label(ctx: &Context) -> Option<String> {
+    ctx.arguments
+        .get(3)
+        .map(|label| label.trim())
+        .filter(|label| !label.is_empty())
+        .map(|label| label.to_string())
 }
@@ -0,0 +1,151 @@
+use crate::extensions::{Context, Extension};
+use crate::{parse_doc, Block, Diagnostic, OriginName};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// **Native extension**: includes another λnote file into the document
+#[derive(Clone)]
+pub struct Include;
+
+impl Extension for Include {
+    fn name(&self) -> String {
+        "Include".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Include another λnote file, relative to the file it is included from.\n\
+        Metadata, aliases and defined extensions are shared with the rest of the document.\n\
+        An optional second argument moves the headings of the file up or down a level.\n\
+        \n```\n--- include, chapters/introduction.ln, 1\n---\n```"
+            .to_string()
+    }
+
+    fn version(&self) -> String {
+        "1".to_string()
+    }
+
+    fn is_safe(&self) -> bool {
+        false
+    }
+
+    fn call(&self, mut ctx: Context) -> Option<String> {
+        // the first argument is the (empty) body of the block
+        let file = match ctx.arguments.get(1).map(|file| file.trim()) {
+            Some(file) if !file.is_empty() => file.to_string(),
+            _ => {
+                self.add_error(
+                    "No file provided. Like this: --- include, chapter.ln",
+                    &mut ctx,
+                );
+                return None;
+            }
+        };
+
+        let offset = match ctx.arguments.get(2).map(|offset| offset.trim()) {
+            None | Some("") => 0,
+            Some(offset) => match offset.parse::<isize>() {
+                Ok(offset) => offset,
+                Err(_) => {
+                    self.add_error(
+                        &format!(
+                            "Expected the heading level offset to be a number, got {}",
+                            offset
+                        ),
+                        &mut ctx,
+                    );
+                    0
+                }
+            },
+        };
+
+        // paths are relative to the file that includes them
+        let including_file = match &ctx.origin.root().name {
+            OriginName::Filename(name) => PathBuf::from(name),
+            OriginName::Expansion(..) => PathBuf::new(),
+        };
+        let path = match including_file.parent() {
+            Some(directory) => directory.join(&file),
+            None => PathBuf::from(&file),
+        };
+
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(error) => {
+                self.add_diagnostic(
+                    Diagnostic::error(&format!("Failed to read {}: {}", path.display(), error))
+                        .with_code("include-failed"),
+                    &mut ctx,
+                );
+                return None;
+            }
+        };
+
+        let target = canonical(&path);
+        let stack = &mut ctx.document.include_stack;
+
+        // the document itself is at the bottom of the stack, so that it can not be included
+        let is_outermost = stack.is_empty();
+        if is_outermost {
+            stack.push((canonical(&including_file), 0));
+        }
+
+        if stack.iter().any(|(file, _)| *file == target) {
+            if is_outermost {
+                stack.clear();
+            }
+            self.add_diagnostic(
+                Diagnostic::error(&format!(
+                    "{} is already being included, including it again would never end",
+                    path.display()
+                ))
+                .with_code("include-cycle"),
+                &mut ctx,
+            );
+            return None;
+        }
+
+        let offset = stack.last().map_or(0, |(_, outer)| *outer) + offset;
+        stack.push((target, offset));
+        if !ctx.document.included_files.contains(&path) {
+            ctx.document.included_files.push(path.clone());
+        }
+
+        let blocks = parse_doc(&source, path.to_string_lossy().as_ref())
+            .into_iter()
+            .map(|block| match block {
+                Block::Heading(text, level, origin) => {
+                    let level = (level as isize + offset).clamp(1, u8::MAX as isize);
+                    Block::Heading(text, level as u8, origin)
+                }
+                block => block,
+            })
+            .collect();
+
+        let output = ctx.document.translate_blocks(blocks);
+
+        let stack = &mut ctx.document.include_stack;
+        stack.pop();
+        if is_outermost {
+            stack.clear();
+        }
+
+        Some(output)
+    }
+
+    fn supports_block(&self) -> bool {
+        true
+    }
+
+    fn supports_inline(&self) -> bool {
+        false
+    }
+
+    fn interests(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// Two paths to the same file are only equal once they are canonical
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
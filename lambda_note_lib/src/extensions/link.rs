@@ -49,10 +49,10 @@ impl Extension for Link {
         match ctx.variant {
             ExtensionVariant::Block => {
                 url = ctx.arguments.get(1);
-                label = ctx.arguments.get(0);
+                label = ctx.arguments.first();
             }
             ExtensionVariant::Inline => {
-                url = ctx.arguments.get(0);
+                url = ctx.arguments.first();
                 label = ctx.arguments.get(1);
             }
         }
@@ -66,22 +66,32 @@ impl Extension for Link {
         let color = ctx.document.metadata.get("link_color").cloned();
 
         match ctx.output_format {
-            OutputFormat::LambdaNote => todo!(),
+            OutputFormat::LambdaNote => Some(ctx.to_source("link")),
             // html output
             OutputFormat::Html => {
                 Some(format!(
                     "<a href={url}{style}>{label}</a>",
                     url = url_text,
                     label = match label {
-                        Some(text) => ctx.document.translate_no_template(text, "Link extension"),
+                        Some(text) => ctx.document.translate_no_template(text, ctx.origin.expand(&self.name())),
                         None => url_text.to_string(),
                     },
                     style = match color {
-                        None => format!(""),
+                        None => String::new(),
                         Some(color) => format!(" style=\"color:{}\"", color)
                     }
                 ))
             }
+            OutputFormat::Markdown => Some(match label {
+                Some(text) => format!(
+                    "[{}]({})",
+                    ctx.document
+                        .translate_no_template(text, ctx.origin.expand(&self.name()))
+                        .trim(),
+                    url_text.trim()
+                ),
+                None => format!("<{}>", url_text.trim()),
+            }),
             // latex output
             OutputFormat::Latex => {
                 ctx.document.import("\\usepackage{hyperref}");
@@ -95,7 +105,7 @@ impl Extension for Link {
                 }
 
                 Some(match label {
-                    Some(text) => format!("\\href{{{}}}{{{}}}", url_text, ctx.document.translate_no_template(text, "Link extension")),
+                    Some(text) => format!("\\href{{{}}}{{{}}}", url_text, ctx.document.translate_no_template(text, ctx.origin.expand(&self.name()))),
                     None => format!("\\url{{{}}}", url_text),
                 })
             }
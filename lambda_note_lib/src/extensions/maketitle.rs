@@ -44,7 +44,8 @@ impl Extension for Maketitle {
         match ctx.output_format {
             OutputFormat::Latex => latex(&mut ctx),
             OutputFormat::Html => html(&mut ctx),
-            _ => panic!("Not implemented yet"),
+            OutputFormat::LambdaNote => Some(ctx.to_source("maketitle")),
+            OutputFormat::Markdown => markdown(&mut ctx),
         }
     }
 
@@ -113,6 +114,11 @@ fn html(ctx: &mut Context) -> Option<String> {
     ))
 }
 
+fn markdown(ctx: &mut Context) -> Option<String> {
+    let (title, author, date) = get_metadata(ctx.document)?;
+    Some(format!("# {}\n\n*{}*\n\n*{}*", title, author, date))
+}
+
 /// Get all the relevent metadata fields, otherwise return None
 fn get_metadata(state: &mut DocumentState) -> Option<(&String, &String, &String)> {
     let title = state.metadata.get("title")?;
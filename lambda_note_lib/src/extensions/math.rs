@@ -1,5 +1,5 @@
 use crate::extensions::{Context, Extension, ExtensionVariant};
-use crate::translator::OutputFormat;
+use crate::translator::{LabelKind, OutputFormat};
 use latex2mathml::{latex_to_mathml, DisplayStyle};
 
 /// **Native extension**: make math equations
@@ -13,7 +13,10 @@ impl Extension for Math {
     }
 
     fn description(&self) -> String {
-        "Format equations and math using LaTeX syntax.".to_string()
+        "Format equations and math using LaTeX syntax.\n\
+        Equation blocks are numbered and can be given a label to refer to.\n\
+        \n```\n--- math, eq:euler\ne^{i\\pi} + 1 = 0\n---\n```"
+            .to_string()
     }
 
     fn version(&self) -> String {
@@ -25,10 +28,36 @@ impl Extension for Math {
     }
 
     fn call(&self, mut ctx: Context) -> Option<String> {
+        // like in LaTeX, every equation block is numbered
+        let equation = match ctx.variant {
+            ExtensionVariant::Block => {
+                let number = ctx.document.next_number(LabelKind::Equation);
+                let origin = ctx.origin.clone();
+                let label = label(&ctx).filter(|label| ctx.document.check_label(label, &origin));
+                if let Some(label) = &label {
+                    let number = number.to_string();
+                    ctx.document
+                        .add_label(label, LabelKind::Equation, &number, &origin);
+                }
+                label.map(|label| (label, number))
+            }
+            ExtensionVariant::Inline => None,
+        };
+
         match ctx.output_format {
-            OutputFormat::Latex => latex(&ctx),
-            OutputFormat::Html => html(&mut ctx),
-            _ => panic!("Not implemented yet"),
+            OutputFormat::Latex => latex(&ctx, equation.map(|(label, _)| label)),
+            OutputFormat::Html => html(&mut ctx).map(|math| match equation {
+                Some((label, number)) => format!(
+                    "<div class=\"equation\" id=\"{}\">\
+                    {}<span class=\"equation-number\">({})</span></div>",
+                    ctx.document.escape_str(&label),
+                    math,
+                    number
+                ),
+                None => math,
+            }),
+            OutputFormat::LambdaNote => Some(ctx.to_source("math")),
+            OutputFormat::Markdown => markdown(&ctx),
         }
     }
 
@@ -48,7 +77,7 @@ impl Extension for Math {
 fn html(ctx: &mut Context) -> Option<String> {
     ctx.document.import(r#"<script id="MathJax-script" async src="https://cdn.jsdelivr.net/npm/mathjax@3/es5/mml-chtml.js"></script>"#);
 
-    let value = ctx.arguments.get(0)?;
+    let value = ctx.arguments.first()?;
     latex_to_mathml(
         value,
         match ctx.variant {
@@ -59,10 +88,33 @@ fn html(ctx: &mut Context) -> Option<String> {
     .ok()
 }
 
-fn latex(ctx: &Context) -> Option<String> {
-    let value = ctx.arguments.get(0).map_or_else(|| "", |content| content);
+/// The label of an equation block is given after the name of the extension
+fn label(ctx: &Context) -> Option<String> {
+    ctx.arguments
+        .get(1)
+        .map(|label| label.trim())
+        .filter(|label| !label.is_empty())
+        .map(|label| label.to_string())
+}
+
+fn latex(ctx: &Context, label: Option<String>) -> Option<String> {
+    let value = ctx.arguments.first().map_or("", |content| content);
+    Some(match ctx.variant {
+        ExtensionVariant::Block => match label {
+            Some(label) => format!(
+                "\\begin{{equation}}\\label{{{}}}\n{}\n\\end{{equation}}",
+                label, value
+            ),
+            None => format!("\\begin{{equation}}\n{}\n\\end{{equation}}", value),
+        },
+        ExtensionVariant::Inline => format!("${}$", value),
+    })
+}
+
+fn markdown(ctx: &Context) -> Option<String> {
+    let value = ctx.arguments.first().map_or("", |content| content.trim());
     Some(match ctx.variant {
-        ExtensionVariant::Block => format!("\\begin{{equation}}\n{}\n\\end{{equation}}", value),
+        ExtensionVariant::Block => format!("$$\n{}\n$$", value),
         ExtensionVariant::Inline => format!("${}$", value),
     })
 }
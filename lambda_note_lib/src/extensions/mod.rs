@@ -11,9 +11,17 @@ mod raw;
 mod alias;
 mod define;
 mod id;
+mod include;
+mod toc;
+mod reference;
+mod cite;
+mod bibliography;
+mod footnote;
+mod footnotes;
 
-use crate::parser::{Origin, OriginName};
-use crate::translator::{DocumentState, OutputFormat};
+use crate::parser::Origin;
+use crate::Diagnostic;
+use crate::translator::{block_extension, inline_extension, DocumentState, OutputFormat};
 use code::Code;
 use conditional::Conditional;
 use hidden::Hidden;
@@ -25,12 +33,20 @@ use calc::Calc;
 use escape::Escape;
 use define::Define;
 use id::Id;
+use include::Include;
+use toc::Toc;
+use reference::Ref;
+use cite::Cite;
+use bibliography::Bibliography;
+use footnote::Footnote;
+use footnotes::Footnotes;
 use raw::Raw;
 use alias::Alias;
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, rc::Rc};
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
 pub enum ExtensionVariant {
     Block,
     Inline,
@@ -63,6 +79,15 @@ impl<'a> Context<'a> {
     pub fn no_arguments(&self) -> bool {
         self.arguments.is_empty()
     }
+
+    /// The expression written as λnote source, used by extensions
+    /// that can not be expanded any further in a λnote document
+    pub fn to_source(&self, name: &str) -> String {
+        match self.variant {
+            ExtensionVariant::Block => block_extension(name, &self.arguments),
+            ExtensionVariant::Inline => inline_extension(name, &self.arguments),
+        }
+    }
 }
 
 pub trait Extension {
@@ -79,36 +104,26 @@ pub trait Extension {
 
     /// Add an error to the current document state
     fn add_error(&self, description: &str, ctx: &mut Context) {
-        let document_name = match &ctx.origin.name {
-            OriginName::Filename(name) => name,
-            _ => "MACRO EXPANSION", // todo, should display the entire expansion
-        };
-
-        ctx.document.errors.push(format!(
-            "Error from {name} expression: {description}.\n\
-                (Line {line_number} of {document_name}",
-            name = self.name(),
-            description = description,
-            line_number = ctx.origin.line_number,
-            document_name = document_name,
-        ));
+        self.add_diagnostic(Diagnostic::error(description), ctx);
     }
 
     /// Add a warning to the current document state
     fn add_warning(&self, description: &str, ctx: &mut Context) {
-        let document_name = match &ctx.origin.name {
-            OriginName::Filename(name) => name,
-            _ => "MACRO EXPANSION", // todo, should display the entire expansion
-        };
+        self.add_diagnostic(Diagnostic::warning(description), ctx);
+    }
+
+    /// Add a diagnostic to the current document state, the origin and
+    /// extension name are filled in from the context unless already set
+    fn add_diagnostic(&self, mut diagnostic: Diagnostic, ctx: &mut Context) {
+        if diagnostic.origin.is_none() {
+            diagnostic.origin = Some(ctx.origin.clone());
+        }
+
+        if diagnostic.extension.is_none() {
+            diagnostic.extension = Some(self.name());
+        }
 
-        ctx.document.warnings.push(format!(
-            "Warning from {name} expression: {description}.\n\
-                (Line {line_number} of {document_name}",
-            name = self.name(),
-            description = description,
-            line_number = ctx.origin.line_number,
-            document_name = document_name,
-        ));
+        ctx.document.add_diagnostic(diagnostic);
     }
 }
 
@@ -128,5 +143,12 @@ pub fn get_native_extensions() -> HashMap<String, Rc<dyn Extension>> {
     map.insert("alias".to_string(), Rc::new(Alias));
     map.insert("define".to_string(), Rc::new(Define));
     map.insert("id".to_string(), Rc::new(Id));
+    map.insert("include".to_string(), Rc::new(Include));
+    map.insert("toc".to_string(), Rc::new(Toc));
+    map.insert("ref".to_string(), Rc::new(Ref));
+    map.insert("cite".to_string(), Rc::new(Cite));
+    map.insert("bibliography".to_string(), Rc::new(Bibliography));
+    map.insert("footnote".to_string(), Rc::new(Footnote));
+    map.insert("footnotes".to_string(), Rc::new(Footnotes));
     map
 }
@@ -0,0 +1,129 @@
+use crate::extensions::{Context, Extension};
+use crate::translator::{DocumentState, Label, OutputFormat};
+use crate::{Diagnostic, Origin};
+
+/// **Native extension**: refer to a labelled figure, equation, table or heading
+#[derive(Clone)]
+pub struct Ref;
+
+impl Extension for Ref {
+    fn name(&self) -> String {
+        "Ref".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Refer to a labelled figure, equation, table or heading by its number.\n\
+        Images and math blocks take a label as an argument, tables take one after\n\
+        the caption and headings get one from a custom id, like `# Results {#results}`.\n\
+        Add `number` to only write the number.\n\
+        \n```\nAs seen in |ref, fig:results| and equation (|ref, eq:euler, number|)\n```"
+            .to_string()
+    }
+
+    fn version(&self) -> String {
+        "1".to_string()
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+
+    fn call(&self, mut ctx: Context) -> Option<String> {
+        let label = match ctx.arguments.first().map(|label| label.trim()) {
+            Some(label) if !label.is_empty() => label.to_string(),
+            _ => {
+                self.add_error("No label provided. Like this: |ref, fig:results|", &mut ctx);
+                return None;
+            }
+        };
+
+        let only_number = match ctx.arguments.get(1).map(|style| style.trim()) {
+            None | Some("") => false,
+            Some("number") => true,
+            Some(style) => {
+                self.add_warning(
+                    &format!("Unknown style {}, expected number", style),
+                    &mut ctx,
+                );
+                false
+            }
+        };
+
+        if ctx.output_format == OutputFormat::LambdaNote {
+            return Some(ctx.to_source("ref"));
+        }
+
+        let origin = ctx.origin.clone();
+        if !ctx.document.check_label(&label, &origin) {
+            return None;
+        }
+
+        if ctx.output_format == OutputFormat::Latex && !only_number {
+            ctx.document.import("\\usepackage{hyperref}");
+        }
+
+        // labels further down in the document are not known yet,
+        // so the reference is made once the whole document is translated
+        let format = ctx.output_format;
+        let origin = ctx.origin.clone();
+        let name = self.name();
+        Some(ctx.document.defer(move |document| {
+            let target = lookup(document, &label, &origin, &name);
+            let text = match (target, only_number) {
+                (Some(target), true) => target.number,
+                (Some(target), false) => format!("{} {}", target.kind.name(), target.number),
+                (None, _) => "??".to_string(),
+            };
+
+            match format {
+                OutputFormat::Html => format!(
+                    "<a class=\"ref\" href=\"#{}\">{}</a>",
+                    document.escape_str(&label),
+                    text
+                ),
+                OutputFormat::Markdown => format!("[{}](#{})", text, label),
+                OutputFormat::Latex if only_number => format!("\\ref{{{}}}", label),
+                _ => format!("\\autoref{{{}}}", label),
+            }
+        }))
+    }
+
+    fn supports_block(&self) -> bool {
+        false
+    }
+
+    fn supports_inline(&self) -> bool {
+        true
+    }
+
+    fn interests(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// Find a label, warns at the reference if there is none or more than one
+fn lookup(document: &mut DocumentState, label: &str, origin: &Origin, name: &str) -> Option<Label> {
+    let target = document.labels.get(label).cloned();
+    match &target {
+        Some(target) if !target.duplicates.is_empty() => document.add_diagnostic(
+            Diagnostic::warning(&format!(
+                "The label {} is given to more than one element, the first one is referred to",
+                label
+            ))
+            .with_origin(origin)
+            .with_extension(name)
+            .with_code("duplicate-label"),
+        ),
+        None => document.add_diagnostic(
+            Diagnostic::warning(&format!("The label {} is not defined", label))
+                .with_origin(origin)
+                .with_extension(name)
+                .with_code("undefined-label")
+                .with_help(
+                    "labels are given to images, math blocks, tables and headings with a custom id",
+                ),
+        ),
+        Some(_) => (),
+    }
+    target
+}
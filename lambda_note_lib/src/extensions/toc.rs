@@ -0,0 +1,184 @@
+use crate::extensions::{Context, Extension, ExtensionVariant};
+use crate::translator::{
+    numbered_heading, DocumentState, Heading, Markdown, OutputFormat, Translator,
+};
+use std::collections::HashMap;
+
+/// **Native extension**: a table of contents with the headings of the document
+#[derive(Clone)]
+pub struct Toc;
+
+impl Extension for Toc {
+    fn name(&self) -> String {
+        "Toc".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Add a table of contents that links to the headings of the document.\n\
+        An optional argument limits how many levels of headings that are listed,\n\
+        counted from the highest level of heading in the document.\n\
+        \n```\n--- toc, 2\n---\n```"
+            .to_string()
+    }
+
+    fn version(&self) -> String {
+        "1".to_string()
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+
+    fn call(&self, mut ctx: Context) -> Option<String> {
+        // the first argument of a block is its (empty) body
+        let depth = match ctx.variant {
+            ExtensionVariant::Block => ctx.arguments.get(1),
+            ExtensionVariant::Inline => ctx.arguments.first(),
+        };
+
+        let depth = match depth.map(|depth| depth.trim()) {
+            None | Some("") => None,
+            Some(depth) => match depth.parse::<u8>() {
+                Ok(depth) if depth > 0 => Some(depth),
+                _ => {
+                    self.add_error(
+                        &format!("Expected the depth to be a positive number, got {}", depth),
+                        &mut ctx,
+                    );
+                    return None;
+                }
+            },
+        };
+
+        // the headings further down are not known yet, so the
+        // table of contents is made once the whole document is translated
+        let format = ctx.output_format;
+        match format {
+            OutputFormat::Html => Some(ctx.document.defer(move |document| {
+                let headings = listed_headings(document, depth);
+                if headings.is_empty() {
+                    return String::new();
+                }
+                format!(
+                    "<nav class=\"toc\">\n{}</nav>",
+                    html_list(document, &headings)
+                )
+            })),
+            OutputFormat::Markdown => Some(ctx.document.defer(move |document| {
+                markdown_list(&listed_headings(document, depth), &github_anchors(document))
+            })),
+            // the levels of LaTeX headings are the same as in λnote, \section is 1
+            OutputFormat::Latex => Some(ctx.document.defer(move |document| match depth {
+                Some(depth) => format!(
+                    "\\setcounter{{tocdepth}}{{{}}}\n\\tableofcontents",
+                    max_level(document, depth)
+                ),
+                None => "\\tableofcontents".to_string(),
+            })),
+            OutputFormat::LambdaNote => Some(ctx.to_source("toc")),
+        }
+    }
+
+    fn supports_block(&self) -> bool {
+        true
+    }
+
+    fn supports_inline(&self) -> bool {
+        true
+    }
+
+    fn interests(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// The deepest level that is listed, `depth` levels from the top level of the document
+fn max_level(document: &DocumentState, depth: u8) -> u8 {
+    let top = document.headings.iter().map(|h| h.level).min().unwrap_or(1);
+    top.saturating_add(depth - 1)
+}
+
+/// The headings that are at most `depth` levels below the top level of the document
+fn listed_headings(document: &DocumentState, depth: Option<u8>) -> Vec<Heading> {
+    let max_level = depth.map_or(u8::MAX, |depth| max_level(document, depth));
+    document
+        .headings
+        .iter()
+        .filter(|heading| heading.level <= max_level)
+        .cloned()
+        .collect()
+}
+
+/// Split headings into the first heading of each branch and the headings below it
+fn branches(headings: &[Heading]) -> Vec<(&Heading, &[Heading])> {
+    let mut branches = vec![];
+    let mut rest = headings;
+    while let Some((heading, below)) = rest.split_first() {
+        let children = below
+            .iter()
+            .take_while(|child| child.level > heading.level)
+            .count();
+        branches.push((heading, &below[..children]));
+        rest = &below[children..];
+    }
+    branches
+}
+
+fn html_list(document: &DocumentState, headings: &[Heading]) -> String {
+    let mut output = String::from("<ul>\n");
+    for (heading, children) in branches(headings) {
+        output.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a>",
+            document.escape_str(&heading.id),
+            numbered_heading(document, heading, &document.escape_str(&heading.text))
+        ));
+        if !children.is_empty() {
+            output.push('\n');
+            output.push_str(&html_list(document, children));
+        }
+        output.push_str("</li>\n");
+    }
+    output.push_str("</ul>\n");
+    output
+}
+
+fn markdown_list(headings: &[Heading], anchors: &HashMap<String, String>) -> String {
+    let mut output = String::new();
+    for (heading, children) in branches(headings) {
+        output.push_str(&format!(
+            "- [{}](#{})\n",
+            Markdown.escape_str(&heading.text),
+            anchors[&heading.id]
+        ));
+        for line in markdown_list(children, anchors).lines() {
+            output.push_str(&format!("  {}\n", line));
+        }
+    }
+    output
+}
+
+/// The anchors that GitHub gives the headings of a Markdown document, by the id of
+/// each heading. Custom ids are not kept in Markdown, so they can not be linked to.
+fn github_anchors(document: &DocumentState) -> HashMap<String, String> {
+    let mut anchors = HashMap::new();
+    let mut taken: HashMap<String, usize> = HashMap::new();
+    for heading in &document.headings {
+        let anchor: String = heading
+            .text
+            .to_lowercase()
+            .chars()
+            .filter(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+            .map(|c| if c == ' ' { '-' } else { c })
+            .collect();
+
+        // repeated anchors are numbered from the second one
+        let count = taken.entry(anchor.clone()).or_insert(0);
+        let unique = match *count {
+            0 => anchor,
+            n => format!("{}-{}", anchor, n),
+        };
+        *count += 1;
+        anchors.entry(heading.id.clone()).or_insert(unique);
+    }
+    anchors
+}
@@ -0,0 +1,569 @@
+use pulldown_cmark::{
+    Alignment as MarkdownAlignment, CodeBlockKind, Event, LinkType, Options, Parser,
+    Tag as MarkdownTag,
+};
+use std::ops::Range;
+
+use crate::translator::{first_difference, inlines_to_string, print_document};
+use crate::{
+    parse_doc, Alignment, Block, Cell, Diagnostic, EscapeChar, Inline, List, ListItem, ListKind,
+    Origin, Position, Span, Table, Tag,
+};
+
+/// Convert a CommonMark (and GitHub flavored) Markdown document into λnote.
+/// Returns the blocks of the new document, which can be printed using
+/// `print_document`, together with warnings about every construct
+/// that does not have a λnote equivalent.
+pub fn import_markdown(source: &str, doc_name: &str) -> (Vec<Block>, Vec<Diagnostic>) {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_MATH
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS;
+
+    let mut importer = Importer {
+        events: Parser::new_ext(source, options)
+            .into_offset_iter()
+            .collect(),
+        index: 0,
+        source,
+        doc_name,
+        line_offsets: std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect(),
+        diagnostics: vec![],
+    };
+
+    let blocks = importer.blocks();
+
+    // make sure that the λnote source says the same thing as the blocks
+    let reparsed = parse_doc(&print_document(&blocks), doc_name);
+    if let Some(block) = first_difference(&blocks, &reparsed).and_then(|i| blocks.get(i)) {
+        let origin = block.get_origin().clone();
+        importer.warn(
+            "This part could not be converted exactly, please check the result",
+            &origin,
+        );
+    }
+
+    (blocks, importer.diagnostics)
+}
+
+struct Importer<'a> {
+    events: Vec<(Event<'a>, Range<usize>)>,
+    index: usize,
+    source: &'a str,
+    doc_name: &'a str,
+    // the byte offset where each line of the source starts
+    line_offsets: Vec<usize>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Importer<'a> {
+    fn next(&mut self) -> Option<(Event<'a>, Range<usize>)> {
+        let event = self.events.get(self.index).cloned();
+        self.index += 1;
+        event
+    }
+
+    fn peek(&self) -> Option<&Event<'a>> {
+        self.events.get(self.index).map(|(event, _)| event)
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let line = self.line_offsets.partition_point(|start| *start <= offset);
+        let line_start = self.line_offsets[line - 1];
+        let column = self.source[line_start..offset].chars().count() + 1;
+        Position::new(offset, line, column)
+    }
+
+    fn origin(&self, range: &Range<usize>) -> Origin {
+        // leave out the trailing newline that most blocks include
+        let text = &self.source[range.clone()];
+        let end = range.start + text.trim_end().len();
+        let span = Span::new(self.position(range.start), self.position(end));
+        Origin::from_span(span, self.doc_name)
+    }
+
+    fn warn(&mut self, message: &str, origin: &Origin) {
+        self.diagnostics.push(
+            Diagnostic::warning(message)
+                .with_origin(origin)
+                .with_code("unsupported-markdown"),
+        );
+    }
+
+    /// Convert the blocks up until the end of the current container
+    fn blocks(&mut self) -> Vec<Block> {
+        let mut blocks = vec![];
+
+        while let Some(event) = self.peek() {
+            if let Event::End(_) = event {
+                break;
+            }
+
+            let (event, range) = self.next().unwrap();
+            let origin = self.origin(&range);
+
+            match event {
+                Event::Start(MarkdownTag::Paragraph) => {
+                    let text = self.inlines();
+                    match text.as_slice() {
+                        // a paragraph with nothing but an equation is a math block
+                        [Inline::Extension(name, arguments, _)]
+                            if name == "math" && self.source[range].trim().starts_with("$$") =>
+                        {
+                            blocks.push(Block::Extension(
+                                "math".into(),
+                                vec![arguments[0].trim().to_string()],
+                                origin,
+                            ))
+                        }
+                        [] => (),
+                        _ => blocks.push(Block::Paragraph(text, origin)),
+                    }
+                }
+                Event::Start(MarkdownTag::Heading { level, .. }) => {
+                    let text = self.inlines();
+                    blocks.push(Block::Heading(text, level as u8, origin));
+                }
+                Event::Start(MarkdownTag::List(start)) => {
+                    let list = self.list(start);
+                    blocks.push(Block::List(list, origin));
+                }
+                Event::Start(MarkdownTag::Table(alignments)) => {
+                    let table = self.table(&alignments);
+                    blocks.push(Block::Table(table, origin));
+                }
+                Event::Start(MarkdownTag::CodeBlock(kind)) => {
+                    let code = self.text();
+                    let mut arguments = vec![code.trim_end_matches('\n').to_string()];
+                    if let CodeBlockKind::Fenced(info) = kind {
+                        if let Some(language) = info.split_whitespace().next() {
+                            arguments.push(language.to_string());
+                        }
+                    }
+                    blocks.push(Block::Extension("code".into(), arguments, origin));
+                }
+                Event::Start(MarkdownTag::HtmlBlock) => {
+                    self.warn(
+                        "HTML is kept as a raw block, it will only work in HTML output",
+                        &origin,
+                    );
+                    let html = self.text();
+                    blocks.push(Block::Extension(
+                        "raw".into(),
+                        vec![html.trim_end().to_string()],
+                        origin,
+                    ));
+                }
+                Event::Start(MarkdownTag::MetadataBlock(_)) => {
+                    let metadata = self.text();
+                    blocks.append(&mut self.metadata(&metadata, &origin));
+                }
+                Event::Start(MarkdownTag::BlockQuote(_)) => {
+                    self.warn(
+                        "λnote has no block quotes, the quote is imported as normal text",
+                        &origin,
+                    );
+                    blocks.append(&mut self.blocks());
+                    self.next();
+                }
+                Event::Start(MarkdownTag::FootnoteDefinition(label)) => {
+                    self.warn(
+                        &format!(
+                            "Footnotes are not converted, footnote {} is imported as normal text",
+                            label
+                        ),
+                        &origin,
+                    );
+                    blocks.append(&mut self.blocks());
+                    self.next();
+                }
+                Event::Rule => blocks.push(Block::Divider(origin)),
+                Event::Start(_) => {
+                    self.warn("This Markdown construct is not supported", &origin);
+                    blocks.append(&mut self.blocks());
+                    self.next();
+                }
+                // text outside of a paragraph, like in tight list items
+                event => {
+                    let mut text = InlineBuilder::new();
+                    self.inline(event, &origin, &mut text);
+                    let text = text.finish();
+                    if !text.is_empty() {
+                        blocks.push(Block::Paragraph(text, origin));
+                    }
+                }
+            }
+        }
+
+        blocks
+    }
+
+    /// Convert the inline elements up until (and including) the end of the current tag
+    fn inlines(&mut self) -> Vec<Inline> {
+        let mut text = InlineBuilder::new();
+
+        while let Some((event, range)) = self.next() {
+            if let Event::End(_) = event {
+                break;
+            }
+            let origin = self.origin(&range);
+            self.inline(event, &origin, &mut text);
+        }
+
+        text.finish()
+    }
+
+    fn inline(&mut self, event: Event, origin: &Origin, text: &mut InlineBuilder) {
+        match event {
+            Event::Text(content) => text.push_text(&content),
+            Event::Code(code) => text.push(extension("code", &[&code])),
+            Event::InlineMath(math) | Event::DisplayMath(math) => {
+                text.push(extension("math", &[&math]))
+            }
+            Event::SoftBreak => text.push_text(" "),
+            Event::HardBreak => {
+                self.warn(
+                    "λnote has no line breaks, the lines are joined together",
+                    origin,
+                );
+                text.push_text(" ");
+            }
+            Event::Start(MarkdownTag::Emphasis) => self.styled(Tag::Italic, text),
+            Event::Start(MarkdownTag::Strong) => self.styled(Tag::Bold, text),
+            Event::Start(MarkdownTag::Strikethrough) => self.styled(Tag::Strikethrough, text),
+            Event::Start(MarkdownTag::Link {
+                link_type,
+                dest_url,
+                ..
+            }) => {
+                let label = inlines_to_string(&self.inlines());
+                let is_autolink = matches!(link_type, LinkType::Autolink | LinkType::Email);
+                if is_autolink || label.is_empty() || label == *dest_url {
+                    text.push(extension("link", &[&dest_url]));
+                } else {
+                    text.push(extension("link", &[&dest_url, &label]));
+                }
+            }
+            Event::Start(MarkdownTag::Image { dest_url, .. }) => {
+                // the alternative text can not contain any styling
+                let alt = self.text();
+                if alt.is_empty() {
+                    text.push(extension("img", &[&dest_url]));
+                } else {
+                    text.push(extension("img", &[&dest_url, &alt]));
+                }
+            }
+            Event::InlineHtml(html) | Event::Html(html) => {
+                self.warn(
+                    "HTML is kept as a raw expression, it will only work in HTML output",
+                    origin,
+                );
+                text.push(extension("raw", &[&html]));
+            }
+            Event::FootnoteReference(label) => {
+                self.warn("Footnotes are not converted, use |footnote, ...| instead", origin);
+                text.push_text(&format!("[^{}]", label));
+            }
+            Event::TaskListMarker(checked) => {
+                self.warn("λnote has no task lists", origin);
+                text.push_text(if checked { "[x] " } else { "[ ] " });
+            }
+            Event::Start(_) => {
+                self.warn("This Markdown construct is not supported", origin);
+                for inline in self.inlines() {
+                    text.push(inline);
+                }
+            }
+            Event::End(_) | Event::Rule => (),
+        }
+    }
+
+    fn styled(&mut self, tag: Tag, text: &mut InlineBuilder) {
+        text.push(Inline::Begin(tag.clone(), Span::default()));
+        for inline in self.inlines() {
+            text.push(inline);
+        }
+        text.push(Inline::End(tag, Span::default()));
+    }
+
+    /// The plain text up until the end of the current tag,
+    /// e.g. the contents of a code block
+    fn text(&mut self) -> String {
+        let mut result = String::new();
+        let mut depth = 0;
+
+        while let Some((event, _)) = self.next() {
+            match event {
+                Event::Start(_) => depth += 1,
+                Event::End(_) if depth == 0 => break,
+                Event::End(_) => depth -= 1,
+                Event::Text(text)
+                | Event::Code(text)
+                | Event::Html(text)
+                | Event::InlineHtml(text)
+                | Event::InlineMath(text)
+                | Event::DisplayMath(text) => result.push_str(&text),
+                Event::SoftBreak | Event::HardBreak => result.push(' '),
+                _ => (),
+            }
+        }
+
+        result
+    }
+
+    fn list(&mut self, start: Option<u64>) -> List {
+        let kind = match start {
+            Some(_) => ListKind::Ordered,
+            None => ListKind::Unordered,
+        };
+        let mut items = vec![];
+
+        while let Some((event, range)) = self.next() {
+            match event {
+                Event::Start(MarkdownTag::Item) => items.push(self.item()),
+                Event::End(_) => break,
+                _ => {
+                    let origin = self.origin(&range);
+                    self.warn("Unexpected content in a list", &origin);
+                }
+            }
+        }
+
+        List {
+            kind,
+            start: start.map_or(1, |start| start as usize),
+            items,
+        }
+    }
+
+    /// List items can only contain text and nested lists in λnote
+    fn item(&mut self) -> ListItem {
+        let mut text = InlineBuilder::new();
+        let mut children = vec![];
+
+        while let Some((event, range)) = self.next() {
+            let origin = self.origin(&range);
+            match event {
+                Event::End(_) => break,
+                Event::Start(MarkdownTag::List(start)) => children.push(self.list(start)),
+                Event::Start(MarkdownTag::Paragraph) => {
+                    if !text.is_empty() {
+                        self.warn(
+                            "λnote list items can only have one paragraph, they are joined",
+                            &origin,
+                        );
+                        text.push_text(" ");
+                    }
+                    for inline in self.inlines() {
+                        text.push(inline);
+                    }
+                }
+                Event::Start(MarkdownTag::Heading { .. })
+                | Event::Start(MarkdownTag::CodeBlock(_))
+                | Event::Start(MarkdownTag::BlockQuote(_))
+                | Event::Start(MarkdownTag::HtmlBlock)
+                | Event::Start(MarkdownTag::Table(_)) => {
+                    self.warn(
+                        "λnote list items can only contain text and lists, this was left out",
+                        &origin,
+                    );
+                    self.index -= 1;
+                    self.skip();
+                }
+                event => self.inline(event, &origin, &mut text),
+            }
+        }
+
+        ListItem {
+            text: text.finish(),
+            children,
+        }
+    }
+
+    /// Skip the next event and everything inside of it
+    fn skip(&mut self) {
+        let mut depth = 0;
+        while let Some((event, _)) = self.next() {
+            match event {
+                Event::Start(_) => depth += 1,
+                Event::End(_) => depth -= 1,
+                _ => (),
+            }
+            if depth == 0 {
+                break;
+            }
+        }
+    }
+
+    fn table(&mut self, alignments: &[MarkdownAlignment]) -> Table {
+        let mut header = None;
+        let mut rows = vec![];
+
+        while let Some((event, _)) = self.next() {
+            match event {
+                Event::Start(MarkdownTag::TableHead) => header = Some(self.row()),
+                Event::Start(MarkdownTag::TableRow) => rows.push(self.row()),
+                _ => break,
+            }
+        }
+
+        Table {
+            header,
+            rows,
+            alignments: alignments
+                .iter()
+                .map(|alignment| match alignment {
+                    MarkdownAlignment::None => Alignment::Default,
+                    MarkdownAlignment::Left => Alignment::Left,
+                    MarkdownAlignment::Center => Alignment::Center,
+                    MarkdownAlignment::Right => Alignment::Right,
+                })
+                .collect(),
+            caption: None,
+            label: None,
+        }
+    }
+
+    fn row(&mut self) -> Vec<Cell> {
+        let mut cells = vec![];
+        while let Some((event, _)) = self.next() {
+            match event {
+                Event::Start(MarkdownTag::TableCell) => cells.push(self.inlines()),
+                _ => break,
+            }
+        }
+        cells
+    }
+
+    /// Front matter with simple `key: value` pairs are turned into metadata fields
+    fn metadata(&mut self, text: &str, origin: &Origin) -> Vec<Block> {
+        let mut blocks = vec![];
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let field = line.split_once(':').and_then(|(key, value)| {
+                let key = key.trim().replace(|c: char| !c.is_alphanumeric(), "_");
+                let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+                (!key.is_empty() && !value.is_empty()).then(|| (key, value.to_string()))
+            });
+
+            match field {
+                Some((key, value)) => blocks.push(Block::Metadata(key, value, origin.clone())),
+                None => self.warn(
+                    &format!(
+                        "Only simple metadata fields are supported, skipped: {}",
+                        line
+                    ),
+                    origin,
+                ),
+            }
+        }
+        blocks
+    }
+}
+
+/// An inline extension with the arguments written as in `|name, a, b|`
+fn extension(name: &str, arguments: &[&str]) -> Inline {
+    Inline::Extension(
+        name.to_string(),
+        arguments
+            .iter()
+            .map(|argument| format!(" {}", argument))
+            .collect(),
+        Span::default(),
+    )
+}
+
+/// Collects inline elements, text is buffered and escaped so
+/// that it will not be mistaken for λnote syntax
+struct InlineBuilder {
+    result: Vec<Inline>,
+    text: String,
+}
+
+impl InlineBuilder {
+    fn new() -> Self {
+        InlineBuilder {
+            result: vec![],
+            text: String::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.result.is_empty() && self.text.is_empty()
+    }
+
+    fn push_text(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+
+    fn push(&mut self, inline: Inline) {
+        match inline {
+            Inline::Text(text, _) => self.push_text(&text),
+            inline => {
+                self.flush();
+                self.result.push(inline);
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<Inline> {
+        self.flush();
+        self.result
+    }
+
+    /// Push the buffered text. Backslashes and bars are always escaped, while
+    /// the characters used for styling are escaped when they are next to each
+    /// other or at the edge of the text (where they could be next to a tag).
+    fn flush(&mut self) {
+        let chars: Vec<char> = self.text.chars().collect();
+        let mut text = String::new();
+
+        for (i, c) in chars.iter().enumerate() {
+            let escaped = match c {
+                '\\' => Some(EscapeChar::BackSlash),
+                '|' => Some(EscapeChar::Bar),
+                '*' | '/' | '=' | '_' | '^' | '~' => {
+                    let previous = i.checked_sub(1).and_then(|i| chars.get(i));
+                    let next = chars.get(i + 1);
+                    if previous.is_none_or(|p| p == c) || next.is_none_or(|n| n == c) {
+                        styling_escape(*c)
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            };
+
+            match escaped {
+                Some(escaped) => {
+                    if !text.is_empty() {
+                        self.result
+                            .push(Inline::Text(std::mem::take(&mut text), Span::default()));
+                    }
+                    self.result.push(Inline::Escaped(escaped, Span::default()));
+                }
+                None => text.push(*c),
+            }
+        }
+
+        if !text.is_empty() {
+            self.result.push(Inline::Text(text, Span::default()));
+        }
+        self.text.clear();
+    }
+}
+
+fn styling_escape(c: char) -> Option<EscapeChar> {
+    match c {
+        '*' => Some(EscapeChar::Asterisk),
+        '/' => Some(EscapeChar::ForwardSlash),
+        '=' => Some(EscapeChar::Equal),
+        '_' => Some(EscapeChar::Underscore),
+        '^' => Some(EscapeChar::Caret),
+        '~' => Some(EscapeChar::Tilde),
+        _ => None,
+    }
+}
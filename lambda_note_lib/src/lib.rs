@@ -12,48 +12,95 @@
 //! let result = document.translate("# Hello\n \\lambdanote!", "test");
 //! ```
 
+mod bibtex;
+mod cache;
+mod diagnostic;
 mod extensions;
+mod import;
 mod parser;
 mod translator;
 
-pub use parser::{parse_doc, Block, EscapeChar, Inline, Origin, Tag};
-pub use translator::{DocumentState, Html, Latex, OutputFormat, Translator, WebPreview, HtmlTemplate};
+pub use diagnostic::{Diagnostic, Severity};
+pub use extensions::{Context, Extension, ExtensionVariant};
+pub use import::import_markdown;
+pub use parser::{
+    inline::parse_inline, parse_doc, Alignment, Block, Cell, EscapeChar, Inline, List, ListItem,
+    ListKind, Origin, OriginName, Position, Span, Table, Tag,
+};
+pub use translator::{
+    format_document, print_document, DocumentState, Heading, Html, HtmlTemplate, Label, LabelKind,
+    LambdaNote, Latex, LatexTemplate, Markdown, OutputFormat, TemplateError, Translator, WebPreview,
+};
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// The span of a byte range in a source text
+    fn span(source: &str, start: usize, end: usize) -> Span {
+        let position = |offset: usize| {
+            let before = &source[..offset];
+            let line = before.matches('\n').count() + 1;
+            let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+            Position::new(offset, line, column)
+        };
+        Span::new(position(start), position(end))
+    }
+
+    /// Write a foreign extension to `<name>.sh`, in a directory of its own for the test.
+    /// The script starts with its info response in `$info`, and the functions `id` and
+    /// `argument` that read the id and first argument of a request.
+    #[cfg(not(target_os = "windows"))]
+    fn extension_script(test: &str, name: &str, body: &str) -> std::path::PathBuf {
+        let directory = std::env::temp_dir()
+            .join(format!("lambda_note_{}_{}", test, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let header = format!(
+            r#"info='"name":"{}","version":"1","description":"",'
+info="$info"'"supportedFormats":["html"],"blockSupport":false,"inlineSupport":true'
+id() {{ printf '%s' "$1" | sed 's/.*"id":\([0-9]*\).*/\1/'; }}
+argument() {{ printf '%s' "$1" | sed 's/.*"arguments":\["\([^"]*\)".*/\1/'; }}
+"#,
+            name
+        );
+        let path = directory.join(format!("{}.sh", name));
+        std::fs::write(&path, header + body).unwrap();
+        path
+    }
+
     #[test]
     fn escape_chars() {
-        let dashes = parse_doc(r#"\endash\emdash"#, "test");
+        let source = r#"\endash\emdash"#;
+        let dashes = parse_doc(source, "test");
         assert_eq!(
             dashes[0],
             Block::Paragraph(
                 vec![
-                    Inline::Escaped(EscapeChar::EnDash),
-                    Inline::Escaped(EscapeChar::EmDash)
+                    Inline::Escaped(EscapeChar::EnDash, span(source, 0, 7)),
+                    Inline::Escaped(EscapeChar::EmDash, span(source, 7, 14))
                 ],
-                Origin::new(1, "test")
+                Origin::from_span(span(source, 0, 14), "test")
             ),
             "Testing en dashes and em dashes"
         );
 
-        let tag_symbols = parse_doc(r#"\*\^\_\/\\\=\~\|\:"#, "test");
+        let source = r#"\*\^\_\/\\\=\~\|\:"#;
+        let tag_symbols = parse_doc(source, "test");
         assert_eq!(
             tag_symbols[0],
             Block::Paragraph(
                 vec![
-                    Inline::Escaped(EscapeChar::Asterisk),
-                    Inline::Escaped(EscapeChar::Caret),
-                    Inline::Escaped(EscapeChar::Underscore),
-                    Inline::Escaped(EscapeChar::ForwardSlash),
-                    Inline::Escaped(EscapeChar::BackSlash),
-                    Inline::Escaped(EscapeChar::Equal),
-                    Inline::Escaped(EscapeChar::Tilde),
-                    Inline::Escaped(EscapeChar::Bar),
-                    Inline::Escaped(EscapeChar::Colon)
+                    Inline::Escaped(EscapeChar::Asterisk, span(source, 0, 2)),
+                    Inline::Escaped(EscapeChar::Caret, span(source, 2, 4)),
+                    Inline::Escaped(EscapeChar::Underscore, span(source, 4, 6)),
+                    Inline::Escaped(EscapeChar::ForwardSlash, span(source, 6, 8)),
+                    Inline::Escaped(EscapeChar::BackSlash, span(source, 8, 10)),
+                    Inline::Escaped(EscapeChar::Equal, span(source, 10, 12)),
+                    Inline::Escaped(EscapeChar::Tilde, span(source, 12, 14)),
+                    Inline::Escaped(EscapeChar::Bar, span(source, 14, 16)),
+                    Inline::Escaped(EscapeChar::Colon, span(source, 16, 18))
                 ],
-                Origin::new(1, "test")
+                Origin::from_span(span(source, 0, 18), "test")
             ),
             "Testing tag symbols"
         );
@@ -77,4 +124,1032 @@ mod tests {
             "Testing greek letters"
         );
     }
+
+    #[test]
+    fn lists() {
+        let source = "- first\n- **second**\n  continued\n    1. one\n    2. two\n- third";
+        let list = parse_doc(source, "test");
+
+        assert_eq!(
+            list,
+            vec![Block::List(
+                List {
+                    kind: ListKind::Unordered,
+                    start: 1,
+                    items: vec![
+                        ListItem {
+                            text: vec![Inline::Text("first".to_string(), span(source, 2, 7))],
+                            children: vec![],
+                        },
+                        ListItem {
+                            text: vec![
+                                Inline::Begin(Tag::Bold, span(source, 10, 12)),
+                                Inline::Text("second".to_string(), span(source, 12, 18)),
+                                Inline::End(Tag::Bold, span(source, 18, 20)),
+                                Inline::Text(" continued".to_string(), span(source, 20, 32)),
+                            ],
+                            children: vec![List {
+                                kind: ListKind::Ordered,
+                                start: 1,
+                                items: vec![
+                                    ListItem {
+                                        text: vec![Inline::Text(
+                                            "one".to_string(),
+                                            span(source, 40, 43)
+                                        )],
+                                        children: vec![],
+                                    },
+                                    ListItem {
+                                        text: vec![Inline::Text(
+                                            "two".to_string(),
+                                            span(source, 51, 54)
+                                        )],
+                                        children: vec![],
+                                    },
+                                ],
+                            }],
+                        },
+                        ListItem {
+                            text: vec![Inline::Text("third".to_string(), span(source, 57, 62))],
+                            children: vec![],
+                        },
+                    ],
+                },
+                Origin::from_span(span(source, 0, 62), "test")
+            )],
+            "Testing nested lists with continuation lines"
+        );
+
+        let blocks = parse_doc("- a\n- b\n1. c\n\nparagraph", "test");
+        assert_eq!(
+            blocks.len(),
+            3,
+            "Testing that lists end on new kinds and blank lines"
+        );
+
+        let mut document = DocumentState::new(Html);
+        assert_eq!(
+            document.translate_no_template("1. a\n   - b", "test"),
+            "<ol>\n<li>a\n<ul>\n<li>b</li>\n</ul></li>\n</ol>\n",
+            "Testing html output of nested lists"
+        );
+
+        let source = "3. a\n4. b\n   1. c\n   - d\n     7. e";
+        let mut document = DocumentState::new(Html);
+        assert_eq!(
+            document.translate_no_template(source, "test"),
+            "<ol start=\"3\">\n<li>a</li>\n<li>b\n<ol>\n<li>c</li>\n</ol>\n<ul>\n<li>d\n\
+            <ol start=\"7\">\n<li>e</li>\n</ol></li>\n</ul></li>\n</ol>\n",
+            "Testing html output of lists that do not start at 1"
+        );
+        let mut document = DocumentState::new(Latex);
+        let output = document.translate_no_template(source, "test");
+        assert!(
+            output.starts_with("\\begin{enumerate}\n\\setcounter{enumi}{2}\n\\item a\n")
+                && output.contains("\\begin{enumerate}\n\\setcounter{enumii}{6}\n\\item e"),
+            "Testing LaTeX output of lists that do not start at 1, got {:?}",
+            output
+        );
+        let mut document = DocumentState::new(Markdown);
+        assert!(
+            document.translate_no_template(source, "test").starts_with("3. a\n4. b\n   1. c"),
+            "Testing Markdown output of lists that do not start at 1"
+        );
+    }
+
+    #[test]
+    fn tables() {
+        let source = "| a | **b** |\n|:--|--:|\n| |math, x| | y |\n| z |\n: Caption {#tab}";
+        let blocks = parse_doc(source, "test");
+
+        assert_eq!(
+            blocks,
+            vec![Block::Table(
+                Table {
+                    header: Some(vec![
+                        vec![Inline::Text("a".to_string(), span(source, 2, 3))],
+                        vec![
+                            Inline::Begin(Tag::Bold, span(source, 6, 8)),
+                            Inline::Text("b".to_string(), span(source, 8, 9)),
+                            Inline::End(Tag::Bold, span(source, 9, 11))
+                        ],
+                    ]),
+                    rows: vec![
+                        vec![
+                            vec![Inline::Extension(
+                                "math".to_string(),
+                                vec![" x".to_string()],
+                                span(source, 26, 35)
+                            )],
+                            vec![Inline::Text("y".to_string(), span(source, 38, 39))],
+                        ],
+                        vec![
+                            vec![Inline::Text("z".to_string(), span(source, 44, 45))],
+                            vec![]
+                        ],
+                    ],
+                    alignments: vec![Alignment::Left, Alignment::Right],
+                    caption: Some(vec![Inline::Text(
+                        "Caption".to_string(),
+                        span(source, 50, 57)
+                    )]),
+                    label: Some("tab".to_string()),
+                },
+                Origin::from_span(span(source, 0, 64), "test")
+            )],
+            "Testing tables with headers, alignment, captions and labels"
+        );
+
+        let blocks = parse_doc("|img, cat.png|\n|---|", "test");
+        assert!(
+            matches!(
+                blocks[0],
+                Block::Table(
+                    Table {
+                        header: Some(_),
+                        ..
+                    },
+                    _
+                )
+            ),
+            "Testing that a header row needs a separator row"
+        );
+
+        let blocks = parse_doc("|img, cat.png|\n|maketitle|", "test");
+        assert!(
+            matches!(blocks[0], Block::Paragraph(_, _)),
+            "Testing that inline extensions are not mistaken for tables"
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let source =
+            "  ## A **title**\n\nSome text\r\nwith |math, x| in λ |it|\n\n--- code\nfoo\n---";
+        let blocks = parse_doc(source, "test");
+
+        assert_eq!(
+            blocks[0].get_span(),
+            span(source, 2, 16),
+            "Testing that block spans exclude indentation"
+        );
+
+        assert_eq!(
+            blocks[0],
+            Block::Heading(
+                vec![
+                    Inline::Text("A ".to_string(), span(source, 5, 7)),
+                    Inline::Begin(Tag::Bold, span(source, 7, 9)),
+                    Inline::Text("title".to_string(), span(source, 9, 14)),
+                    Inline::End(Tag::Bold, span(source, 14, 16)),
+                ],
+                2,
+                Origin::from_span(span(source, 2, 16), "test")
+            ),
+            "Testing spans of headings"
+        );
+
+        match &blocks[1] {
+            Block::Paragraph(text, origin) => {
+                assert_eq!(origin.span, span(source, 18, 54));
+                assert_eq!(
+                    text[1],
+                    Inline::Extension(
+                        "math".to_string(),
+                        vec![" x".to_string()],
+                        span(source, 34, 43)
+                    ),
+                    "Testing spans of inline extensions on the second line of a paragraph"
+                );
+                assert_eq!(text[1].get_span().start.line, 4);
+                assert_eq!(text[1].get_span().start.column, 6);
+                assert_eq!(
+                    text[3].get_span(),
+                    span(source, 50, 54),
+                    "Testing that columns are counted in characters"
+                );
+                assert_eq!(text[3].get_span().start.column, 21);
+            }
+            block => panic!("Expected a paragraph, found {:?}", block),
+        }
+
+        assert_eq!(
+            blocks[2].get_span(),
+            span(source, 56, 72),
+            "Testing that block extensions span their closing line"
+        );
+
+        assert_eq!(
+            parse_inline("a\n**b**")[1].get_span(),
+            Span::new(Position::new(2, 2, 1), Position::new(4, 2, 3)),
+            "Testing spans of inline elements parsed on their own"
+        );
+    }
+
+    #[test]
+    fn diagnostics() {
+        let source = "Some text\nwith |foo, bar| in it\n\n---- hidden\n----";
+        let mut doc = DocumentState::new(Html);
+        doc.translate(source, "doc");
+
+        assert_eq!(doc.errors().count(), 1, "Testing that unknown extensions are errors");
+        let error = &doc.diagnostics[0];
+        assert_eq!(error.code.as_deref(), Some("unknown-extension"));
+        assert_eq!(
+            error.origin.as_ref().map(|origin| origin.span),
+            Some(span(source, 15, 25)),
+            "Testing that diagnostics point to the extension"
+        );
+
+        let json = serde_json::to_value(error).unwrap();
+        assert_eq!(json["severity"], "error", "Testing diagnostics as JSON");
+        assert_eq!(json["origin"]["name"]["filename"], "doc");
+        assert_eq!(json["origin"]["span"]["start"]["column"], 6);
+
+        let mut doc = DocumentState::new(Html);
+        doc.translate("|escape, a, b|", "doc");
+        assert_eq!(
+            doc.warnings().next().and_then(|w| w.extension.as_deref()),
+            Some("Escape"),
+            "Testing that extension warnings name the extension"
+        );
+        assert!(!doc.has_errors());
+    }
+
+    #[test]
+    fn expansion_backtraces() {
+        let source = "Text\n\n------ id\nIntro\n---- id\nSee |nope|\n----\n------";
+        let mut doc = DocumentState::new(Html);
+        doc.translate(source, "doc");
+
+        let origin = doc.diagnostics[0].origin.clone().unwrap();
+        assert_eq!(
+            origin.root().span,
+            span(source, 6, source.len()),
+            "Testing that expansions are traced back to the source file"
+        );
+        assert_eq!(
+            origin
+                .backtrace()
+                .iter()
+                .map(|(extension, origin)| (*extension, origin.line_number))
+                .collect::<Vec<_>>(),
+            vec![("Identity", 2), ("Identity", 1)],
+            "Testing the chain of expansions"
+        );
+        assert_eq!(
+            doc.diagnostics[0].to_string().lines().nth(1),
+            Some(" --> doc:3:1")
+        );
+    }
+
+    #[test]
+    fn lambda_note() {
+        let source = "::title=Test\n:: author = Me\n  # Hello **world** \\alpha\n\n\
+            some text\n   on |link, https://a.b, go\\, now| two lines\n\
+            * a\n    1. b\n| A | B |\n|:--|--:|\n| 1 | 2 |\n: Numbers {#numbers}\n\
+            ------ code, rust\n----\n------";
+
+        let formatted = format_document(source, "doc").unwrap();
+        assert_eq!(
+            formatted,
+            ":: title  = Test\n:: author = Me\n\n# Hello **world** \\alpha\n\n\
+            some text on |link, https://a.b, go\\, now| two lines\n\n\
+            - a\n  1. b\n\n| A   |   B |\n| :-- | --: |\n| 1   |   2 |\n: Numbers {#numbers}\n\n\
+            ----- code, rust\n----\n-----\n",
+            "Testing the formatting of λnote documents"
+        );
+        assert_eq!(
+            format_document(&formatted, "doc").unwrap(),
+            formatted,
+            "Testing that formatting twice gives the same result"
+        );
+
+        let mut doc = DocumentState::new(LambdaNote);
+        assert_eq!(
+            doc.translate(":: x = 1\n\n**|calc, 2 * 3|** |img, cat.png|", "doc"),
+            ":: x = 1\n\n**6** |img, cat.png|\n",
+            "Testing that extensions are expanded in λnote output"
+        );
+    }
+
+    #[test]
+    fn markdown() {
+        let mut doc = DocumentState::new(Markdown);
+        assert_eq!(
+            doc.translate(
+                "# Hi //there//\n\nH__2__O and x^^2^^ |link, https://a.b, a **b**|\n\n\
+                - a\n  - |code, x|\n\n---- code, rust\nfn main() {}\n----",
+                "doc"
+            ),
+            "# Hi *there*\n\nH<sub>2</sub>O and x<sup>2</sup> [a **b**](https://a.b)\n\n\
+            - a\n  - `x`\n\n```rust\nfn main() {}\n```\n",
+            "Testing Markdown output"
+        );
+    }
+
+    #[test]
+    fn markdown_import() {
+        let (blocks, diagnostics) = import_markdown(
+            "---\ntitle: Notes\n---\n\n# Hi *there*\n\n**a** [b](https://c.d) `x` $y$ 2*3\n\n\
+            ```rust\nfn main() {}\n```\n\n- a\n  - b\n\n> quote",
+            "doc.md",
+        );
+        assert_eq!(
+            print_document(&blocks),
+            ":: title = Notes\n\n# Hi //there//\n\n\
+            **a** |link, https://c.d, b| |code, x| |math, y| 2*3\n\n\
+            ---- code, rust\nfn main() {}\n----\n\n- a\n  - b\n\nquote\n",
+            "Testing Markdown import"
+        );
+        assert_eq!(diagnostics.len(), 1, "Testing Markdown import report");
+        assert_eq!(
+            diagnostics[0].origin.as_ref().map(|origin| origin.line_number),
+            Some(16),
+            "Testing Markdown import report origin"
+        );
+    }
+
+    #[test]
+    fn source_lines() {
+        let mut doc = DocumentState::new(WebPreview::new());
+        let result = doc.translate_no_template(
+            "# Title\n\nSome\ntext\n\n---- code\nx\n----\n\n|img, a.png|\n\n- a",
+            "doc",
+        );
+        let lines: Vec<&str> = result
+            .match_indices("data-source-line=\"")
+            .map(|(i, _)| &result[i + 18..i + 18 + result[i + 18..].find('"').unwrap()])
+            .collect();
+        assert_eq!(lines, ["1", "3", "6", "10", "12"], "Testing source lines in previews");
+        assert!(
+            result.starts_with("<h1 data-source-line=\"1\" id=\"title\">Title</h1>"),
+            "Testing source line attributes"
+        );
+    }
+
+    #[test]
+    fn html_template() {
+        let template = "<title>{{title|Untitled}}</title>{{content}}<p>{{author|anon}}</p>";
+        let mut doc = DocumentState::new(HtmlTemplate::new(template, false));
+        assert_eq!(
+            doc.translate(":: title = Notes\n\n# Hi", "doc"),
+            "<title>Notes</title><h1 id=\"hi\">Hi</h1>\n<p>anon</p>",
+            "Testing HTML templates"
+        );
+
+        let mut doc = DocumentState::new(HtmlTemplate::new("{{content}}{{bottom}}", true));
+        let result = doc.translate("# Hi", "doc");
+        assert!(
+            result.starts_with("<h1 data-source-line=\"1\" id=\"hi\">Hi</h1>"),
+            "Testing HTML templates with source lines"
+        );
+        assert!(
+            result.contains("scrollToLine"),
+            "Testing HTML templates with preview code"
+        );
+        assert!(
+            !result.contains("localhost:5432")
+                && !DocumentState::new(WebPreview::new())
+                    .translate("", "doc")
+                    .contains("localhost:5432"),
+            "Testing that the preview links to local files on any port"
+        );
+    }
+
+    #[test]
+    fn latex_template() {
+        let template = "\\title{{{title}}}\\author{{{author|Anonymous}}}\n\
+            \\date{{{raw date}}}\n{{imports}}{{content}}";
+        let mut doc = DocumentState::new(LatexTemplate::new(template));
+        assert_eq!(
+            doc.translate(
+                ":: title = 100% R&D\n:: date = \\today\n\n# Intro",
+                "doc"
+            ),
+            "\\title{100\\% R\\&D}\\author{Anonymous}\n\\date{\\today}\n\
+            \\section{Intro}\\label{intro}\n",
+            "Testing LaTeX templates"
+        );
+    }
+
+    #[test]
+    fn include() {
+        let directory = std::env::temp_dir().join("lambda_note_include");
+        std::fs::create_dir_all(directory.join("chapters")).unwrap();
+        std::fs::write(
+            directory.join("chapters/intro.ln"),
+            "# Intro\n\n:: author = Ada\n\n|alias, math, m|\n\n|nothing|",
+        )
+        .unwrap();
+        std::fs::write(directory.join("loop.ln"), "--- include, loop.ln\n---").unwrap();
+        let main = directory.join("main.ln");
+        let main = main.to_str().unwrap();
+
+        let mut doc = DocumentState::new(Html);
+        let result = doc.translate_no_template(
+            "--- include, chapters/intro.ln, 1\n---\n\n|m, x|",
+            main,
+        );
+        assert!(
+            result.starts_with("<h2 id=\"intro\">Intro</h2>"),
+            "Testing included files and heading offsets"
+        );
+        assert!(
+            result.contains("<math"),
+            "Testing that aliases carry across included files"
+        );
+        assert_eq!(
+            doc.metadata.get("author").map(String::as_str),
+            Some("Ada"),
+            "Testing that metadata carries across included files"
+        );
+        assert_eq!(
+            doc.diagnostics[0].origin.as_ref().map(|origin| &origin.name),
+            Some(&OriginName::Filename(
+                directory.join("chapters/intro.ln").to_string_lossy().to_string()
+            )),
+            "Testing the origins of included files"
+        );
+
+        let mut doc = DocumentState::new(Html);
+        doc.translate_no_template("--- include, loop.ln\n---", main);
+        assert_eq!(
+            doc.errors().filter_map(|e| e.code.as_deref()).collect::<Vec<_>>(),
+            vec!["include-cycle"],
+            "Testing include cycles"
+        );
+
+        let mut doc = DocumentState::new(Html);
+        doc.set_safe_mode(true);
+        assert_eq!(
+            doc.translate_no_template("--- include, chapters/intro.ln\n---", main),
+            "",
+            "Testing that include is disabled in safe mode"
+        );
+    }
+
+    #[test]
+    fn table_of_contents() {
+        let source = "--- toc, 2\n---\n\n# Intro\n\n## Why\n\n### Details\n\n# Intro";
+        let mut doc = DocumentState::new(Html);
+        assert_eq!(
+            doc.translate_no_template(source, "test"),
+            "<nav class=\"toc\">\n<ul>\n<li><a href=\"#intro\">Intro</a>\n\
+            <ul>\n<li><a href=\"#why\">Why</a></li>\n</ul>\n</li>\n\
+            <li><a href=\"#intro-1\">Intro</a></li>\n</ul>\n</nav>\n\
+            <h1 id=\"intro\">Intro</h1>\n<h2 id=\"why\">Why</h2>\n\
+            <h3 id=\"details\">Details</h3>\n<h1 id=\"intro-1\">Intro</h1>\n",
+            "Testing tables of contents with headings further down"
+        );
+
+        let mut doc = DocumentState::new(Latex);
+        assert_eq!(
+            doc.translate_no_template("|toc|", "test"),
+            "\\tableofcontents\n\n\n",
+            "Testing tables of contents in LaTeX"
+        );
+
+        let mut doc = DocumentState::new(Latex);
+        assert_eq!(
+            doc.translate_no_template("--- toc, 1\n---\n\n## A", "test"),
+            "\\setcounter{tocdepth}{2}\n\\tableofcontents\n\\subsection{A}\\label{a}\n",
+            "Testing that the depth is counted from the top heading in LaTeX"
+        );
+
+        // the characters around the placeholders of deferred output
+        let mut doc = DocumentState::new(Markdown);
+        assert_eq!(
+            doc.translate_no_template("--- toc\n---\n\n# A\n\n\u{E000}0\u{E001} \u{E000}", "test"),
+            "- [A](#a)\n\n\n# A\n\n\u{E000}0\u{E001} \u{E000}\n\n",
+            "Testing that placeholders can not be written in the document"
+        );
+    }
+
+    #[test]
+    fn table_of_contents_entries() {
+        let source = "--- toc\n---\n\n# See |ref, intro| {#intro}\n\n## Notes|footnote, A note|";
+        let mut doc = DocumentState::new(Html);
+        let output = doc.translate_no_template(source, "test");
+        assert!(
+            output.starts_with(
+                "<nav class=\"toc\">\n<ul>\n<li><a href=\"#intro\">See</a>\n\
+                <ul>\n<li><a href=\"#notes\">Notes</a></li>\n</ul>\n</li>\n</ul>\n</nav>\n\
+                <h1 id=\"intro\">See <a class=\"ref\" href=\"#intro\">Section 1</a></h1>\n"
+            ),
+            "Testing that references in headings are kept and not repeated in the TOC"
+        );
+        assert_eq!(
+            output.matches("id=\"fnref-1\"").count(),
+            1,
+            "Testing that footnotes in headings are not repeated in the TOC"
+        );
+
+        let mut doc = DocumentState::new(Markdown);
+        let source = "--- toc\n---\n\n# See |ref, intro| {#intro}\n\n# Why?\n\n# Why";
+        assert!(
+            doc.translate_no_template(source, "test")
+                .starts_with("- [See](#see)\n- [Why?](#why)\n- [Why](#why-1)\n"),
+            "Testing that Markdown tables of contents link to the anchors made by GitHub"
+        );
+    }
+
+    #[test]
+    fn heading_ids() {
+        let source = ":: section_numbers = true\n\n# Intro {#start}\n\n## A\n\n## A\n\n# Next\n\n\
+            ### Deep\n\n# Again {#start}";
+        let mut doc = DocumentState::new(Html);
+        assert_eq!(
+            doc.translate_no_template(source, "test"),
+            "<h1 id=\"start\"><span class=\"section-number\">1</span> Intro</h1>\n\
+            <h2 id=\"a\"><span class=\"section-number\">1.1</span> A</h2>\n\
+            <h2 id=\"a-1\"><span class=\"section-number\">1.2</span> A</h2>\n\
+            <h1 id=\"next\"><span class=\"section-number\">2</span> Next</h1>\n\
+            <h3 id=\"deep\"><span class=\"section-number\">2.0.1</span> Deep</h3>\n\
+            <h1 id=\"start\"><span class=\"section-number\">3</span> Again</h1>\n",
+            "Testing heading ids and section numbers"
+        );
+        assert_eq!(
+            doc.warnings().filter_map(|w| w.code.as_deref()).collect::<Vec<_>>(),
+            vec!["duplicate-id"],
+            "Testing duplicate heading ids"
+        );
+
+        let mut doc = DocumentState::new(Latex);
+        assert_eq!(
+            doc.translate_no_template("## Getting **started** {#start}", "test"),
+            "\\subsection{Getting \\textbf{started}}\\label{start}\n",
+            "Testing LaTeX heading labels"
+        );
+    }
+
+    #[test]
+    fn cross_references() {
+        let source = "See |ref, fig:cat|, |ref, eq:e, number| and |ref, tab:t|.\n\n\
+            |img, cat.png, A cat, 50, fig:cat|\n\n--- math, eq:e\ne = mc^2\n---\n\n\
+            | a |\n|---|\n| 1 |\n: Numbers {#tab:t}\n\n# Intro {#intro}\n\n|ref, intro|";
+        let mut doc = DocumentState::new(Html);
+        assert_eq!(
+            doc.translate_no_template(source, "test"),
+            "<p>See <a class=\"ref\" href=\"#fig:cat\">Figure 1</a>, \
+            <a class=\"ref\" href=\"#eq:e\">1</a> and \
+            <a class=\"ref\" href=\"#tab:t\">Table 1</a>.</p>\n\
+            <p><span class=\"figure\" id=\"fig:cat\">\
+            <img src=\"cat.png\" alt=\"A cat\" style=\"max-width:50%\">\
+            <span class=\"figcaption\">Figure 1: A cat</span></span></p>\n\
+            <div class=\"equation\" id=\"eq:e\">\
+            <math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\">\
+            <mi>e</mi><mo>=</mo><mi>m</mi><msup><mi>c</mi><mn>2</mn></msup></math>\
+            <span class=\"equation-number\">(1)</span></div>\n\
+            <table id=\"tab:t\">\n<caption>Table 1: Numbers</caption>\n\
+            <thead>\n<tr><th>a</th></tr>\n\
+            </thead>\n<tbody>\n<tr><td>1</td></tr>\n</tbody>\n</table>\n\
+            <h1 id=\"intro\">Intro</h1>\n<p><a class=\"ref\" href=\"#intro\">Section 1</a></p>\n",
+            "Testing references to figures, equations, tables and headings"
+        );
+        assert_eq!(doc.warnings().count(), 0, "Testing defined labels");
+
+        let mut doc = DocumentState::new(Html);
+        assert_eq!(
+            doc.translate_no_template(
+                "---- img, a.png ----\n<A & B>\n----\n\n| a |\n|---|\n| 1 |\n: Numbers",
+                "test"
+            ),
+            "<figure><img src=\"a.png\" alt=\"&lt;A &amp; B&gt;\" style=\"max-width:100%\">\
+            <figcaption>Figure 1: &lt;A &amp; B&gt;</figcaption></figure>\n\
+            <table>\n<caption>Table 1: Numbers</caption>\n\
+            <thead>\n<tr><th>a</th></tr>\n\
+            </thead>\n<tbody>\n<tr><td>1</td></tr>\n</tbody>\n</table>\n",
+            "Testing numbered figures and tables without labels"
+        );
+
+        let mut doc = DocumentState::new(Latex);
+        assert_eq!(
+            doc.translate_no_template("|ref, fig:a| |ref, fig:a, number|", "test"),
+            "\\autoref{fig:a} \\ref{fig:a}\n\n\n",
+            "Testing LaTeX references"
+        );
+        assert_eq!(
+            doc.warnings().filter_map(|w| w.code.as_deref()).collect::<Vec<_>>(),
+            vec!["undefined-label", "undefined-label"],
+            "Testing undefined labels"
+        );
+
+        let mut doc = DocumentState::new(Html);
+        doc.translate_no_template(
+            "|ref, x|\n\n--- math, x\na\n---\n\n--- math, x\nb\n---",
+            "test",
+        );
+        let warnings: Vec<_> = doc.warnings().collect();
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|w| (w.code.as_deref(), w.origin.as_ref().map(|o| o.line_number)))
+                .collect::<Vec<_>>(),
+            vec![(Some("duplicate-label"), Some(7)), (Some("duplicate-label"), Some(1))],
+            "Testing duplicate labels"
+        );
+
+        let mut doc = DocumentState::new(Latex);
+        let output = doc.translate_no_template(
+            "# Intro {#a\\b%}\n\n| a |\n|---|\n| 1 |\n: T {#t#}\n\n\
+            |ref, x}y| |img, cat.png, A cat, 50, fig cat|\n\n--- math, e}\nx\n---",
+            "test",
+        );
+        assert!(
+            output.contains("\\label{intro}") && output.matches("\\label").count() == 1,
+            "Testing that invalid labels are not written to LaTeX, got {:?}",
+            output
+        );
+        assert_eq!(
+            doc.warnings().filter_map(|w| w.code.as_deref()).collect::<Vec<_>>(),
+            vec!["invalid-label"; 5],
+            "Testing invalid labels"
+        );
+    }
+
+    #[test]
+    fn citations() {
+        let directory = std::env::temp_dir().join("lambda_note_citations");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("refs.bib"),
+            "@string{cj = \"The Computer Journal\"}\n\
+            @article{knuth, author = {Donald E. Knuth}, title = {Literate {P}rogramming},\n\
+            journal = cj, pages = {97--111}, year = 1984}\n\
+            @book{lamport, author = \"Lamport, Leslie and G{\\\"o}del, Kurt\",\n\
+            title = {{\\LaTeX}}, year = {1994},}",
+        )
+        .unwrap();
+        let main = directory.join("main.ln");
+        let main = main.to_str().unwrap();
+        let source = ":: bibliography = refs.bib\n\n|cite, lamport, knuth| |cite, knuth, nope|\n\n\
+            --- bibliography\n---";
+
+        let mut doc = DocumentState::new(Html);
+        assert_eq!(
+            doc.translate_no_template(source, main),
+            "<p>[<a href=\"#ref-lamport\">1</a>, <a href=\"#ref-knuth\">2</a>] \
+            [<a href=\"#ref-knuth\">2</a>, nope]</p>\n<ol class=\"bibliography\">\n\
+            <li id=\"ref-lamport\">Lamport, L. and Go\u{308}del, K. (1994). <em>LaTeX</em>.</li>\n\
+            <li id=\"ref-knuth\">Knuth, D. E. (1984). Literate Programming. \
+            <em>The Computer Journal</em>, 97–111.</li>\n</ol>\n",
+            "Testing numeric citations"
+        );
+        assert_eq!(
+            doc.warnings().filter_map(|w| w.code.as_deref()).collect::<Vec<_>>(),
+            vec!["unknown-citation"],
+            "Testing unknown citation keys"
+        );
+
+        let mut doc = DocumentState::new(Markdown);
+        assert_eq!(
+            doc.translate_no_template(
+                &format!(":: citation_style = author-year\n{}", source),
+                main
+            ),
+            "(Lamport and Go\u{308}del 1994; Knuth 1984) (Knuth 1984; nope)\n\n\
+            - Knuth, D. E. (1984). Literate Programming. *The Computer Journal*, 97–111.\n\
+            - Lamport, L. and Go\u{308}del, K. (1994). *LaTeX*.\n\n\n",
+            "Testing author-year citations"
+        );
+
+        let mut doc = DocumentState::new(Latex);
+        doc.translate_no_template(source, main);
+        assert!(
+            doc.imports
+                .contains("\\usepackage[style=numeric]{biblatex}\n\\addbibresource{refs.bib}"),
+            "Testing biblatex in LaTeX"
+        );
+
+        let mut doc = DocumentState::new(Html);
+        doc.translate_no_template("|cite, knuth|", main);
+        assert_eq!(
+            doc.errors().filter_map(|e| e.code.as_deref()).collect::<Vec<_>>(),
+            vec!["no-bibliography"],
+            "Testing citations without a bibliography"
+        );
+
+        let outside = std::env::temp_dir().join("lambda_note_citations_outside.bib");
+        std::fs::write(&outside, "@book{knuth, title = {TeX}}").unwrap();
+        let source = format!(":: bibliography = {}\n\n|cite, knuth|", outside.display());
+        let mut doc = DocumentState::new(Html);
+        doc.translate_no_template(&source, main);
+        assert_eq!(doc.errors().count(), 0, "Testing bibliographies outside the document");
+
+        let relative = ":: bibliography = ../lambda_note_citations_outside.bib\n\n|cite, knuth|";
+        for source in [source.as_str(), relative] {
+            let mut doc = DocumentState::new(Html);
+            doc.set_safe_mode(true);
+            doc.translate_no_template(source, main);
+            assert_eq!(
+                doc.errors().filter_map(|e| e.code.as_deref()).collect::<Vec<_>>(),
+                vec!["unsafe-bibliography"],
+                "Testing bibliographies outside the document in safe mode"
+            );
+        }
+
+        let mut doc = DocumentState::new(Html);
+        doc.set_safe_mode(true);
+        doc.translate_no_template(":: bibliography = refs.bib\n\n|cite, knuth|", main);
+        assert_eq!(
+            doc.errors().count(),
+            0,
+            "Testing bibliographies next to the document in safe mode"
+        );
+    }
+
+    #[test]
+    fn footnotes() {
+        let source =
+            "A|footnote, **Bold**, with a comma|.\n\n--- footnotes\n---\n\nB|footnote, Two|";
+        let mut doc = DocumentState::new(Html);
+        assert_eq!(
+            doc.translate_no_template(source, "test"),
+            "<p>A<sup class=\"footnote-ref\"><a id=\"fnref-1\" href=\"#fn-1\">1</a></sup>.</p>\n\
+            <section class=\"footnotes\">\n<ol>\n\
+            <li id=\"fn-1\" value=\"1\"><strong>Bold</strong>, with a comma \
+            <a class=\"footnote-back\" href=\"#fnref-1\">↩</a></li>\n</ol>\n</section>\n\
+            <p>B<sup class=\"footnote-ref\"><a id=\"fnref-2\" href=\"#fn-2\">2</a></sup></p>\n\
+            <section class=\"footnotes\">\n<ol>\n<li id=\"fn-2\" value=\"2\">Two \
+            <a class=\"footnote-back\" href=\"#fnref-2\">↩</a></li>\n</ol>\n</section>\n",
+            "Testing footnotes in HTML"
+        );
+
+        let mut doc = DocumentState::new(Latex);
+        assert_eq!(
+            doc.translate_no_template(source, "test"),
+            "A\\footnote{\\textbf{Bold}, with a comma}.\n\n\n\nB\\footnote{Two}\n\n\n",
+            "Testing footnotes in LaTeX"
+        );
+
+        let mut doc = DocumentState::new(Markdown);
+        assert_eq!(
+            doc.translate_no_template(source, "test"),
+            "A[^1].\n\n[^1]: **Bold**, with a comma\n\n\nB[^2]\n\n[^2]: Two\n\n",
+            "Testing footnotes in Markdown"
+        );
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn persistent_extensions() {
+        // answers with the id of the process, which stays the same between requests
+        let script = r#"while read -r line; do
+  case "$line" in
+    *'"shutdown"'*) exit 0;;
+    *'"info"'*) printf '{"jsonrpc":"2.0","id":%s,"result":{%s}}\n' "$(id "$line")" "$info";;
+    *) printf '{"jsonrpc":"2.0","id":%s,"result":{"content":["%d"]}}\n' "$(id "$line")" $$;;
+  esac
+done
+"#;
+        let path = extension_script("persistent", "pid", script);
+
+        let mut doc = DocumentState::new(Html);
+        let result = doc.translate_no_template(
+            &format!("|define, pid, sh {}, persistent|\n\n|pid| |pid|", path.display()),
+            "test",
+        );
+        assert_eq!(doc.errors().count(), 0, "Testing persistent extensions");
+        let ids: Vec<&str> = result
+            .trim_start_matches("<p></p>\n<p>")
+            .trim_end_matches("</p>\n")
+            .split(' ')
+            .collect();
+        assert!(
+            ids.len() == 2 && ids[0] == ids[1] && !ids[0].is_empty(),
+            "Testing that persistent extensions are started once, got {:?}",
+            result
+        );
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn pure_extensions() {
+        // sleeps, so that the calls are only quick if they run at the same time
+        let one_shot = r#"info="$info"',"pure":true'
+request=$(cat)
+case "$request" in
+  *'"info"'*) printf '{%s}' "$info";;
+  *) sleep 0.3; printf '{"content":["%s"]}' "$(argument "$request")";;
+esac
+"#;
+        // answers the two action requests in reverse order
+        let persistent = r#"info="$info"',"pure":true'
+read -r line
+printf '{"jsonrpc":"2.0","id":%s,"result":{%s}}\n' "$(id "$line")" "$info"
+read -r first
+read -r second
+for line in "$second" "$first"; do
+  printf '{"jsonrpc":"2.0","id":%s,"result":{"content":["%s"]}}\n' \
+    "$(id "$line")" "$(argument "$line")"
+done
+read -r line
+"#;
+        let one_shot_path = extension_script("pure", "echo", one_shot);
+        let persistent_path = extension_script("pure", "echo_persistent", persistent);
+
+        let mut doc = DocumentState::new(Html);
+        let source = format!(
+            "|define, echo, sh {}|\n\n|echo,a| |echo,b| |echo,c| |echo,d|",
+            one_shot_path.display()
+        );
+        let start = std::time::Instant::now();
+        assert_eq!(
+            doc.translate_no_template(&source, "test"),
+            "<p></p>\n<p>a b c d</p>\n",
+            "Testing that pure extensions are put back in document order"
+        );
+        let elapsed = start.elapsed();
+        assert_eq!(doc.errors().count(), 0, "Testing pure extensions");
+        // one after the other, the four calls would take 1.2 seconds
+        assert!(
+            elapsed < std::time::Duration::from_millis(800),
+            "Testing that pure extensions are called at the same time, took {:?}",
+            elapsed
+        );
+
+        let mut doc = DocumentState::new(Html);
+        let source = format!(
+            "|define, echo, sh {}, persistent|\n\n|echo,a| |echo,b|",
+            persistent_path.display()
+        );
+        assert_eq!(
+            doc.translate_no_template(&source, "test"),
+            "<p></p>\n<p>a b</p>\n",
+            "Testing pure extensions that answer out of order"
+        );
+        assert_eq!(doc.errors().count(), 0, "Testing pure persistent extensions");
+
+        // more output than fits in a pipe, which is only read while the command runs
+        let big = r#"info="$info"',"pure":true'
+case "$(cat)" in
+  *'"info"'*) printf '{%s}' "$info";;
+  *) printf '{"content":["'; head -c 200000 /dev/zero | tr '\0' a; printf '"]}';;
+esac
+"#;
+        let mut doc = DocumentState::new(Html);
+        let source = format!(
+            "|define, big, sh {}|\n\n|big|\n\n:: timeout = 5",
+            extension_script("pure", "big", big).display()
+        );
+        let start = std::time::Instant::now();
+        let result = doc.translate_no_template(&source, "test");
+        assert_eq!(doc.errors().count(), 0, "Testing large responses");
+        assert!(
+            result.contains(&"a".repeat(200000)) && start.elapsed().as_secs() < 5,
+            "Testing that large responses are read before the command exits"
+        );
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn cached_extensions() {
+        // answers with the id of the process, which is new for every call
+        let script = r#"for option in "$@"; do
+  case "$option" in
+    uncached) info="$info"',"cache":false';;
+    v2) info="$info"',"protocol":2';;
+  esac
+done
+case "$(cat)" in
+  *'"info"'*) printf '{%s}' "$info";;
+  *) printf '{"content":["%d"]}' $$;;
+esac
+"#;
+        let path = extension_script("cache", "pid", script);
+        let directory = path.parent().unwrap().to_path_buf();
+        let _ = std::fs::remove_dir_all(directory.join("cache"));
+
+        let translate = |source: &str| {
+            let mut doc = DocumentState::new(Html);
+            doc.set_cache_directory(Some(directory.join("cache")));
+            let result = doc.translate_no_template(source, "test");
+            assert_eq!(doc.errors().count(), 0, "Testing cached extensions");
+            result
+        };
+
+        let cached = format!("|define, pid, sh {}|\n\n|pid, a|", path.display());
+        assert_eq!(
+            translate(&cached),
+            translate(&cached),
+            "Testing that responses are cached"
+        );
+        assert_ne!(
+            translate(&cached),
+            translate(&cached.replace("|pid, a|", "|pid, b|")),
+            "Testing that requests with other arguments are not cached together"
+        );
+
+        let uncached = format!("|define, pid, sh {} uncached|\n\n|pid, a|", path.display());
+        assert_ne!(
+            translate(&uncached),
+            translate(&uncached),
+            "Testing that extensions can opt out of the cache"
+        );
+
+        let source = format!("|define, pid, sh {} v2|\n\n|pid, a|", path.display());
+        let moved = source.replace("\n\n", "\n\nText\n\n");
+        assert_eq!(
+            translate(&source).trim_start_matches("<p></p>\n<p>"),
+            translate(&moved).trim_start_matches("<p></p>\n<p>Text</p>\n<p>"),
+            "Testing that calls on other lines are cached together"
+        );
+
+        // entries that have not been used for a long time are removed
+        let pruned = directory.join("pruned");
+        std::fs::create_dir_all(&pruned).unwrap();
+        let month_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(31 * 86400);
+        for file in ["old.json", "new.json", "old.txt"] {
+            std::fs::write(pruned.join(file), "").unwrap();
+        }
+        for file in ["old.json", "old.txt"] {
+            std::fs::File::options()
+                .write(true)
+                .open(pruned.join(file))
+                .and_then(|file| file.set_modified(month_ago))
+                .unwrap();
+        }
+        DocumentState::new(Html).set_cache_directory(Some(pruned.clone()));
+        assert_eq!(
+            ["old.json", "new.json", "old.txt"].map(|file| pruned.join(file).exists()),
+            [false, true, true],
+            "Testing that unused cache entries are removed"
+        );
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn extension_protocol() {
+        let script = r#"if [ -n "$1" ]; then info="$info"',"protocol":'"$1"; fi
+case "$(cat)" in
+  *'"info"'*) printf '{%s}' "$info";;
+  *'"variant":"inline","origin":{"file":"test","line":3},"documentPath":null'*)
+    printf '{"content":["inline on line 3"]}';;
+  *'"variant"'*) printf '{"content":["unexpected request"]}';;
+  *) printf '{"content":["version 1"]}';;
+esac
+"#;
+        let path = extension_script("protocol", "where", script);
+
+        let translate = |protocol: &str| {
+            let mut doc = DocumentState::new(Html);
+            let source = format!("|define, where, sh {} {}|\n\n|where|", path.display(), protocol);
+            let result = doc.translate_no_template(&source, "test");
+            assert_eq!(doc.errors().count(), 0, "Testing the extension protocol");
+            result
+        };
+        assert_eq!(
+            translate("2"),
+            "<p></p>\n<p>inline on line 3</p>\n",
+            "Testing that extensions are told how and where they are called"
+        );
+        assert_eq!(
+            translate(""),
+            "<p></p>\n<p>version 1</p>\n",
+            "Testing that the first version of the protocol is used by default"
+        );
+    }
+
+    #[test]
+    fn template_engine() {
+        let template = "{{#if author}}<p>By {{author}}</p>{{else}}<p>Anonymous</p>{{/if}}\n\
+            <ul>\n{{#each tags}}\n<li>{{this}}</li>\n{{else}}\n<li>none</li>\n{{/each}}\n</ul>";
+        let mut doc = DocumentState::new(HtmlTemplate::new(template, false));
+        assert_eq!(
+            doc.translate(":: author = Tom & Jerry\n:: tags = a, <b>, c", "doc"),
+            "<p>By Tom &amp; Jerry</p>\n<ul>\n<li>a</li>\n<li>&lt;b&gt;</li>\n<li>c</li>\n</ul>",
+            "Testing template conditionals, loops and escaping"
+        );
+
+        let mut doc = DocumentState::new(HtmlTemplate::new(template, false));
+        assert_eq!(
+            doc.translate("", "doc"),
+            "<p>Anonymous</p>\n<ul>\n<li>none</li>\n</ul>",
+            "Testing template else branches"
+        );
+
+        let directory = std::env::temp_dir().join("lambda_note_template_engine");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("header.tex"), "\\title{{{title}}}").unwrap();
+        let template = LatexTemplate::new("{{> header.tex}}\n{{content}}")
+            .with_partial_directory(&directory);
+        assert!(template.check().is_ok(), "Testing template partials");
+        let mut doc = DocumentState::new(template);
+        assert_eq!(
+            doc.translate(":: title = A_B\n\nHi", "doc"),
+            "\\title{A\\_B}\nHi\n\n\n",
+            "Testing template partials"
+        );
+
+        let missing = HtmlTemplate::new("{{> missing.html}}", false)
+            .with_partial_directory(&directory);
+        assert!(missing.check().is_err(), "Testing missing template partials");
+        let unclosed = HtmlTemplate::new("{{#if title}}\n{{content}}", false);
+        assert_eq!(
+            unclosed.check().map_err(|e| e.line),
+            Err(1),
+            "Testing unclosed template blocks"
+        );
+    }
 }
@@ -1,19 +1,33 @@
-use super::{inline::parse_inline, Block, Lines, Origin};
+use super::{
+    inline::parse_inline_mapped, Alignment, Block, Cell, Lines, List, ListItem, ListKind,
+    MappedText, Origin, Source, Table,
+};
 use lazy_static::lazy_static;
 use regex::Regex;
 
 /// Returns the next block and consumes the corresponding lines
 /// Note: this function does not parse normal paragraph blocks,
 /// that is done in the `parse_doc` function.
-pub fn next_block(lines: &mut Lines, doc_name: &str) -> Option<Block> {
-    parse_extension(lines, doc_name)
-        .or_else(|| parse_metadata(lines, doc_name))
-        .or_else(|| parse_heading(lines, doc_name))
-        .or_else(|| parse_divider(lines, doc_name))
-        .or_else(|| parse_list(lines, doc_name))
+pub fn next_block(lines: &mut Lines, source: &Source) -> Option<Block> {
+    let (_, first) = *lines.peek()?;
+
+    let mut block = parse_extension(lines, source)
+        .or_else(|| parse_metadata(lines, source))
+        .or_else(|| parse_heading(lines, source))
+        .or_else(|| parse_divider(lines, source))
+        .or_else(|| parse_list(lines, source))
+        .or_else(|| parse_table(lines, source))?;
+
+    // the block spans every line that was consumed
+    let last = lines
+        .peek()
+        .map_or(source.line_count(), |(_, line_number)| line_number - 1);
+    block.get_origin_mut().span = source.line_span(first, last);
+
+    Some(block)
 }
 
-fn parse_metadata(lines: &mut Lines, doc_name: &str) -> Option<Block> {
+fn parse_metadata(lines: &mut Lines, source: &Source) -> Option<Block> {
     let (line, _) = lines.peek()?;
 
     lazy_static! {
@@ -29,26 +43,309 @@ fn parse_metadata(lines: &mut Lines, doc_name: &str) -> Option<Block> {
     Some(Block::Metadata(
         key.into(),
         value.into(),
-        Origin::new(line_number, doc_name),
+        Origin::new(line_number, source.name.clone()),
     ))
 }
 
-fn parse_divider(lines: &mut Lines, doc_name: &str) -> Option<Block> {
+fn parse_divider(lines: &mut Lines, source: &Source) -> Option<Block> {
     let (line, line_number) = lines.peek()?;
     let line_number = *line_number;
 
     line.trim_start().starts_with("===").then(|| {
         lines.next(); // consume the line
-        Block::Divider(Origin::new(line_number, doc_name))
+        Block::Divider(Origin::new(line_number, source.name.clone()))
     })
 }
 
-// TODO
-fn parse_list(_lines: &mut Lines, _: &str) -> Option<Block> {
-    None
+fn parse_list(lines: &mut Lines, source: &Source) -> Option<Block> {
+    let (line, line_number) = lines.peek()?;
+    let line_number = *line_number;
+    let (indent, kind, _, _) = list_item(line)?;
+
+    let list = parse_list_level(lines, source, indent, kind);
+    Some(Block::List(list, Origin::new(line_number, source.name.clone())))
+}
+
+/// Parse all the consecutive items of a list with a given
+/// indentation and kind. More indented items are parsed
+/// as children of the previous item while continuation lines
+/// (indented text that is not an item) are added to the item above.
+fn parse_list_level(lines: &mut Lines, source: &Source, indent: usize, kind: ListKind) -> List {
+    // the text of each item is kept as source code up until
+    // the item is complete, since it might span multiple lines
+    let mut items: Vec<(MappedText, Vec<List>)> = vec![];
+    let mut start = 1;
+
+    while let Some((line, line_number)) = lines.peek() {
+        let line_number = *line_number;
+        if line.trim().is_empty() {
+            break;
+        }
+
+        match list_item(line) {
+            Some((item_indent, item_kind, number, column))
+                if item_indent == indent && item_kind == kind =>
+            {
+                // like in Markdown, an ordered list counts from its first number
+                if items.is_empty() {
+                    start = number;
+                }
+                let position = source.position(line_number, column);
+                items.push((MappedText::from_str(&line[column..], position), vec![]));
+                lines.next();
+            }
+            Some((item_indent, item_kind, _, _)) if item_indent > indent && !items.is_empty() => {
+                let child = parse_list_level(lines, source, item_indent, item_kind);
+                items.last_mut().unwrap().1.push(child);
+            }
+            // a less indented item or an item of a different kind ends the list
+            Some(_) => break,
+            None if indentation(line) > indent && !items.is_empty() => {
+                let (text, _) = items.last_mut().unwrap();
+                let column = line.len() - line.trim_start().len();
+                text.push_unmapped("\n");
+                text.push(line.trim(), source.position(line_number, column));
+                lines.next();
+            }
+            None => break,
+        }
+    }
+
+    List {
+        kind,
+        start,
+        items: items
+            .into_iter()
+            .map(|(text, children)| ListItem {
+                text: parse_inline_mapped(&text),
+                children,
+            })
+            .collect(),
+    }
 }
 
-fn parse_extension(lines: &mut Lines, doc_name: &str) -> Option<Block> {
+/// Given a line, check if it is the start of a list item. If so, return the
+/// indentation of the marker, the kind of list, the number of the item (1 for
+/// bullets) and the byte offset of the item's text.
+fn list_item(line: &str) -> Option<(usize, ListKind, usize, usize)> {
+    lazy_static! {
+        static ref RULE: Regex =
+            Regex::new(r"^(?P<indent>\s*)(?:(?P<bullet>[-*])|(?P<number>\d+\.))\s+(?P<text>\S.*)$")
+                .unwrap();
+    }
+
+    let captures = RULE.captures(line)?;
+    let (kind, number) = match captures.name("number") {
+        Some(number) => (
+            ListKind::Ordered,
+            number.as_str().trim_end_matches('.').parse().ok()?,
+        ),
+        None => (ListKind::Unordered, 1),
+    };
+
+    Some((
+        indentation(captures.name("indent")?.as_str()),
+        kind,
+        number,
+        captures.name("text")?.start(),
+    ))
+}
+
+/// The width of the leading whitespace of a line, tabs are counted as four spaces
+fn indentation(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+/// Tables are rows of cells separated by vertical bars. A table must start with
+/// either a separator row or a single header row followed by a separator row,
+/// this makes it possible to tell a table apart from a line with an inline extension.
+/// ```text
+/// | Name | Age |
+/// |:-----|----:|
+/// | Ada  | 36  |
+/// : An optional caption {#optional-label}
+/// ```
+fn parse_table(lines: &mut Lines, source: &Source) -> Option<Block> {
+    let mut lookahead = lines.clone();
+    let (first, line_number) = lookahead.next()?;
+
+    if !is_table_row(first) {
+        return None;
+    }
+
+    let (header, separator) = if is_table_separator(first) {
+        (None, first)
+    } else {
+        let (second, _) = lookahead.next()?;
+        if !is_table_separator(second) {
+            return None;
+        }
+        (Some((first, line_number)), second)
+    };
+
+    // we now know that it is a table, consume the header and separator rows
+    if header.is_some() {
+        lines.next();
+    }
+    lines.next();
+
+    // separator rows can not contain any extensions, so they are split on every bar
+    let mut alignments: Vec<Alignment> = separator
+        .trim()
+        .trim_matches('|')
+        .split('|')
+        .map(|cell| parse_alignment(cell.trim()))
+        .collect();
+
+    let header: Option<Vec<Cell>> =
+        header.map(|(row, line_number)| parse_row(row, line_number, source));
+
+    let mut rows: Vec<Vec<Cell>> = vec![];
+    while let Some((line, line_number)) = lines.peek() {
+        if !is_table_row(line) {
+            break;
+        }
+
+        // any additional separator rows are just ignored
+        if !is_table_separator(line) {
+            rows.push(parse_row(line, *line_number, source));
+        }
+        lines.next();
+    }
+
+    let caption = lines.peek().and_then(|(line, line_number)| {
+        table_caption(line).map(|(column, caption, label)| {
+            let position = source.position(*line_number, column);
+            (
+                parse_inline_mapped(&MappedText::from_str(caption, position)),
+                label,
+            )
+        })
+    });
+
+    let (caption, label) = match caption {
+        Some((caption, label)) => {
+            lines.next();
+            (Some(caption).filter(|c| !c.is_empty()), label)
+        }
+        None => (None, None),
+    };
+
+    // make sure every row has the same number of cells
+    let columns = header
+        .iter()
+        .chain(rows.iter())
+        .map(|row| row.len())
+        .chain(std::iter::once(alignments.len()))
+        .max()
+        .unwrap_or(0);
+
+    alignments.resize(columns, Alignment::Default);
+    let header = header.map(|mut row| {
+        row.resize(columns, vec![]);
+        row
+    });
+    for row in rows.iter_mut() {
+        row.resize(columns, vec![]);
+    }
+
+    Some(Block::Table(
+        Table {
+            header,
+            rows,
+            alignments,
+            caption,
+            label,
+        },
+        Origin::new(line_number, source.name.clone()),
+    ))
+}
+
+fn is_table_row(line: &str) -> bool {
+    let line = line.trim();
+    line.len() > 1 && line.starts_with('|') && line.ends_with('|')
+}
+
+fn is_table_separator(line: &str) -> bool {
+    lazy_static! {
+        static ref RULE: Regex = Regex::new(r"^\s*\|(?:\s*:?-+:?\s*\|)+\s*$").unwrap();
+    }
+    RULE.is_match(line)
+}
+
+/// Parse the inline contents of each cell in a table row
+fn parse_row(row: &str, line_number: usize, source: &Source) -> Vec<Cell> {
+    split_cells(row)
+        .into_iter()
+        .map(|(column, cell)| {
+            let position = source.position(line_number, column);
+            parse_inline_mapped(&MappedText::from_str(cell, position))
+        })
+        .collect()
+}
+
+/// Split a table row into the source text of each cell, and the byte offset of where it starts.
+/// Cells are separated by vertical bars surrounded by whitespace, this way
+/// inline extensions such as `|math, x|` can still be used inside of a cell.
+fn split_cells(row: &str) -> Vec<(usize, &str)> {
+    // skip the leading and trailing bars
+    let start = row.len() - row.trim_start().len() + 1;
+    let end = row.trim_end().len() - 1;
+    let inner = &row[start..end];
+    let chars: Vec<(usize, char)> = inner.char_indices().collect();
+
+    let mut ranges = vec![];
+    let mut cell_start = 0;
+    for (i, (offset, c)) in chars.iter().enumerate() {
+        let space_before = i == 0 || chars[i - 1].1.is_whitespace();
+        let space_after = chars.get(i + 1).is_none_or(|(_, c)| c.is_whitespace());
+
+        if *c == '|' && space_before && space_after {
+            ranges.push(cell_start..*offset);
+            cell_start = offset + 1;
+        }
+    }
+    ranges.push(cell_start..inner.len());
+
+    ranges
+        .into_iter()
+        .map(|range| {
+            let cell = &inner[range.clone()];
+            let indentation = cell.len() - cell.trim_start().len();
+            (start + range.start + indentation, cell.trim())
+        })
+        .collect()
+}
+
+fn parse_alignment(cell: &str) -> Alignment {
+    match (cell.starts_with(':'), cell.ends_with(':')) {
+        (true, true) => Alignment::Center,
+        (true, false) => Alignment::Left,
+        (false, true) => Alignment::Right,
+        (false, false) => Alignment::Default,
+    }
+}
+
+/// Parse a caption line on the form `: caption {#label}`
+fn table_caption(line: &str) -> Option<(usize, &str, Option<String>)> {
+    lazy_static! {
+        static ref RULE: Regex =
+            Regex::new(r"^\s*:\s+(?P<caption>.*?)\s*(?:\{#(?P<label>[^\s}]+)\})?\s*$").unwrap();
+    }
+
+    let captures = RULE.captures(line)?;
+    let caption = captures.name("caption")?;
+    Some((
+        caption.start(),
+        caption.as_str(),
+        captures.name("label").map(|m| m.as_str().to_string()),
+    ))
+}
+
+fn parse_extension(lines: &mut Lines, source: &Source) -> Option<Block> {
     lazy_static! {
         static ref RULE: Regex =
             Regex::new(r"^\s*(?P<div>-{3,})\s*(?P<ident>\w+)\s*(?:,(?P<args>[^-]+))?-*\s*$")
@@ -84,11 +381,17 @@ fn parse_extension(lines: &mut Lines, doc_name: &str) -> Option<Block> {
     // the first argument will be the main content of ,the block
     arguments.insert(0, contents);
 
-    Some(Block::Extension(ident, arguments, Origin::new(line_number, doc_name)))
+    Some(Block::Extension(
+        ident,
+        arguments,
+        Origin::new(line_number, source.name.clone()),
+    ))
 }
 
-fn parse_heading(lines: &mut Lines, doc_name: &str) -> Option<Block> {
-    let (line, _) = lines.peek()?;
+fn parse_heading(lines: &mut Lines, source: &Source) -> Option<Block> {
+    let (line, line_number) = lines.peek()?;
+    let line_number = *line_number;
+    let indentation = line.len() - line.trim_start().len();
     let line = line.trim_start();
     let level = line.chars().take_while(|c| *c == '#').count();
 
@@ -96,21 +399,20 @@ fn parse_heading(lines: &mut Lines, doc_name: &str) -> Option<Block> {
         return None;
     }
 
-    // TODO: replace with split_once, when it reaches stable
-    let title = line
-        .split("# ")
-        .skip(1)
-        .fold(String::new(), |acc, s| acc + s);
+    let (hashes, title) = line.split_once("# ")?;
 
     if title.is_empty() {
         return None;
     }
 
+    let column = indentation + hashes.len() + 2;
+    let title = MappedText::from_str(title, source.position(line_number, column));
+
     // consume the line
-    let (_, line_number) = lines.next()?;
+    lines.next()?;
     Some(Block::Heading(
-        parse_inline(&title),
+        parse_inline_mapped(&title),
         level as u8,
-        Origin::new(line_number, doc_name),
+        Origin::new(line_number, source.name.clone()),
     ))
 }
@@ -5,15 +5,21 @@
 use lazy_static::lazy_static;
 use tst::{tstmap, TSTMap};
 
-use super::{EscapeChar, Inline, Tag};
-use std::{iter::Peekable, str::Chars};
+use super::{EscapeChar, Inline, MappedText, Position, Span, Tag};
 
 /// Parse a string slice into a vector of
 /// all the inline elements found inside of it.
+/// The spans of the elements are relative to the start of the string.
 pub fn parse_inline(source: &str) -> Vec<Inline> {
+    parse_inline_mapped(&MappedText::from_str(source, Position::start()))
+}
+
+/// Parse a text that has been assembled from parts of a
+/// larger source, the spans will point into the larger source.
+pub fn parse_inline_mapped(source: &MappedText) -> Vec<Inline> {
     let mut state = ParserState::new(source);
 
-    while let Some(current) = state.chars.next() {
+    while let Some(current) = state.next_token() {
         match current {
             '\\' => escape(&mut state),
             '|' => extension(&mut state),
@@ -28,48 +34,100 @@ pub fn parse_inline(source: &str) -> Vec<Inline> {
 
         state.prev_is_whitespace = current.is_whitespace();
     }
+    state.token_start = state.chars.offset;
     state.amend();
     state.result
 }
 
+/// An iterator over the chars of a string
+/// that keeps track of the current byte offset
+#[derive(Clone)]
+struct Chars<'a> {
+    source: &'a str,
+    offset: usize,
+}
+
+impl<'a> Chars<'a> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.offset..].chars().next()
+    }
+}
+
+impl<'a> Iterator for Chars<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+}
+
 struct ParserState<'a> {
     result: Vec<Inline>,
     // the stack is used to keep track of begin tags
     // that are yet to be closed by an end tag.
     open_tags: Vec<Tag>,
     text_buffer: String,
-    chars: Peekable<Chars<'a>>,
+    // the offset of where the text in the buffer starts
+    text_start: usize,
+    // the offset of the token that is currently being parsed
+    token_start: usize,
+    chars: Chars<'a>,
+    source: &'a MappedText,
     prev_is_whitespace: bool,
 }
 
 impl<'a> ParserState<'a> {
-    fn new(source: &'a str) -> Self {
+    fn new(source: &'a MappedText) -> Self {
         ParserState {
             result: vec![],
             open_tags: vec![],
             text_buffer: String::new(),
-            chars: source.chars().peekable(),
+            text_start: 0,
+            token_start: 0,
+            chars: Chars {
+                source: &source.text,
+                offset: 0,
+            },
+            source,
             prev_is_whitespace: false,
         }
     }
 
+    /// advance to the next char and mark it as the start of a new token
+    fn next_token(&mut self) -> Option<char> {
+        self.token_start = self.chars.offset;
+        self.chars.next()
+    }
+
+    /// the span from the start of the current token up until the current char
+    fn token_span(&self) -> Span {
+        self.source.span(self.token_start, self.chars.offset)
+    }
+
     /// push the text buffer to the result vec
     fn push_buffer(&mut self) {
-        if self.text_buffer.is_empty() {
-            return;
+        if !self.text_buffer.is_empty() {
+            let span = self.source.span(self.text_start, self.token_start);
+            self.result
+                .push(Inline::Text(self.text_buffer.clone(), span));
+            self.text_buffer.clear();
         }
-        self.result.push(Inline::Text(self.text_buffer.clone()));
-        self.text_buffer.clear();
+        self.text_start = self.token_start;
+    }
+
+    /// push the text buffer, followed by an element spanning the current token
+    fn push_element<F: FnOnce(Span) -> Inline>(&mut self, element: F) {
+        self.push_buffer();
+        self.result.push(element(self.token_span()));
+        self.text_start = self.chars.offset;
     }
 
     /// peek in the chars iterator and check if the next
     /// char is a given character
     fn next_is(&mut self, c: char) -> bool {
-        if let Some(next) = self.chars.peek() {
-            *next == c
-        } else {
-            false
-        }
+        self.chars.peek() == Some(c)
     }
 
     fn is_closing_tag(&self, tag: &Tag) -> bool {
@@ -98,9 +156,9 @@ impl<'a> ParserState<'a> {
 
             let missplaced_tag = self.open_tags.last().unwrap();
 
-            if let Inline::Begin(tag) = &self.result[i] {
+            if let Inline::Begin(tag, span) = &self.result[i] {
                 if tag == missplaced_tag {
-                    self.result[i] = Inline::Text(tag_to_string(tag));
+                    self.result[i] = Inline::Text(tag_to_string(tag), *span);
                     self.open_tags.pop();
                 }
             }
@@ -111,7 +169,6 @@ impl<'a> ParserState<'a> {
     }
 }
 
-
 /// Parse inline extensions
 fn extension(state: &mut ParserState) {
     let mut char_iter = state.chars.clone();
@@ -132,11 +189,11 @@ fn extension(state: &mut ParserState) {
 
         // handle escaping of bar characters and commas inside
         // of extensions such as "\|" and "\,".
-        if c == '\\' && Some(&'|') == char_iter.peek() {
+        if c == '\\' && Some('|') == char_iter.peek() {
             current_arg.push('|');
             char_iter.next();
         } 
-        else if c == '\\' && Some(&',') == char_iter.peek() {
+        else if c == '\\' && Some(',') == char_iter.peek() {
             current_arg.push(',');
             char_iter.next();
         }
@@ -154,18 +211,16 @@ fn extension(state: &mut ParserState) {
     // unterminated extensions are
     // not valid, so we do not save any of our progress and just return
     if !terminated {
-        state.push_buffer();
-        state.result.push(Inline::Text(String::from('|')));
+        state.push_element(|span| Inline::Text(String::from('|'), span));
         return;
     }
 
     // replace the iterator with the one that has advanced
     state.chars = char_iter;
 
-    state.push_buffer();
-    state
-        .result
-        .push(Inline::Extension(argv[0].trim().to_string(), argv[1..].to_vec()));
+    state.push_element(|span| {
+        Inline::Extension(argv[0].trim().to_string(), argv[1..].to_vec(), span)
+    });
 }
 
 /// Handle potential start and end tags
@@ -195,8 +250,7 @@ fn tag(first: char, state: &mut ParserState) {
     
         // seems like we got a valid closing tag
         state.open_tags.pop();
-        state.push_buffer();
-        state.result.push(Inline::End(tag));
+        state.push_element(|span| Inline::End(tag, span));
         return;
     }
     
@@ -211,8 +265,7 @@ fn tag(first: char, state: &mut ParserState) {
         }
     }
     state.open_tags.push(tag.clone());
-    state.push_buffer();
-    state.result.push(Inline::Begin(tag));
+    state.push_element(|span| Inline::Begin(tag, span));
 }
 
 /// Given a char, return the styling tag it
@@ -229,7 +282,7 @@ fn char_to_tag(c: char) -> Option<Tag> {
     }
 }
 
-fn tag_to_string(tag: &Tag) -> String {
+pub(crate) fn tag_to_string(tag: &Tag) -> String {
     match *tag {
         Tag::Bold => "**",
         Tag::Italic => "//",
@@ -241,75 +294,84 @@ fn tag_to_string(tag: &Tag) -> String {
     .to_string()
 }
 
+lazy_static! {
+    // a prefix tree maping all the possible special escape characters
+    static ref ESCAPE_TRIE: TSTMap<EscapeChar> = tstmap! {
+        "alpha" => EscapeChar::Alpha,
+        "beta" => EscapeChar::Beta,
+        "gamma" => EscapeChar::GammaLower,
+        "Gamma" => EscapeChar::GammaUpper,
+        "delta" => EscapeChar::DeltaLower,
+        "Delta" => EscapeChar::DeltaUpper,
+        "epsilon" => EscapeChar::Epsilon,
+        "varepsilon" => EscapeChar::EpsilonVar,
+        "zeta" => EscapeChar::Zeta,
+        "eta" => EscapeChar::Eta,
+        "theta" => EscapeChar::ThetaLower,
+        "Theta" => EscapeChar::ThetaUpper,
+        "vartheta" => EscapeChar::ThetaVar,
+        "iota" => EscapeChar::Iota,
+        "kappa" => EscapeChar::Kappa,
+        "lambda" => EscapeChar::LambdaLower,
+        "Lambda" => EscapeChar::LambdaUpper,
+        "mu" => EscapeChar::Mu,
+        "nu" => EscapeChar::Nu,
+        "xi" => EscapeChar::XiLower,
+        "Xi" => EscapeChar::XiUpper,
+        "pi" => EscapeChar::PiLower,
+        "Pi" => EscapeChar::PiUpper,
+        "rho" => EscapeChar::Rho,
+        "varrho" => EscapeChar::RhoVar,
+        "sigma" => EscapeChar::SigmaLower,
+        "Sigma" => EscapeChar::SigmaUpper,
+        "tau" => EscapeChar::Tau,
+        "upsilon" => EscapeChar::UpsilonLower,
+        "Upsilon" => EscapeChar::UpsilonUpper,
+        "phi" => EscapeChar::PhiLower,
+        "Phi" => EscapeChar::PhiUpper,
+        "varphi" => EscapeChar::PhiVar,
+        "chi" => EscapeChar::Chi,
+        "psi" => EscapeChar::PsiLower,
+        "Psi" => EscapeChar::PsiUpper,
+        "omega" => EscapeChar::OmegaLower,
+        "Omega" => EscapeChar::OmegaUpper,
+
+        "endash" => EscapeChar::EnDash,
+        "emdash" => EscapeChar::EmDash,
+
+        "right" => EscapeChar::RightThin,
+        "Right" => EscapeChar::RightBold,
+        "left" => EscapeChar::LeftThin,
+        "Left" => EscapeChar::LeftBold,
+        "up" => EscapeChar::UpThin,
+        "Up" => EscapeChar::UpBold,
+        "down" => EscapeChar::DownThin,
+        "Down" => EscapeChar::DownBold,
+        // escaping lambda
+        "*" => EscapeChar::Asterisk,
+        "^" => EscapeChar::Caret,
+        "_" => EscapeChar::Underscore,
+        "/" => EscapeChar::ForwardSlash,
+        "\\" => EscapeChar::BackSlash,
+        "=" => EscapeChar::Equal,
+        "~" => EscapeChar::Tilde,
+        "|" => EscapeChar::Bar,
+        ":" => EscapeChar::Colon,
+        "tableflip" => EscapeChar::TableFlip,
+    };
+}
+
+/// The name of an escape character, i.e what comes after the backslash
+pub(crate) fn escape_to_string(escaped: &EscapeChar) -> String {
+    ESCAPE_TRIE
+        .iter()
+        .find(|(_, c)| *c == escaped)
+        .map(|(name, _)| name)
+        .expect("Every escape char has a name")
+}
+
 /// Handle potential escape characters and add them to the result vec
 fn escape(state: &mut ParserState) {
-    lazy_static! {
-        // a prefix tree maping all the possible special escape characters
-        static ref ESCAPE_TRIE: TSTMap<EscapeChar> = tstmap! {
-            "alpha" => EscapeChar::Alpha,
-            "beta" => EscapeChar::Beta,
-            "gamma" => EscapeChar::GammaLower,
-            "Gamma" => EscapeChar::GammaUpper,
-            "delta" => EscapeChar::DeltaLower,
-            "Delta" => EscapeChar::DeltaUpper,
-            "epsilon" => EscapeChar::Epsilon,
-            "varepsilon" => EscapeChar::EpsilonVar,
-            "zeta" => EscapeChar::Zeta,
-            "eta" => EscapeChar::Eta,
-            "theta" => EscapeChar::ThetaLower,
-            "Theta" => EscapeChar::ThetaUpper,
-            "vartheta" => EscapeChar::ThetaVar,
-            "iota" => EscapeChar::Iota,
-            "kappa" => EscapeChar::Kappa,
-            "lambda" => EscapeChar::LambdaLower,
-            "Lambda" => EscapeChar::LambdaUpper,
-            "mu" => EscapeChar::Mu,
-            "nu" => EscapeChar::Nu,
-            "xi" => EscapeChar::XiLower,
-            "Xi" => EscapeChar::XiUpper,
-            "pi" => EscapeChar::PiLower,
-            "Pi" => EscapeChar::PiUpper,
-            "rho" => EscapeChar::Rho,
-            "varrho" => EscapeChar::RhoVar,
-            "sigma" => EscapeChar::SigmaLower,
-            "Sigma" => EscapeChar::SigmaUpper,
-            "tau" => EscapeChar::Tau,
-            "upsilon" => EscapeChar::UpsilonLower,
-            "Upsilon" => EscapeChar::UpsilonUpper,
-            "phi" => EscapeChar::PhiLower,
-            "Phi" => EscapeChar::PhiUpper,
-            "varphi" => EscapeChar::PhiVar,
-            "chi" => EscapeChar::Chi,
-            "psi" => EscapeChar::PsiLower,
-            "Psi" => EscapeChar::PsiUpper,
-            "omega" => EscapeChar::OmegaLower,
-            "Omega" => EscapeChar::OmegaUpper,
-
-            "endash" => EscapeChar::EnDash,
-            "emdash" => EscapeChar::EmDash,
-
-            "right" => EscapeChar::RightThin,
-            "Right" => EscapeChar::RightBold,
-            "left" => EscapeChar::LeftThin,
-            "Left" => EscapeChar::LeftBold,
-            "up" => EscapeChar::UpThin,
-            "Up" => EscapeChar::UpBold,
-            "down" => EscapeChar::DownThin,
-            "Down" => EscapeChar::DownBold,
-            // escaping lambda
-            "*" => EscapeChar::Asterisk,
-            "^" => EscapeChar::Caret,
-            "_" => EscapeChar::Underscore,
-            "/" => EscapeChar::ForwardSlash,
-            "\\" => EscapeChar::BackSlash,
-            "=" => EscapeChar::Equal,
-            "~" => EscapeChar::Tilde,
-            "|" => EscapeChar::Bar,
-            ":" => EscapeChar::Colon,
-            "tableflip" => EscapeChar::TableFlip,
-        };
-    }
-
     // take all chars up until the next whitespace
     let word: String = state
         .chars
@@ -333,12 +395,10 @@ fn escape(state: &mut ParserState) {
         state.chars.next();
     }
 
-    state.push_buffer();
+    let escaped = ESCAPE_TRIE
+        .get(escape_char)
+        .expect("Failed to find escape char (should not be to happen)")
+        .to_owned();
 
-    state.result.push(Inline::Escaped(
-        ESCAPE_TRIE
-            .get(escape_char)
-            .expect("Failed to find escape char (should not be to happen)")
-            .to_owned(),
-    ));
+    state.push_element(|span| Inline::Escaped(escaped, span));
 }
@@ -7,59 +7,247 @@ use std::{
 
 pub mod block;
 pub mod inline;
+pub mod span;
 
 use block::next_block;
-use inline::parse_inline;
+use inline::parse_inline_mapped;
+pub use span::{MappedText, Position, Span};
+use serde::{Deserialize, Serialize};
 
 type LineNumber = usize;
 
 /// Describes the origin of a block
 /// i.e the line number and the document name or the name of
-/// the extension macro that created it
-#[derive(Debug, PartialEq, Clone)]
+/// the extension macro that created it, as well as the exact
+/// span of the source text.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Origin {
     pub line_number: usize,
     pub name: OriginName,
+    pub span: Span,
 }
 
 /// source texts can, apart from files, come from macro expansions inside
 /// of extensions, so we would need to keep track of who spawned them
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub enum OriginName {
     Filename(String),
-    Expansion(Box<OriginName>),
+    /// Text produced by an extension, the name of the extension
+    /// and the origin of the expression that called it
+    Expansion(String, Box<Origin>),
+}
+
+impl From<&str> for OriginName {
+    fn from(name: &str) -> Self {
+        OriginName::Filename(name.to_string())
+    }
+}
+
+impl From<String> for OriginName {
+    fn from(name: String) -> Self {
+        OriginName::Filename(name)
+    }
 }
 
 impl Origin {
-    pub fn new(line_number: usize, document_name: &str) -> Self {
+    pub fn new(line_number: usize, name: impl Into<OriginName>) -> Self {
         Origin {
             line_number,
-            name: OriginName::Filename(document_name.to_string()),
+            name: name.into(),
+            span: Span::default(),
+        }
+    }
+
+    /// Create an origin given the exact span of the source text
+    pub fn from_span(span: Span, name: impl Into<OriginName>) -> Self {
+        Origin {
+            line_number: span.start.line,
+            name: name.into(),
+            span,
+        }
+    }
+
+    /// The origin of a part of the source text that this origin spans,
+    /// e.g. the origin of an inline extension in a paragraph.
+    pub fn narrow(&self, span: Span) -> Self {
+        if span.is_unknown() {
+            return self.clone();
+        }
+
+        Origin {
+            line_number: span.start.line,
+            name: self.name.clone(),
+            span,
+        }
+    }
+
+    /// The name of a text produced by the given extension
+    /// when it was called at this origin
+    pub fn expand(&self, extension: &str) -> OriginName {
+        OriginName::Expansion(extension.to_string(), Box::new(self.clone()))
+    }
+
+    /// Follow the expansions back to the origin in the source file
+    pub fn root(&self) -> &Origin {
+        match &self.name {
+            OriginName::Filename(_) => self,
+            OriginName::Expansion(_, call_site) => call_site.root(),
+        }
+    }
+
+    /// The chain of expansions that produced this origin, starting with the
+    /// outermost one. Each entry is the name of an extension together with
+    /// the origin inside of the expansion where the next step happened.
+    pub fn backtrace(&self) -> Vec<(&str, &Origin)> {
+        let mut backtrace = vec![];
+        let mut origin = self;
+
+        while let OriginName::Expansion(extension, call_site) = &origin.name {
+            backtrace.push((extension.as_str(), origin));
+            origin = call_site;
         }
+
+        backtrace.reverse();
+        backtrace
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Block {
     Heading(Vec<Inline>, u8, Origin),
     Paragraph(Vec<Inline>, Origin),
     Metadata(String, String, Origin),
-    List(Vec<Inline>, Origin), // TODO, support ordered lists
-    Divider(Origin),           // a section divider, i.e a new page
+    List(List, Origin),
+    Table(Table, Origin),
+    Divider(Origin), // a section divider, i.e a new page
     Extension(String, Vec<String>, Origin),
 }
 
+/// A (possibly nested) list of items
+#[derive(Debug, PartialEq, Clone)]
+pub struct List {
+    pub kind: ListKind,
+    /// The number of the first item, which is 1 for unordered lists
+    pub start: usize,
+    pub items: Vec<ListItem>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ListKind {
+    Unordered,
+    Ordered,
+}
+
+/// A single list item, the text of the item
+/// followed by any lists nested beneath it
+#[derive(Debug, PartialEq, Clone)]
+pub struct ListItem {
+    pub text: Vec<Inline>,
+    pub children: Vec<List>,
+}
+
+impl fmt::Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tag = match self.kind {
+            ListKind::Unordered => "ul",
+            ListKind::Ordered => "ol",
+        };
+
+        match (self.kind, self.start) {
+            (ListKind::Ordered, start) if start != 1 => writeln!(f, "<ol start=\"{}\">", start)?,
+            _ => writeln!(f, "<{}>", tag)?,
+        }
+        for item in &self.items {
+            write!(
+                f,
+                "<li>{}",
+                item.text.iter().map(|i| i.to_string()).collect::<String>()
+            )?;
+            for child in &item.children {
+                write!(f, "\n{}", child)?;
+            }
+            writeln!(f, "</li>")?;
+        }
+        write!(f, "</{}>", tag)
+    }
+}
+
+/// The inline contents of a single table cell
+pub type Cell = Vec<Inline>;
+
+/// A table with an optional header row, where every
+/// row has the same number of cells as there are columns
+#[derive(Debug, PartialEq, Clone)]
+pub struct Table {
+    pub header: Option<Vec<Cell>>,
+    pub rows: Vec<Vec<Cell>>,
+    pub alignments: Vec<Alignment>,
+    pub caption: Option<Vec<Inline>>,
+    pub label: Option<String>,
+}
+
+/// The alignment of a table column
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Alignment {
+    Default,
+    Left,
+    Center,
+    Right,
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let row_to_string = |row: &Vec<Cell>| {
+            row.iter()
+                .map(|cell| cell.iter().map(|i| i.to_string()).collect::<String>())
+                .collect::<Vec<String>>()
+                .join(" | ")
+        };
+
+        writeln!(f, "<table>")?;
+        if let Some(header) = &self.header {
+            writeln!(f, "<header>{}</header>", row_to_string(header))?;
+        }
+        for row in &self.rows {
+            writeln!(f, "<row>{}</row>", row_to_string(row))?;
+        }
+        write!(f, "</table>")
+    }
+}
+
 impl Block {
     pub fn get_line_number(&self) -> LineNumber {
+        self.get_origin().line_number
+    }
+
+    pub fn get_origin(&self) -> &Origin {
         match self {
             Block::Heading(_, _, origin) => origin,
             Block::Paragraph(_, origin) => origin,
             Block::Metadata(_, _, origin) => origin,
             Block::List(_, origin) => origin,
+            Block::Table(_, origin) => origin,
+            Block::Divider(origin) => origin,
+            Block::Extension(_, _, origin) => origin,
+        }
+    }
+
+    pub fn get_span(&self) -> Span {
+        self.get_origin().span
+    }
+
+    pub(crate) fn get_origin_mut(&mut self) -> &mut Origin {
+        match self {
+            Block::Heading(_, _, origin) => origin,
+            Block::Paragraph(_, origin) => origin,
+            Block::Metadata(_, _, origin) => origin,
+            Block::List(_, origin) => origin,
+            Block::Table(_, origin) => origin,
             Block::Divider(origin) => origin,
             Block::Extension(_, _, origin) => origin,
         }
-        .line_number
     }
 }
 
@@ -78,6 +266,8 @@ impl fmt::Display for Block {
                     "{}\n",
                     text.iter().map(|i| i.to_string()).collect::<String>()
                 ),
+                Block::List(list, _) => format!("{}\n", list),
+                Block::Table(table, _) => format!("{}\n", table),
                 Block::Divider(_) => "</divider>".to_string(),
                 Block::Extension(name, args, _) =>
                     format!("<{name}, {args:?}>\n</{name}>", name = name, args = args),
@@ -87,13 +277,26 @@ impl fmt::Display for Block {
     }
 }
 
+/// An inline element and the span of the source text it was parsed from
 #[derive(Debug, PartialEq, Clone)]
 pub enum Inline {
-    Text(String),
-    Escaped(EscapeChar),
-    Begin(Tag),
-    End(Tag),
-    Extension(String, Vec<String>),
+    Text(String, Span),
+    Escaped(EscapeChar, Span),
+    Begin(Tag, Span),
+    End(Tag, Span),
+    Extension(String, Vec<String>, Span),
+}
+
+impl Inline {
+    pub fn get_span(&self) -> Span {
+        match self {
+            Inline::Text(_, span) => *span,
+            Inline::Escaped(_, span) => *span,
+            Inline::Begin(_, span) => *span,
+            Inline::End(_, span) => *span,
+            Inline::Extension(_, _, span) => *span,
+        }
+    }
 }
 
 impl fmt::Display for Inline {
@@ -102,11 +305,11 @@ impl fmt::Display for Inline {
             f,
             "{}",
             match self {
-                Inline::Text(contents) => contents.clone(),
-                Inline::Escaped(character) => character.to_string(),
-                Inline::Begin(tag) => format!("<{}>", tag.to_string()),
-                Inline::End(tag) => format!("</{}>", tag.to_string()),
-                Inline::Extension(name, args) => format!("<{}, {:?}/>", name, args),
+                Inline::Text(contents, _) => contents.clone(),
+                Inline::Escaped(character, _) => character.to_string(),
+                Inline::Begin(tag, _) => format!("<{}>", tag),
+                Inline::End(tag, _) => format!("</{}>", tag),
+                Inline::Extension(name, args, _) => format!("<{}, {:?}/>", name, args),
             }
         )
     }
@@ -282,16 +485,72 @@ impl fmt::Display for EscapeChar {
 
 type Lines<'a> = iter::Peekable<Zip<str::Lines<'a>, RangeFrom<LineNumber>>>;
 
-pub fn parse_doc(source: &str, doc_name: &str) -> Vec<Block> {
-    let mut lines = source.lines().zip(1..).peekable();
+/// The document that is being parsed, used to find
+/// the positions of its lines
+pub struct Source<'a> {
+    pub name: OriginName,
+    lines: Vec<&'a str>,
+    line_offsets: Vec<usize>,
+}
+
+impl<'a> Source<'a> {
+    fn new(text: &'a str, name: OriginName) -> Self {
+        let mut line_offsets = vec![0];
+        line_offsets.extend(
+            text.bytes()
+                .enumerate()
+                .filter(|(_, b)| *b == b'\n')
+                .map(|(i, _)| i + 1),
+        );
+
+        Source {
+            name,
+            lines: text.lines().collect(),
+            line_offsets,
+        }
+    }
+
+    /// The position of a byte offset within a line
+    pub fn position(&self, line_number: LineNumber, column: usize) -> Position {
+        let line = self.lines.get(line_number - 1).copied().unwrap_or("");
+        let column = column.min(line.len());
+
+        Position::new(
+            self.line_offsets[line_number - 1] + column,
+            line_number,
+            line[..column].chars().count() + 1,
+        )
+    }
+
+    /// The span of a range of lines, excluding the indentation of the first line
+    pub fn line_span(&self, first: LineNumber, last: LineNumber) -> Span {
+        let first_line = self.lines.get(first - 1).copied().unwrap_or("");
+        let last_line = self.lines.get(last - 1).copied().unwrap_or("");
+        let indentation = first_line.len() - first_line.trim_start().len();
+
+        Span::new(
+            self.position(first, indentation),
+            self.position(last, last_line.trim_end().len()),
+        )
+    }
+
+    fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+}
+
+pub fn parse_doc(source: &str, doc_name: impl Into<OriginName>) -> Vec<Block> {
+    let source_text = source;
+    let source = Source::new(source_text, doc_name.into());
+    let mut lines = source_text.lines().zip(1..).peekable();
     let mut text: Vec<(String, LineNumber)> = vec![];
     let mut blocks = vec![];
 
     loop {
-        if let Some(block) = next_block(&mut lines, doc_name) {
+        if let Some(block) = next_block(&mut lines, &source) {
             // start of a new block -> empty the buffer
             if !text.is_empty() {
-                blocks.append(&mut consume_text_buffer(&mut text, doc_name));
+                blocks.append(&mut consume_text_buffer(&mut text, &source));
             }
 
             // append the new block as well
@@ -310,7 +569,7 @@ pub fn parse_doc(source: &str, doc_name: &str) -> Vec<Block> {
         // finally, if the document is fully exhausted,
         // append the last paragraph and break the loop
         if !text.is_empty() {
-            blocks.append(&mut consume_text_buffer(&mut text, doc_name));
+            blocks.append(&mut consume_text_buffer(&mut text, &source));
         }
 
         break blocks;
@@ -318,21 +577,30 @@ pub fn parse_doc(source: &str, doc_name: &str) -> Vec<Block> {
 }
 
 /// Consumes the text buffer and returns a list of paragraph blocks
-fn consume_text_buffer(text: &mut Vec<(String, LineNumber)>, doc_name: &str) -> Vec<Block> {
+fn consume_text_buffer(text: &mut Vec<(String, LineNumber)>, source: &Source) -> Vec<Block> {
     let paragraphs = text
         .split(|(s, _)| s.trim().is_empty()) // get each paragraph
         .filter_map(|lines| {
-            // get the line number of the start of the paragraph
-            let (_, line_number) = lines.get(0)?;
+            // get the line number of the start and end of the paragraph
+            let (_, first) = lines.first()?;
+            let (_, last) = lines.last()?;
 
-            // remove the line numbers join into a string
-            let text = lines
-                .iter()
-                .map(|(l, _)| l.clone())
-                .collect::<Vec<String>>()
-                .join("\n");
+            // join the lines into a string, while remembering
+            // where each line is located in the source.
+            // Whitespace around a line has no meaning and is left out.
+            let mut text = MappedText::new();
+            for (i, (line, line_number)) in lines.iter().enumerate() {
+                if i > 0 {
+                    text.push_unmapped("\n");
+                }
+                let indentation = line.len() - line.trim_start().len();
+                text.push(line.trim(), source.position(*line_number, indentation));
+            }
 
-            let block = Block::Paragraph(parse_inline(&text), Origin::new(*line_number, doc_name));
+            let block = Block::Paragraph(
+                parse_inline_mapped(&text),
+                Origin::from_span(source.line_span(*first, *last), source.name.clone()),
+            );
             // remove empty paragraph blocks
             if is_empty(&block) {
                 None
@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+/// A position in a source text. Lines and columns start at 1
+/// and the column is counted in characters, not bytes.
+/// A position where the line is 0 is unknown,
+/// e.g. for nodes that were not produced by the parser.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Position {
+    pub offset: usize, // in bytes
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new(offset: usize, line: usize, column: usize) -> Self {
+        Position {
+            offset,
+            line,
+            column,
+        }
+    }
+
+    /// The position of the first character in a text
+    pub fn start() -> Self {
+        Position::new(0, 1, 1)
+    }
+}
+
+/// The range of the source text that a node was parsed from,
+/// the end position is exclusive.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Span { start, end }
+    }
+
+    /// The byte range of the span, useful for slicing the source text
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.start.offset..self.end.offset
+    }
+
+    /// Returns true if the span does not point to any known text
+    pub fn is_unknown(&self) -> bool {
+        self.start.line == 0
+    }
+}
+
+/// A text that has been assembled from different pieces
+/// of a source text (such as the lines of a paragraph),
+/// that remembers where each piece came from.
+pub struct MappedText {
+    pub text: String,
+    // the offset of the piece in the assembled text and where it starts in the source
+    pieces: Vec<(usize, Position)>,
+}
+
+impl MappedText {
+    pub fn new() -> Self {
+        MappedText {
+            text: String::new(),
+            pieces: vec![],
+        }
+    }
+
+    /// A text that is a single continuous piece of the source
+    pub fn from_str(text: &str, start: Position) -> Self {
+        let mut mapped = MappedText::new();
+        mapped.push(text, start);
+        mapped
+    }
+
+    /// Append a piece of the source that starts at a given position
+    pub fn push(&mut self, text: &str, start: Position) {
+        self.pieces.push((self.text.len(), start));
+        self.text.push_str(text);
+    }
+
+    /// Append text that is not a part of the source, like
+    /// the newline used to join two lines of a list item
+    pub fn push_unmapped(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+
+    /// Get the source position of a byte offset in the assembled text
+    pub fn position(&self, offset: usize) -> Position {
+        let (piece_offset, mut position) = match self
+            .pieces
+            .iter()
+            .take_while(|(piece_offset, _)| *piece_offset <= offset)
+            .last()
+        {
+            Some(piece) => *piece,
+            None => return Position::default(),
+        };
+
+        for c in self.text[piece_offset..offset].chars() {
+            if c == '\n' {
+                position.line += 1;
+                position.column = 1;
+            } else {
+                position.column += 1;
+            }
+        }
+        position.offset += offset - piece_offset;
+        position
+    }
+
+    /// Get the source span of a byte range in the assembled text
+    pub fn span(&self, start: usize, end: usize) -> Span {
+        Span::new(self.position(start), self.position(end))
+    }
+}
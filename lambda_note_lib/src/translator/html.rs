@@ -1,6 +1,8 @@
 use std::collections::{HashMap, HashSet};
 
-use super::{Block, DocumentState, Inline, OutputFormat, Tag, Translator};
+use super::template::{render_builtin, TemplateValues};
+use super::{Block, DocumentState, Heading, Inline, LabelKind, OutputFormat, Tag, Translator};
+use crate::{Alignment, Cell, List, ListKind, Origin, Table};
 
 /// A translator that transpiles into HTML code
 pub struct Html;
@@ -13,25 +15,28 @@ impl Translator for Html {
     fn block(&self, state: &mut DocumentState, block: Block) -> Option<String> {
         match block {
             Block::Heading(_, lvl, _) => {
+                let heading = state.translate_heading(&block);
                 Some(format!(
-                    "<h{level}>{text}</h{level}>",
-                    text = state.translate_content(&block),
+                    "<h{level} id=\"{id}\">{text}</h{level}>",
+                    text = numbered_heading(state, &heading, &heading.content),
+                    id = self.escape_str(&heading.id),
                     level = if lvl > 6 { 6 } else { lvl },
-                    
                 ))
             }
             Block::Divider(_) => Some("<hr/>".to_string()),
             Block::Paragraph(_, _) => Some(format!("<p>{}</p>", state.translate_content(&block))),
+            Block::List(list, origin) => Some(list_to_html(state, &list, &origin)),
+            Block::Table(table, origin) => Some(table_to_html(state, &table, &origin)),
             _ => None,
         }
     }
 
     fn inline(&self, inline: &Inline) -> String {
         match inline {
-            Inline::Begin(tag) => format!("<{}>", tag_to_string(tag)),
-            Inline::End(tag) => format!("</{}>", tag_to_string(tag)),
-            Inline::Escaped(escaped) => escaped.to_string(),
-            Inline::Text(content) => self.escape_str(content),
+            Inline::Begin(tag, _) => format!("<{}>", tag_to_string(tag)),
+            Inline::End(tag, _) => format!("</{}>", tag_to_string(tag)),
+            Inline::Escaped(escaped, _) => escaped.to_string(),
+            Inline::Text(content, _) => self.escape_str(content),
             _ => panic!("Failed to translate inline element {:?}", inline),
         }
     }
@@ -44,51 +49,195 @@ impl Translator for Html {
         imports: &HashSet<String>,
         metadata: &HashMap<String, String>,
     ) -> String {
-        
-        let mut import_str = String::new();
-        for import in imports {
-            import_str.push_str(import);
-            import_str.push('\n');
+        let values = TemplateValues {
+            content,
+            top,
+            bottom,
+            imports,
+            metadata,
+        };
+        render_builtin(TEMPLATE, &values, &|raw| self.escape_str(raw))
+    }
+
+    fn escape_str(&self, raw: &str) -> String {
+        raw.chars()
+            .map(|c| match c {
+                '&' => "&amp;".to_string(),
+                '<' => "&lt;".to_string(),
+                '>' => "&gt;".to_string(),
+                '"' => "&quot;".to_string(),
+                '\'' => "&#39;".to_string(),
+                c => c.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// The text of a heading, such as its content, with its section number if the document asks for it
+pub(crate) fn numbered_heading(state: &DocumentState, heading: &Heading, text: &str) -> String {
+    match &heading.number {
+        Some(number) if state.section_numbers() => format!(
+            "<span class=\"section-number\">{}</span> {}",
+            number, text
+        ),
+        _ => text.to_string(),
+    }
+}
+
+/// The list of footnotes, each with a link back to where it is referred to
+pub(crate) fn footnotes_to_html(footnotes: &[(usize, String)]) -> String {
+    let mut output = String::from("<section class=\"footnotes\">\n<ol>\n");
+    for (number, text) in footnotes {
+        output.push_str(&format!(
+            "<li id=\"fn-{0}\" value=\"{0}\">{1} \
+            <a class=\"footnote-back\" href=\"#fnref-{0}\">↩</a></li>\n",
+            number, text
+        ));
+    }
+    output.push_str("</ol>\n</section>");
+    output
+}
+
+fn list_to_html(state: &mut DocumentState, list: &List, origin: &Origin) -> String {
+    let tag = match list.kind {
+        ListKind::Unordered => "ul",
+        ListKind::Ordered => "ol",
+    };
+
+    let mut output = match (list.kind, list.start) {
+        (ListKind::Ordered, start) if start != 1 => format!("<ol start=\"{}\">\n", start),
+        _ => format!("<{}>\n", tag),
+    };
+    for item in &list.items {
+        output.push_str("<li>");
+        output.push_str(&state.translate_inlines(&item.text, origin));
+        for child in &item.children {
+            output.push('\n');
+            output.push_str(&list_to_html(state, child, origin));
         }
+        output.push_str("</li>\n");
+    }
+    output.push_str(&format!("</{}>", tag));
+    output
+}
+
+fn table_to_html(state: &mut DocumentState, table: &Table, origin: &Origin) -> String {
+    let mut output = match &table.label {
+        Some(label) => format!("<table id=\"{}\">\n", state.escape_str(label)),
+        None => "<table>\n".to_string(),
+    };
+
+    // tables with a caption or a label are numbered, like in LaTeX
+    let number = match state.counters.get(&LabelKind::Table) {
+        Some(number) if table.caption.is_some() || table.label.is_some() => {
+            Some(format!("Table {}", number))
+        }
+        _ => None,
+    };
+    let caption = table
+        .caption
+        .as_ref()
+        .map(|caption| state.translate_inlines(caption, origin));
+
+    match (number, caption) {
+        (Some(number), Some(caption)) => {
+            output.push_str(&format!("<caption>{}: {}</caption>\n", number, caption))
+        }
+        (Some(caption), None) | (None, Some(caption)) => {
+            output.push_str(&format!("<caption>{}</caption>\n", caption))
+        }
+        (None, None) => (),
+    }
+
+    if let Some(header) = &table.header {
+        output.push_str("<thead>\n");
+        output.push_str(&row_to_html(state, header, &table.alignments, "th", origin));
+        output.push_str("</thead>\n");
+    }
+
+    output.push_str("<tbody>\n");
+    for row in &table.rows {
+        output.push_str(&row_to_html(state, row, &table.alignments, "td", origin));
+    }
+    output.push_str("</tbody>\n</table>");
+    output
+}
+
+fn row_to_html(
+    state: &mut DocumentState,
+    row: &[Cell],
+    alignments: &[Alignment],
+    cell_tag: &str,
+    origin: &Origin,
+) -> String {
+    let mut output = String::from("<tr>");
+    for (cell, alignment) in row.iter().zip(alignments) {
+        let style = match alignment {
+            Alignment::Default => "",
+            Alignment::Left => " style=\"text-align:left\"",
+            Alignment::Center => " style=\"text-align:center\"",
+            Alignment::Right => " style=\"text-align:right\"",
+        };
+        output.push_str(&format!(
+            "<{tag}{style}>{content}</{tag}>",
+            tag = cell_tag,
+            style = style,
+            content = state.translate_inlines(cell, origin)
+        ));
+    }
+    output.push_str("</tr>\n");
+    output
+}
 
-        format!(
-            r#"
+fn tag_to_string(tag: &Tag) -> String {
+    match *tag {
+        Tag::Bold => "strong",
+        Tag::Italic => "em",
+        Tag::Underline => "ins",
+        Tag::Superscript => "sup",
+        Tag::Subscript => "sub",
+        Tag::Strikethrough => "del",
+    }
+    .to_string()
+}
+
+const TEMPLATE: &str = r#"
 <!DOCTYPE html>
-<html lang="{language}">
+<html lang="{{language|en}}">
 <head>
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    {imports}
+    {{imports}}
     <link rel="preconnect" href="https://fonts.googleapis.com">
     <link rel="preconnect" href="https://fonts.gstatic.com" crossorigin>
     <link href="https://fonts.googleapis.com/css2?family=Fira+Code&family=PT+Serif:ital,wght@0,400;0,700;1,400;1,700&family=Poppins:wght@700&display=swap" rel="stylesheet">
     <style>
-        html {{
+        html {
             scroll-behavior: smooth;
-        }}
+        }
         
-        *::-moz-selection, *::selection {{
+        *::-moz-selection, *::selection {
             background: #E2705B;
             color:white;
-        }}
+        }
 
-        h1, h2, h3, h4, h5, h6 {{
+        h1, h2, h3, h4, h5, h6 {
             font-family: 'Poppins', sans-serif;
-        }}
+        }
 
-        h1 {{
+        h1 {
             font-size: 1.7rem;
-        }}
+        }
 
-        h2 {{
+        h2 {
             font-size: 1.5rem;
-        }}
+        }
 
-        body, p {{
+        body, p {
             font-family: 'PT Serif', serif;
-        }}
+        }
 
-        .content {{
+        .content {
             box-sizing: border-box;
             padding:0.8rem;
             margin-top:2rem;
@@ -96,80 +245,50 @@ impl Translator for Html {
             margin-left:auto;
             margin-right:auto;
             max-width:750px;
-        }}
+        }
 
-        img {{
+        img {
             max-width:100%;
-        }}
+        }
 
-        pre {{
+        pre {
             border-radius:0.3rem;
             padding:0.8rem;
             font-family: 'Fira Code', monospace;
             box-sizing:border-box;
             font-size:0.9rem;
             overflow-x:auto;
-        }}
+        }
+
+        .figure, .figcaption {
+            display:block;
+        }
 
-        hr {{
+        hr {
             margin-top:2rem;
             margin-bottom:2rem;
-        }}
+        }
 
-        @media print {{
-            hr {{ 
+        @media print {
+            hr { 
                 page-break-after: always;
                 visibility: hidden;
                 margin:0;
                 padding:0;
-            }}
+            }
 
-            .content {{
+            .content {
                 padding:0;
-            }}
-        }}
+            }
+        }
     </style>
-    <title>{title}</title>
+    <title>{{title|Document}}</title>
 </head>
 <body>
     <div class="content">
-{top}
-{content}
-{bottom}
+{{top}}
+{{content}}
+{{bottom}}
 </div>
 </body>
-</html>"#,
-            imports = import_str,
-            top = top,
-            bottom = bottom,
-            language = metadata.get("language").unwrap_or(&"en".to_string()),
-            title = metadata.get("title").unwrap_or(&"Document".to_string()),
-            content = content,
-        )
-    }
-
-    fn escape_str(&self, raw: &str) -> String {
-        raw.chars()
-            .map(|c| match c {
-                '&' => "&amp;".to_string(),
-                '<' => "&lt;".to_string(),
-                '>' => "&gt;".to_string(),
-                '"' => "&quot;".to_string(),
-                '\'' => "&#39;".to_string(),
-                c => c.to_string(),
-            })
-            .collect()
-    }
-}
-
-fn tag_to_string(tag: &Tag) -> String {
-    match *tag {
-        Tag::Bold => "strong",
-        Tag::Italic => "em",
-        Tag::Underline => "ins",
-        Tag::Superscript => "sup",
-        Tag::Subscript => "sub",
-        Tag::Strikethrough => "del",
-    }
-    .to_string()
-}
+</html>"#;
@@ -1,17 +1,26 @@
-use crate::{Translator, Html, DocumentState, Block, Inline, OutputFormat};
-use regex::{Regex, Captures};
+use super::template::{load_partial, Template, TemplateError, TemplateValues};
+use super::web_preview::annotate_source_line;
+use crate::{Translator, Html, DocumentState, Block, Inline, OutputFormat, Origin};
 use std::collections::{HashSet, HashMap};
+use std::path::{Path, PathBuf};
 
 /// A Html translator that allows you to specify your own template
 /// The string template is just an ordinary html document where
 /// you can inject the 'top', 'content', 'bottom' and 'import' blocks as well
 /// as any metadata fields using this syntax:
 /// {{content}}
-/// {{foo}} will add the value of the foo metadata field, or if not found an empty string
+/// {{foo}} will add the escaped value of the foo metadata field, or if not found an empty string
 /// {{foo| no value found }} You can add a vertical bar and speciify the fallback value yourself.  
+/// {{raw foo}} adds the value without escaping it, for metadata that is html code
+/// {{#if author}}<p>{{author}}</p>{{else}}...{{/if}} is only added if author is set
+/// {{#each tags}}<li>{{this}}</li>{{/each}} loops over a comma separated field
+/// {{> header.html}} includes another template, relative to the partial directory
+/// With `include_preview_code` the page can be reloaded and scrolled
+/// like a `WebPreview`, and every block is tagged with its source line.
 pub struct HtmlTemplate {
     html_translator: Html,
-    template: String,
+    template: Result<Template, TemplateError>,
+    partial_directory: Option<PathBuf>,
     preview: bool, // if true, js code to update the document
                    // via a message will be added 
 }
@@ -20,13 +29,29 @@ impl HtmlTemplate {
     pub fn new(template: &str, include_preview_code: bool) -> Self {
         Self {
             html_translator: Html,
-            template: template.to_string(),
+            template: Template::parse(template),
+            partial_directory: None,
             preview: include_preview_code,
         }
     }
-}
 
+    /// Look for partials in a directory, such as the one of the template file,
+    /// rather than in the working directory
+    pub fn with_partial_directory(mut self, directory: &Path) -> Self {
+        self.partial_directory = Some(directory.to_path_buf());
+        self
+    }
 
+    /// Check that the template is valid and that all of its partials can be found.
+    /// An invalid template falls back to the default template of `Html`.
+    pub fn check(&self) -> Result<(), TemplateError> {
+        let directory = self.partial_directory.as_deref();
+        self.template
+            .as_ref()
+            .map_err(Clone::clone)?
+            .check(&|name| load_partial(directory, name))
+    }
+}
 
 impl Translator for HtmlTemplate {
     fn block(&self, state: &mut DocumentState, block: Block) -> Option<String> {
@@ -45,9 +70,6 @@ impl Translator for HtmlTemplate {
         imports: &HashSet<String>,
         metadata: &HashMap<String, String>,
     ) -> String {
-        // a regex to match something on the form {{foo}} or {{foo|default}}
-        let re = Regex::new(r"\{\{\s?(\w+)\s?(?:\|(.*))?\}\}").unwrap();
-
         // generate a string from the bottom of the doc and include the
         // preview js scripts if need be
         let mut bottom_string = String::from(bottom); 
@@ -55,21 +77,27 @@ impl Translator for HtmlTemplate {
             bottom_string.push_str(PREVIEW_MSG_LISTENER);
         }
 
-        // replace all "template-tags"
-        re.replace_all(&self.template, |caps: &Captures| {
-            caps.get(1)
-                .and_then(|m| match m.as_str() {
-                    "top" => Some(top.to_string()),
-                    "content" => Some(content.to_string()),
-                    "bottom" => Some(bottom_string.clone()),
-                    "imports" =>Some(set_to_str(imports)),
-                    field => metadata.get(field).cloned(),
-                })
-                // falback to the specified default
-                .or_else(|| caps.get(2).map(|m| m.as_str().to_string()))
-                // or in the worst case replace the tag with an empty string
-                .unwrap_or_else(|| String::from(""))
-        }).to_string()
+        let template = match &self.template {
+            Ok(template) => template,
+            Err(_) => {
+                return self.html_translator
+                    .template(content, top, &bottom_string, imports, metadata)
+            }
+        };
+
+        let values = TemplateValues {
+            content,
+            top,
+            bottom: &bottom_string,
+            imports,
+            metadata,
+        };
+        let directory = self.partial_directory.as_deref();
+        template.render(
+            &values,
+            &|raw| self.escape_str(raw),
+            &|name| load_partial(directory, name),
+        )
     }
 
     fn escape_str(&self, raw: &str) -> String {
@@ -79,33 +107,32 @@ impl Translator for HtmlTemplate {
     fn output_format(&self) -> OutputFormat {
         OutputFormat::Html
     }
-}
 
-/// helper to convert the import hashset to a String
-fn set_to_str(set: &HashSet<String>) -> String {
-    let mut s = String::new();
-    for x in set {
-        s.push_str(x);
+    fn annotate_block(&self, output: String, origin: &Origin) -> String {
+        if self.preview {
+            annotate_source_line(output, origin)
+        } else {
+            output
+        }
     }
-    s
 }
 
 const PREVIEW_MSG_LISTENER: &str = r#"
 <script>
 // any references to local files will be broken due to
-// the browsers origin policy. To fix this, we host a server at
-// localhost:5432, and use the following code updates all href and src
-// tags in the page to point to the local server instead of directly to the local file.
+// the browsers origin policy. To fix this, the page is served by a local
+// server, and the following code updates all href and src tags that point
+// to a local file to point to the same server instead, whatever its port is.
 document.addEventListener("DOMContentLoaded", function () {
     document.body.querySelectorAll('[href], [src]').forEach(node => {
         if (typeof node.href !== 'undefined') {
-            if (node.href.startsWith('http')) return;
-            node.href = 'http://localhost:5432/' + node.href.replace("file:///", "");
+            if (!node.href.startsWith('file:')) return;
+            node.href = '/' + node.href.replace("file:///", "");
         }
 
         if (typeof node.src !== 'undefined') {
-            if (node.src.startsWith('http')) return;
-            node.src = 'http://localhost:5432/' + node.src.replace("file:///", "");
+            if (!node.src.startsWith('file:')) return;
+            node.src = '/' + node.src.replace("file:///", "");
         }
     });
 });
@@ -117,6 +144,21 @@ window.addEventListener('message', event => {
         console.log(event.data); 
         if (event.data === "reload")
             window.location.reload()
+        else if (event.data && event.data.command === "scroll")
+            scrollToLine(event.data.line)
 }); 
+
+// scroll to the last block that starts at or before the given line
+function scrollToLine(line) {
+    let target = null;
+    document.querySelectorAll('[data-source-line]').forEach(node => {
+        if (parseInt(node.dataset.sourceLine) <= line)
+            target = node;
+    });
+    if (target)
+        target.scrollIntoView({ block: "start" });
+    else
+        window.scrollTo(0, 0);
+}
 </script>
 "#;
@@ -0,0 +1,355 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{Block, DocumentState, Inline, OutputFormat, Translator};
+use crate::parser::inline::{escape_to_string, tag_to_string};
+use crate::{parse_doc, Alignment, Cell, Diagnostic, List, ListKind, Origin, Span, Table};
+
+/// A translator that outputs λnote source. All extensions are expanded,
+/// so the result is a document that only uses the native syntax and
+/// the extensions that can not be expanded any further, such as images.
+/// Use `format_document` to pretty print a document without expanding it.
+pub struct LambdaNote;
+
+impl Translator for LambdaNote {
+    fn output_format(&self) -> OutputFormat {
+        OutputFormat::LambdaNote
+    }
+
+    fn block(&self, state: &mut DocumentState, block: Block) -> Option<String> {
+        match block {
+            Block::Heading(text, level, origin) => {
+                Some(heading(&state.translate_inlines(&text, &origin), level))
+            }
+            Block::Paragraph(_, _) => Some(state.translate_content(&block)),
+            Block::List(list, origin) => Some(list_to_string(&list, 0, &mut |text| {
+                state.translate_inlines(text, &origin)
+            })),
+            Block::Table(table, origin) => Some(table_to_string(&table, &mut |text| {
+                state.translate_inlines(text, &origin)
+            })),
+            Block::Divider(_) => Some(DIVIDER.to_string()),
+            _ => None,
+        }
+    }
+
+    fn inline(&self, inline: &Inline) -> String {
+        inline_to_string(inline)
+    }
+
+    fn template(
+        &self,
+        content: &str,
+        _top: &str,
+        _bottom: &str,
+        _imports: &HashSet<String>,
+        metadata: &HashMap<String, String>,
+    ) -> String {
+        // the metadata has already been consumed, so it is placed at the top
+        let mut fields: Vec<(&String, &String)> = metadata.iter().collect();
+        fields.sort();
+        let fields: Vec<(&str, &str)> = fields
+            .into_iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+
+        let mut output = metadata_to_string(&fields);
+        if !output.is_empty() {
+            output.push_str("\n\n");
+        }
+        output.push_str(content.trim());
+        output.push('\n');
+        output
+    }
+
+    fn escape_str(&self, raw: &str) -> String {
+        raw.chars()
+            .map(|c| match c {
+                '\\' | '|' | '*' | '/' | '=' | '_' | '^' | '~' => format!("\\{}", c),
+                c => c.to_string(),
+            })
+            .collect()
+    }
+}
+
+const DIVIDER: &str = "===";
+
+/// Print a parsed document as normalized λnote source, without
+/// expanding any extensions or metadata fields.
+pub fn print_document(blocks: &[Block]) -> String {
+    let mut output = String::new();
+    let mut i = 0;
+
+    while i < blocks.len() {
+        if !output.is_empty() {
+            output.push_str("\n\n");
+        }
+
+        // consecutive metadata fields are kept together and aligned
+        let fields: Vec<(&str, &str)> = blocks[i..]
+            .iter()
+            .map_while(|block| match block {
+                Block::Metadata(key, value, _) => Some((key.as_str(), value.as_str())),
+                _ => None,
+            })
+            .collect();
+
+        if !fields.is_empty() {
+            output.push_str(&metadata_to_string(&fields));
+            i += fields.len();
+            continue;
+        }
+
+        output.push_str(&match &blocks[i] {
+            Block::Heading(text, level, _) => heading(&inlines_to_string(text), *level),
+            Block::Paragraph(text, _) => inlines_to_string(text),
+            Block::List(list, _) => list_to_string(list, 0, &mut inlines_to_string),
+            Block::Table(table, _) => table_to_string(table, &mut inlines_to_string),
+            Block::Divider(_) => DIVIDER.to_string(),
+            Block::Extension(name, arguments, _) => block_extension(name, arguments),
+            Block::Metadata(_, _, _) => unreachable!(),
+        });
+        i += 1;
+    }
+
+    output.push('\n');
+    output
+}
+
+/// Pretty print λnote source. The result is parsed again and compared to the
+/// original document, if they differ an error is returned that points at
+/// the first block that would have been changed by the formatting.
+pub fn format_document(source: &str, doc_name: &str) -> Result<String, Box<Diagnostic>> {
+    let original = parse_doc(source, doc_name);
+    let formatted = print_document(&original);
+    let reparsed = parse_doc(&formatted, doc_name);
+
+    let changed = first_difference(&original, &reparsed);
+
+    match changed {
+        None => Ok(formatted),
+        Some(index) => {
+            let mut diagnostic = Diagnostic::error(
+                "This part of the document can not be formatted without changing its meaning",
+            )
+            .with_code("unstable-format");
+            if let Some(block) = original.get(index).or_else(|| original.last()) {
+                diagnostic = diagnostic.with_origin(block.get_origin());
+            }
+            Err(Box::new(diagnostic))
+        }
+    }
+}
+
+/// The index of the first block where two documents differ, ignoring where
+/// the blocks came from. Returns None if the documents are the same.
+pub(crate) fn first_difference(a: &[Block], b: &[Block]) -> Option<usize> {
+    without_origins(a)
+        .iter()
+        .zip(without_origins(b).iter())
+        .position(|(a, b)| a != b)
+        .or_else(|| (a.len() != b.len()).then_some(a.len().min(b.len())))
+}
+
+/// The blocks of a document without any origins or spans,
+/// so that documents can be compared based on their structure alone
+fn without_origins(blocks: &[Block]) -> Vec<Block> {
+    blocks
+        .iter()
+        .cloned()
+        .map(|mut block| {
+            *block.get_origin_mut() = Origin::new(0, "");
+            match &mut block {
+                Block::Heading(text, _, _) | Block::Paragraph(text, _) => clear_spans(text),
+                Block::List(list, _) => clear_list_spans(list),
+                Block::Table(table, _) => {
+                    let rows = table.header.iter_mut().chain(table.rows.iter_mut());
+                    rows.flatten()
+                        .chain(table.caption.iter_mut())
+                        .for_each(|cell| clear_spans(cell));
+                }
+                _ => (),
+            }
+            block
+        })
+        .collect()
+}
+
+fn clear_list_spans(list: &mut List) {
+    for item in list.items.iter_mut() {
+        clear_spans(&mut item.text);
+        item.children.iter_mut().for_each(clear_list_spans);
+    }
+}
+
+fn clear_spans(text: &mut [Inline]) {
+    for inline in text.iter_mut() {
+        match inline {
+            Inline::Text(_, span)
+            | Inline::Escaped(_, span)
+            | Inline::Begin(_, span)
+            | Inline::End(_, span)
+            | Inline::Extension(_, _, span) => *span = Span::default(),
+        }
+    }
+}
+
+fn heading(text: &str, level: u8) -> String {
+    format!("{} {}", "#".repeat(level as usize), text)
+}
+
+/// Metadata fields with their equal signs aligned
+fn metadata_to_string(fields: &[(&str, &str)]) -> String {
+    let width = fields
+        .iter()
+        .map(|(key, _)| key.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    fields
+        .iter()
+        .map(|(key, value)| format!(":: {:width$} = {}", key, value, width = width))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+pub(crate) fn inlines_to_string(text: &[Inline]) -> String {
+    text.iter().map(inline_to_string).collect()
+}
+
+fn inline_to_string(inline: &Inline) -> String {
+    match inline {
+        Inline::Text(text, _) => text.clone(),
+        Inline::Escaped(escaped, _) => format!("\\{}", escape_to_string(escaped)),
+        Inline::Begin(tag, _) | Inline::End(tag, _) => tag_to_string(tag),
+        Inline::Extension(name, arguments, _) => inline_extension(name, arguments),
+    }
+}
+
+/// An inline extension, bars and commas inside of the arguments are escaped
+pub(crate) fn inline_extension(name: &str, arguments: &[String]) -> String {
+    let mut output = format!("|{}", name);
+    for argument in arguments {
+        output.push(',');
+        output.push_str(&argument.replace('|', "\\|").replace(',', "\\,"));
+    }
+    output.push('|');
+    output
+}
+
+/// A block extension where the first argument is the body of the block
+pub(crate) fn block_extension(name: &str, arguments: &[String]) -> String {
+    let (body, arguments) = match arguments.split_first() {
+        Some((body, arguments)) => (body.as_str(), arguments),
+        None => ("", arguments),
+    };
+
+    // the block ends at the first line that starts with as many dashes
+    // as the divider, so it has to be longer than any such line in the body
+    let longest_dashes = body
+        .lines()
+        .map(|line| line.trim_start().chars().take_while(|c| *c == '-').count())
+        .max()
+        .unwrap_or(0);
+    let divider = "-".repeat(longest_dashes.max(3) + 1);
+
+    let mut output = format!("{} {}", divider, name);
+    for argument in arguments {
+        output.push_str(", ");
+        output.push_str(argument);
+    }
+    output.push('\n');
+
+    if !body.is_empty() {
+        output.push_str(body);
+        output.push('\n');
+    }
+    output.push_str(&divider);
+    output
+}
+
+fn list_to_string(
+    list: &List,
+    indentation: usize,
+    inlines: &mut dyn FnMut(&[Inline]) -> String,
+) -> String {
+    let mut lines = vec![];
+    for (i, item) in list.items.iter().enumerate() {
+        let marker = match list.kind {
+            ListKind::Unordered => "-".to_string(),
+            ListKind::Ordered => format!("{}.", list.start + i),
+        };
+
+        lines.push(format!(
+            "{}{} {}",
+            " ".repeat(indentation),
+            marker,
+            inlines(&item.text)
+        ));
+
+        // nested lists are aligned with the text of the item
+        for child in &item.children {
+            lines.push(list_to_string(
+                child,
+                indentation + marker.len() + 1,
+                inlines,
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+fn table_to_string(table: &Table, inlines: &mut dyn FnMut(&[Inline]) -> String) -> String {
+    let mut print_row = |row: &[Cell]| row.iter().map(|cell| inlines(cell)).collect::<Vec<_>>();
+    let header = table.header.as_ref().map(|row| print_row(row));
+    let rows: Vec<Vec<String>> = table.rows.iter().map(|row| print_row(row)).collect();
+
+    // every column is as wide as its widest cell, but at least three
+    // characters wide to leave room for the alignment in the separator
+    let mut widths = vec![3; table.alignments.len()];
+    for row in header.iter().chain(rows.iter()) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let row_to_string = |row: &[String]| {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(widths.iter().zip(&table.alignments))
+            .map(|(cell, (width, alignment))| match alignment {
+                Alignment::Right => format!("{:>width$}", cell, width = width),
+                Alignment::Center => format!("{:^width$}", cell, width = width),
+                _ => format!("{:width$}", cell, width = width),
+            })
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let separator: Vec<String> = widths
+        .iter()
+        .zip(&table.alignments)
+        .map(|(width, alignment)| match alignment {
+            Alignment::Default => "-".repeat(*width),
+            Alignment::Left => format!(":{}", "-".repeat(width - 1)),
+            Alignment::Right => format!("{}:", "-".repeat(width - 1)),
+            Alignment::Center => format!(":{}:", "-".repeat(width - 2)),
+        })
+        .collect();
+
+    let mut lines = vec![];
+    if let Some(header) = &header {
+        lines.push(row_to_string(header));
+    }
+    lines.push(format!("| {} |", separator.join(" | ")));
+    lines.extend(rows.iter().map(|row| row_to_string(row)));
+
+    let caption = table.caption.as_ref().map(|caption| inlines(caption));
+    match (caption, &table.label) {
+        (Some(caption), Some(label)) => lines.push(format!(": {} {{#{}}}", caption, label)),
+        (Some(caption), None) => lines.push(format!(": {}", caption)),
+        (None, Some(label)) => lines.push(format!(": {{#{}}}", label)),
+        (None, None) => (),
+    }
+
+    lines.join("\n")
+}
@@ -1,6 +1,10 @@
 use std::collections::{HashMap, HashSet};
 
-use crate::{Block, DocumentState, EscapeChar, Inline, OutputFormat, Tag, Translator};
+use super::template::{render_builtin, TemplateValues};
+use crate::{
+    Alignment, Block, Cell, DocumentState, EscapeChar, Inline, List, ListKind, Origin, OutputFormat,
+    Table, Tag, Translator,
+};
 /// A translator that transpiles into LaTeX code.
 pub struct Latex;
 
@@ -11,19 +15,28 @@ impl Translator for Latex {
 
     fn block(&self, state: &mut DocumentState, block: Block) -> Option<String> {
         match block {
-            Block::Heading(_, lvl, _) => Some(heading(state.translate_content(&block), lvl)),
+            Block::Heading(_, lvl, _) => {
+                let translated = state.translate_heading(&block);
+                Some(format!(
+                    "{}\\label{{{}}}",
+                    heading(translated.content, lvl),
+                    translated.id
+                ))
+            }
             Block::Divider(_) => Some("\\newpage".to_string()),
             Block::Paragraph(_, _) => Some(format!("{}\n\n", state.translate_content(&block))),
+            Block::List(list, origin) => Some(list_to_latex(state, &list, 0, &origin)),
+            Block::Table(table, origin) => Some(table_to_latex(state, &table, &origin)),
             _ => None,
         }
     }
 
     fn inline(&self, inline: &Inline) -> String {
         match inline {
-            Inline::Begin(tag) => format!("\\{}{{", tag_to_string(tag)),
-            Inline::End(_) => "}".to_string(),
-            Inline::Escaped(escaped) => escape_char(escaped),
-            Inline::Text(content) => self.escape_str(content),
+            Inline::Begin(tag, _) => format!("\\{}{{", tag_to_string(tag)),
+            Inline::End(_, _) => "}".to_string(),
+            Inline::Escaped(escaped, _) => escape_char(escaped),
+            Inline::Text(content, _) => self.escape_str(content),
             _ => panic!("Failed to translate inline element {:?}", inline),
         }
     }
@@ -36,25 +49,14 @@ impl Translator for Latex {
         imports: &HashSet<String>,
         metadata: &HashMap<String, String>,
     ) -> String {
-        format!(
-            r#"
-\documentclass[12pt]{{{class}}}
-\usepackage[utf8]{{inputenc}}
-{imports}
-\begin{{document}}
-{top}
-{content}
-{bottom}
-\end{{document}}
-            "#,
-            imports = imports.iter().fold(String::new(), |acc, s| acc + s + "\n"),
-            top = top,
-            bottom = bottom,
-            class = metadata
-                .get("documentclass")
-                .unwrap_or(&"article".to_string()),
-            content = content,
-        )
+        let values = TemplateValues {
+            content,
+            top,
+            bottom,
+            imports,
+            metadata,
+        };
+        render_builtin(TEMPLATE, &values, &|raw| self.escape_str(raw))
     }
 
     fn escape_str(&self, raw: &str) -> String {
@@ -67,11 +69,12 @@ impl Translator for Latex {
                 '_' => "\\_".to_string(),
                 '{' => "\\{".to_string(),
                 '}' => "\\}".to_string(),
-                '>' => "\\textgreater".to_string(),
-                '<' => "\\textless".to_string(),
-                '~' => "\\textasciitilde".to_string(),
-                '^' => "\\textasciicircum".to_string(),
-                '\\' => "\\textbackslash".to_string(),
+                // the braces end the command, so that it is not merged with the text after
+                '>' => "\\textgreater{}".to_string(),
+                '<' => "\\textless{}".to_string(),
+                '~' => "\\textasciitilde{}".to_string(),
+                '^' => "\\textasciicircum{}".to_string(),
+                '\\' => "\\textbackslash{}".to_string(),
                 c => c.to_string(),
             })
             .collect()
@@ -88,6 +91,89 @@ fn heading(text: String, level: u8) -> String {
     }
 }
 
+/// `depth` is the number of ordered lists that the list is nested in,
+/// which tells which counter LaTeX numbers the items with
+fn list_to_latex(state: &mut DocumentState, list: &List, depth: usize, origin: &Origin) -> String {
+    let environment = match list.kind {
+        ListKind::Unordered => "itemize",
+        ListKind::Ordered => "enumerate",
+    };
+
+    let mut output = format!("\\begin{{{}}}\n", environment);
+    let depth = match list.kind {
+        ListKind::Ordered if list.start != 1 && depth < 4 => {
+            let counter = ["enumi", "enumii", "enumiii", "enumiv"][depth];
+            output.push_str(&format!("\\setcounter{{{}}}{{{}}}\n", counter, list.start - 1));
+            depth + 1
+        }
+        ListKind::Ordered => depth + 1,
+        ListKind::Unordered => depth,
+    };
+    for item in &list.items {
+        output.push_str("\\item ");
+        output.push_str(&state.translate_inlines(&item.text, origin));
+        output.push('\n');
+        for child in &item.children {
+            output.push_str(&list_to_latex(state, child, depth, origin));
+            output.push('\n');
+        }
+    }
+    output.push_str(&format!("\\end{{{}}}", environment));
+    output
+}
+
+fn table_to_latex(state: &mut DocumentState, table: &Table, origin: &Origin) -> String {
+    let columns: String = table
+        .alignments
+        .iter()
+        .map(|alignment| match alignment {
+            Alignment::Default | Alignment::Left => 'l',
+            Alignment::Center => 'c',
+            Alignment::Right => 'r',
+        })
+        .collect();
+
+    let mut output = format!(
+        "\\begin{{table}}[h]\n\\centering\n\\begin{{tabular}}{{{}}}\n\\hline\n",
+        columns
+    );
+
+    if let Some(header) = &table.header {
+        output.push_str(&row_to_latex(state, header, origin));
+        output.push_str("\\hline\n");
+    }
+
+    for row in &table.rows {
+        output.push_str(&row_to_latex(state, row, origin));
+    }
+    output.push_str("\\hline\n\\end{tabular}\n");
+
+    if let Some(caption) = &table.caption {
+        output.push_str(&format!(
+            "\\caption{{{}}}\n",
+            state.translate_inlines(caption, origin)
+        ));
+    } else if table.label.is_some() {
+        // a label without a caption would refer to the section instead
+        output.push_str("\\caption{}\n");
+    }
+
+    if let Some(label) = &table.label {
+        output.push_str(&format!("\\label{{{}}}\n", label));
+    }
+
+    output.push_str("\\end{table}");
+    output
+}
+
+fn row_to_latex(state: &mut DocumentState, row: &[Cell], origin: &Origin) -> String {
+    let cells: Vec<String> = row
+        .iter()
+        .map(|cell| state.translate_inlines(cell, origin))
+        .collect();
+    format!("{} \\\\\n", cells.join(" & "))
+}
+
 fn tag_to_string(tag: &Tag) -> String {
     match *tag {
         Tag::Bold => "textbf",
@@ -166,3 +252,14 @@ fn escape_char(c: &EscapeChar) -> String {
     }
     .to_string()
 }
+
+const TEMPLATE: &str = r#"
+\documentclass[12pt]{{{documentclass|article}}}
+\usepackage[utf8]{inputenc}
+{{imports}}
+\begin{document}
+{{top}}
+{{content}}
+{{bottom}}
+\end{document}
+            "#;
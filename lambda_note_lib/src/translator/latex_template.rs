@@ -0,0 +1,92 @@
+use super::template::{load_partial, Template, TemplateError, TemplateValues};
+use super::{Block, DocumentState, Inline, Latex, OutputFormat, Translator};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// A LaTeX translator that allows you to specify your own template, such as
+/// the preamble of a thesis. Works like `HtmlTemplate`:
+/// {{content}}, {{top}}, {{bottom}} and {{imports}} inserts the parts of the document
+/// {{foo}} will add the value of the foo metadata field, or if not found an empty string
+/// {{foo|no value found}} You can add a vertical bar and specify the fallback value yourself.
+/// {{#if foo}}, {{#each foo}} and {{> partial.tex}} work as in `HtmlTemplate`.
+/// Metadata values are escaped so that they are typeset as written,
+/// use {{raw foo}} to insert a value that is LaTeX code as it is.
+pub struct LatexTemplate {
+    latex_translator: Latex,
+    template: Result<Template, TemplateError>,
+    partial_directory: Option<PathBuf>,
+}
+
+impl LatexTemplate {
+    pub fn new(template: &str) -> Self {
+        Self {
+            latex_translator: Latex,
+            template: Template::parse(template),
+            partial_directory: None,
+        }
+    }
+
+    /// Look for partials in a directory rather than in the working directory
+    pub fn with_partial_directory(mut self, directory: &Path) -> Self {
+        self.partial_directory = Some(directory.to_path_buf());
+        self
+    }
+
+    /// Check that the template is valid and that all of its partials can be found.
+    /// An invalid template falls back to the default template of `Latex`.
+    pub fn check(&self) -> Result<(), TemplateError> {
+        let directory = self.partial_directory.as_deref();
+        self.template
+            .as_ref()
+            .map_err(Clone::clone)?
+            .check(&|name| load_partial(directory, name))
+    }
+}
+
+impl Translator for LatexTemplate {
+    fn block(&self, state: &mut DocumentState, block: Block) -> Option<String> {
+        self.latex_translator.block(state, block)
+    }
+
+    fn inline(&self, inline: &Inline) -> String {
+        self.latex_translator.inline(inline)
+    }
+
+    fn template(
+        &self,
+        content: &str,
+        top: &str,
+        bottom: &str,
+        imports: &HashSet<String>,
+        metadata: &HashMap<String, String>,
+    ) -> String {
+        let template = match &self.template {
+            Ok(template) => template,
+            Err(_) => {
+                return self
+                    .latex_translator
+                    .template(content, top, bottom, imports, metadata)
+            }
+        };
+
+        let values = TemplateValues {
+            content,
+            top,
+            bottom,
+            imports,
+            metadata,
+        };
+        let directory = self.partial_directory.as_deref();
+        template.render(&values, &|raw| self.escape_str(raw), &|name| {
+            load_partial(directory, name)
+        })
+    }
+
+    fn escape_str(&self, raw: &str) -> String {
+        self.latex_translator.escape_str(raw)
+    }
+
+    fn output_format(&self) -> OutputFormat {
+        OutputFormat::Latex
+    }
+}
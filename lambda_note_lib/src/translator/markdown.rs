@@ -0,0 +1,163 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{Block, DocumentState, Inline, OutputFormat, Tag, Translator};
+use crate::{Alignment, Cell, List, ListKind, Origin, Table};
+
+/// A translator that transpiles into GitHub flavored Markdown.
+/// Styles that Markdown lacks, such as subscript, fall back to inline HTML.
+pub struct Markdown;
+
+impl Translator for Markdown {
+    fn output_format(&self) -> OutputFormat {
+        OutputFormat::Markdown
+    }
+
+    fn block(&self, state: &mut DocumentState, block: Block) -> Option<String> {
+        match block {
+            Block::Heading(_, level, _) => Some(format!(
+                "{} {}",
+                "#".repeat(level.min(6) as usize),
+                state.translate_heading(&block).content
+            )),
+            Block::Divider(_) => Some("---".to_string()),
+            Block::Paragraph(_, _) => Some(state.translate_content(&block)),
+            Block::List(list, origin) => Some(list_to_markdown(state, &list, 0, &origin)),
+            Block::Table(table, origin) => Some(table_to_markdown(state, &table, &origin)),
+            _ => None,
+        }
+    }
+
+    fn inline(&self, inline: &Inline) -> String {
+        match inline {
+            Inline::Begin(tag, _) => tag_to_string(tag, true),
+            Inline::End(tag, _) => tag_to_string(tag, false),
+            Inline::Escaped(escaped, _) => self.escape_str(&escaped.to_string()),
+            Inline::Text(content, _) => self.escape_str(content),
+            _ => panic!("Failed to translate inline element {:?}", inline),
+        }
+    }
+
+    fn template(
+        &self,
+        content: &str,
+        top: &str,
+        bottom: &str,
+        _imports: &HashSet<String>,
+        _metadata: &HashMap<String, String>,
+    ) -> String {
+        let mut output = String::new();
+        for part in [top, content, bottom].iter() {
+            if !part.trim().is_empty() {
+                output.push_str(part.trim());
+                output.push_str("\n\n");
+            }
+        }
+        output.truncate(output.trim_end().len());
+        output.push('\n');
+        output
+    }
+
+    fn escape_str(&self, raw: &str) -> String {
+        raw.chars()
+            .map(|c| match c {
+                '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '~' => format!("\\{}", c),
+                c => c.to_string(),
+            })
+            .collect()
+    }
+}
+
+fn list_to_markdown(
+    state: &mut DocumentState,
+    list: &List,
+    indentation: usize,
+    origin: &Origin,
+) -> String {
+    let mut lines = vec![];
+    for (i, item) in list.items.iter().enumerate() {
+        let marker = match list.kind {
+            ListKind::Unordered => "-".to_string(),
+            ListKind::Ordered => format!("{}.", list.start + i),
+        };
+
+        lines.push(format!(
+            "{}{} {}",
+            " ".repeat(indentation),
+            marker,
+            state.translate_inlines(&item.text, origin)
+        ));
+
+        // nested lists need to be aligned with the text of the item
+        for child in &item.children {
+            lines.push(list_to_markdown(
+                state,
+                child,
+                indentation + marker.len() + 1,
+                origin,
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+fn table_to_markdown(state: &mut DocumentState, table: &Table, origin: &Origin) -> String {
+    let mut lines = vec![];
+
+    // Markdown has no labels, but an anchor works just as well for links
+    if let Some(label) = &table.label {
+        lines.push(format!("<a id=\"{}\"></a>", label));
+        lines.push(String::new());
+    }
+
+    // a header row is required, so use an empty one if the table lacks it
+    let empty_header = vec![vec![]; table.alignments.len()];
+    let header = table.header.as_ref().unwrap_or(&empty_header);
+    lines.push(row_to_markdown(state, header, origin));
+
+    let separator: Vec<&str> = table
+        .alignments
+        .iter()
+        .map(|alignment| match alignment {
+            Alignment::Default => "---",
+            Alignment::Left => ":--",
+            Alignment::Center => ":-:",
+            Alignment::Right => "--:",
+        })
+        .collect();
+    lines.push(format!("| {} |", separator.join(" | ")));
+
+    for row in &table.rows {
+        lines.push(row_to_markdown(state, row, origin));
+    }
+
+    // neither are there captions, so it is placed in its own paragraph
+    if let Some(caption) = &table.caption {
+        lines.push(String::new());
+        lines.push(format!("*{}*", state.translate_inlines(caption, origin)));
+    }
+
+    lines.join("\n")
+}
+
+fn row_to_markdown(state: &mut DocumentState, row: &[Cell], origin: &Origin) -> String {
+    let cells: Vec<String> = row
+        .iter()
+        .map(|cell| state.translate_inlines(cell, origin))
+        .collect();
+    format!("| {} |", cells.join(" | "))
+}
+
+fn tag_to_string(tag: &Tag, begin: bool) -> String {
+    match (tag, begin) {
+        (Tag::Bold, _) => "**",
+        (Tag::Italic, _) => "*",
+        (Tag::Strikethrough, _) => "~~",
+        (Tag::Underline, true) => "<ins>",
+        (Tag::Underline, false) => "</ins>",
+        (Tag::Superscript, true) => "<sup>",
+        (Tag::Superscript, false) => "</sup>",
+        (Tag::Subscript, true) => "<sub>",
+        (Tag::Subscript, false) => "</sub>",
+    }
+    .to_string()
+}
@@ -1,19 +1,38 @@
 mod html;
+mod html_template;
+mod lambda_note;
 mod latex;
+mod latex_template;
+mod markdown;
+mod template;
 mod web_preview;
-mod html_template;
 
+use crate::bibtex::Bibliography;
+use crate::cache;
 use crate::extensions::{get_native_extensions, Context, Extension, ExtensionVariant};
-use crate::{parse_doc, Block, Inline, Origin, Tag};
+use crate::{parse_doc, parse_inline, Block, Diagnostic, Inline, Origin, OriginName, Tag};
+use lazy_static::lazy_static;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::hash_map::RandomState,
     collections::{HashMap, HashSet},
+    hash::{BuildHasher, Hasher},
+    path::PathBuf,
     rc::Rc,
 };
 
-pub use html_template::HtmlTemplate;
 pub use html::Html;
+pub(crate) use html::{footnotes_to_html, numbered_heading};
+pub use html_template::HtmlTemplate;
+pub use lambda_note::{format_document, print_document, LambdaNote};
+pub(crate) use lambda_note::{
+    block_extension, first_difference, inline_extension, inlines_to_string,
+};
 pub use latex::Latex;
+pub use latex_template::LatexTemplate;
+pub use markdown::Markdown;
+pub use template::TemplateError;
 pub use web_preview::WebPreview;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
@@ -22,6 +41,64 @@ pub enum OutputFormat {
     LambdaNote,
     Html,
     Latex,
+    Markdown,
+}
+
+/// The placeholder for deferred output is the key of the document and the index
+/// of the output between these characters, from the private use area. The key is
+/// random, so that a document can not write a placeholder of its own.
+const DEFERRED_START: char = '\u{E000}';
+const DEFERRED_END: char = '\u{E001}';
+
+/// Output that is produced once the rest of the document has been translated
+type Deferred = Box<dyn FnOnce(&mut DocumentState) -> String>;
+
+/// A heading of the document, collected while it is translated
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heading {
+    pub level: u8,
+    /// An id that is unique in the document, used to link to the heading
+    pub id: String,
+    /// The text of the heading in the output format
+    pub content: String,
+    /// The text without styles or the output of extensions, which can not be
+    /// repeated elsewhere in the document since it may contain links and footnotes
+    pub text: String,
+    /// The section number, such as 1.2.1, like the numbers in LaTeX
+    /// only the first three levels of headings are numbered
+    pub number: Option<String>,
+    pub origin: Origin,
+}
+
+/// The kinds of elements that are numbered and can be referred to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LabelKind {
+    Figure,
+    Equation,
+    Table,
+    Section,
+}
+
+impl LabelKind {
+    /// The name of the kind, as written before the number in a reference
+    pub fn name(&self) -> &'static str {
+        match self {
+            LabelKind::Figure => "Figure",
+            LabelKind::Equation => "Equation",
+            LabelKind::Table => "Table",
+            LabelKind::Section => "Section",
+        }
+    }
+}
+
+/// A labelled element that can be referred to with the ref extension
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub kind: LabelKind,
+    pub number: String,
+    pub origin: Origin,
+    /// Where the same label is given to other elements
+    pub duplicates: Vec<Origin>,
 }
 
 pub trait Translator {
@@ -45,20 +122,48 @@ pub trait Translator {
     fn escape_str(&self, raw: &str) -> String;
 
     fn output_format(&self) -> OutputFormat;
+
+    /// Called with the output of every block, including block extensions,
+    /// so that a translator can tag it with where it came from in the source
+    fn annotate_block(&self, output: String, _origin: &Origin) -> String {
+        output
+    }
 }
 pub struct DocumentState {
     pub metadata: HashMap<String, String>,
-    pub warnings: Vec<String>,
-    pub errors: Vec<String>,
+    pub diagnostics: Vec<Diagnostic>,
     pub extensions: HashMap<String, Rc<dyn Extension>>,
     translator: Rc<dyn Translator>,
     pub imports: HashSet<String>,
     pub top: String,
     pub bottom: String,
+    /// The files that have been included with the include extension
+    pub included_files: Vec<PathBuf>,
+    /// The files that are being included right now,
+    /// together with how much their headings are moved
+    pub(crate) include_stack: Vec<(PathBuf, isize)>,
+    /// All headings translated so far, in the order of the document
+    pub headings: Vec<Heading>,
+    section_counters: [usize; 3],
+    /// The labelled figures, equations, tables and headings, by their label
+    pub labels: HashMap<String, Label>,
+    counters: HashMap<LabelKind, usize>,
+    /// The cited keys, in the order they are first cited
+    pub citations: Vec<String>,
+    pub(crate) bibliography: Option<Rc<Bibliography>>,
+    /// The footnotes that have not been placed in the document yet, with their numbers
+    footnotes: Vec<(usize, String)>,
+    footnote_count: usize,
+    deferred: Vec<Option<Deferred>>,
+    deferred_key: u64,
+    /// How many calls to `translate_no_template` that are in progress
+    nesting: usize,
     is_safe: bool,
+    /// Where the responses of foreign extensions are cached, nothing is cached if it is None
+    pub(crate) cache_directory: Option<PathBuf>,
 }
 
-impl<'a> DocumentState {
+impl DocumentState {
     /// create a new document state that
     /// only contains the prelude of native extensions
     pub fn new<T: 'static + Translator>(translator: T) -> Self {
@@ -69,9 +174,22 @@ impl<'a> DocumentState {
             bottom: String::new(),
             extensions: get_native_extensions(),
             translator: Rc::new(translator),
+            included_files: vec![],
+            include_stack: vec![],
+            headings: vec![],
+            section_counters: [0; 3],
+            labels: HashMap::new(),
+            counters: HashMap::new(),
+            citations: vec![],
+            bibliography: None,
+            footnotes: vec![],
+            footnote_count: 0,
+            deferred: vec![],
+            deferred_key: RandomState::new().build_hasher().finish(),
+            nesting: 0,
             is_safe: false,
-            warnings: vec![],
-            errors: vec![],
+            cache_directory: None,
+            diagnostics: vec![],
         }
     }
 
@@ -79,19 +197,57 @@ impl<'a> DocumentState {
         self.is_safe = safe;
     }
 
+    pub fn safe_mode(&self) -> bool {
+        self.is_safe
+    }
+
+    /// Cache the responses of foreign extensions in a directory,
+    /// so that they are not called again with the same arguments.
+    /// Entries that have not been used for 30 days are removed.
+    pub fn set_cache_directory(&mut self, directory: Option<PathBuf>) {
+        if let Some(directory) = &directory {
+            cache::prune(directory);
+        }
+        self.cache_directory = directory;
+    }
+
     pub fn import(&mut self, import: &str) {
         self.imports.insert(import.to_string());
     }
 
+    /// Report an error or a warning
+    pub fn add_diagnostic(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// All the errors found so far
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.is_error())
+    }
+
+    /// All the warnings found so far
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| !d.is_error())
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
     /// Given the current document state translate the source text
     /// and mutate the state if a new extensions or metadata fields
     /// are found
     pub fn translate(&mut self, source: &str, doc_name: &str) -> String {
         let result = self.translate_no_template(source, doc_name);
-        // TODO: the translator should not be cloned,
-        // there is def. a better way to do this.
+        self.apply_template(&result)
+    }
+
+    /// Wrap content from `translate_no_template` in the template of the
+    /// translator, together with the top, bottom, imports and metadata
+    /// of the document
+    pub fn apply_template(&self, content: &str) -> String {
         self.translator.template(
-            &result,
+            content,
             &self.top,
             &self.bottom,
             &self.imports,
@@ -99,13 +255,146 @@ impl<'a> DocumentState {
         )
     }
 
-    pub fn translate_no_template(&mut self, source: &str, doc_name: &str) -> String {
+    /// Translate the source text without the template of the translator.
+    /// Extensions that translate text of their own should name it after
+    /// their call site, i.e `ctx.origin.expand(&self.name())`, so
+    /// that diagnostics can be traced back to the source file.
+    pub fn translate_no_template(
+        &mut self,
+        source: &str,
+        doc_name: impl Into<OriginName>,
+    ) -> String {
+        self.nesting += 1;
+        let output = self.translate_blocks(parse_doc(source, doc_name));
+        self.nesting -= 1;
+
+        // extensions translate text of their own, the document is
+        // only finished when the outermost translation is
+        if self.nesting == 0 {
+            // footnotes that have not been placed by the footnotes extension end up last
+            let footnotes = self.footnote_list();
+            let output = if footnotes.is_empty() {
+                output
+            } else {
+                format!("{}{}\n", output, footnotes)
+            };
+            self.resolve_deferred(&output)
+        } else {
+            output
+        }
+    }
+
+    /// Translate a text of inline elements, without wrapping it in a paragraph
+    pub fn translate_inline_text(
+        &mut self,
+        source: &str,
+        doc_name: impl Into<OriginName>,
+    ) -> String {
+        let origin = Origin::new(1, doc_name);
+        self.translate_inlines(&parse_inline(source), &origin)
+    }
+
+    /// Number a footnote, the text is placed in the document by `footnote_list`
+    pub fn add_footnote(&mut self, text: String) -> usize {
+        self.footnote_count += 1;
+        self.footnotes.push((self.footnote_count, text));
+        self.footnote_count
+    }
+
+    /// The footnotes added since the last list of footnotes. Only HTML and
+    /// Markdown have lists, LaTeX places each footnote where it is referred to.
+    pub fn footnote_list(&mut self) -> String {
+        let footnotes = std::mem::take(&mut self.footnotes);
+        if footnotes.is_empty() {
+            return String::new();
+        }
+
+        match self.get_output_format() {
+            OutputFormat::Html => footnotes_to_html(&footnotes),
+            OutputFormat::Markdown => footnotes
+                .iter()
+                .map(|(number, text)| format!("[^{}]: {}\n", number, text))
+                .collect(),
+            OutputFormat::Latex | OutputFormat::LambdaNote => String::new(),
+        }
+    }
+
+    /// Produce part of the output once the whole document has been translated,
+    /// such as a table of contents that lists the headings further down.
+    /// Returns a placeholder that is replaced with the output in the end.
+    pub fn defer(&mut self, output: impl FnOnce(&mut DocumentState) -> String + 'static) -> String {
+        self.deferred.push(Some(Box::new(output)));
+        format!(
+            "{}{:x}:{}{}",
+            DEFERRED_START,
+            self.deferred_key,
+            self.deferred.len() - 1,
+            DEFERRED_END
+        )
+    }
+
+    /// The second pass, replace the placeholders from `defer` with their output
+    fn resolve_deferred(&mut self, text: &str) -> String {
+        if self.deferred.is_empty() {
+            return text.to_string();
+        }
+
+        let output = self.replace_placeholders(text);
+        self.deferred.clear();
+        output
+    }
+
+    /// Replace placeholders, including the ones in the output of the deferred closures
+    fn replace_placeholders(&mut self, text: &str) -> String {
         let mut output = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find(DEFERRED_START) {
+            let end = match rest[start..].find(DEFERRED_END) {
+                Some(end) => start + end,
+                None => break,
+            };
+            let after_start = start + DEFERRED_START.len_utf8();
 
-        for block in parse_doc(source, doc_name) {
+            // the characters may also be in the text of the document, which is kept as it is
+            let index = rest[after_start..end]
+                .split_once(':')
+                .filter(|(key, _)| u64::from_str_radix(key, 16) == Ok(self.deferred_key))
+                .and_then(|(_, index)| index.parse::<usize>().ok());
+            let index = match index {
+                Some(index) => index,
+                None => {
+                    output.push_str(&rest[..after_start]);
+                    rest = &rest[after_start..];
+                    continue;
+                }
+            };
+
+            output.push_str(&rest[..start]);
+            if let Some(deferred) = self.deferred.get_mut(index).and_then(Option::take) {
+                let deferred_output = deferred(self);
+                output.push_str(&self.replace_placeholders(&deferred_output));
+            }
+            rest = &rest[end + DEFERRED_END.len_utf8()..];
+        }
+        output.push_str(rest);
+        output
+    }
+
+    /// Translate blocks that have already been parsed
+    pub(crate) fn translate_blocks(&mut self, blocks: Vec<Block>) -> String {
+        let mut output = String::new();
+
+        for block in blocks {
             if let Some(s) = self.translate_block(block) {
                 output.push_str(&s);
                 output.push('\n');
+                // λnote and Markdown need an empty line to tell two blocks apart
+                if matches!(
+                    self.get_output_format(),
+                    OutputFormat::LambdaNote | OutputFormat::Markdown
+                ) {
+                    output.push('\n');
+                }
             }
         }
 
@@ -120,29 +409,51 @@ impl<'a> DocumentState {
         variant: ExtensionVariant,
         origin: &Origin,
     ) -> Option<String> {
+        let extension = match self.extensions.get(symbol).cloned() {
+            Some(extension) => extension,
+            None => {
+                self.add_diagnostic(
+                    Diagnostic::error(&format!("No extension found with the name of {}", symbol))
+                        .with_origin(origin)
+                        .with_code("unknown-extension")
+                        .with_help("new extensions can be added with |define, name, command|"),
+                );
+                return None;
+            }
+        };
 
-        let extension = self.extensions.get(symbol).cloned();
-        if extension.is_none() {
-            self.errors
-                .push(format!("No extension found with the name of {}", symbol));
-        }
-        let extension = extension?;
-
-        if self.is_safe && !extension.is_safe() {
-            self.errors.push(format!(
-                "Extension {} is not trusted in safe mode", extension.name()));
-            return None;
-        }
-
-        if variant == ExtensionVariant::Block && !extension.supports_block() {
-            self.errors.push(format!(
-                "Extension {} does not support block expressions", extension.name()));
-            return None;
-        }
+        let problem = if self.is_safe && !extension.is_safe() {
+            Some((
+                format!("Extension {} is not trusted in safe mode", extension.name()),
+                "unsafe-extension",
+            ))
+        } else if variant == ExtensionVariant::Block && !extension.supports_block() {
+            Some((
+                format!(
+                    "Extension {} does not support block expressions",
+                    extension.name()
+                ),
+                "unsupported-block",
+            ))
+        } else if variant == ExtensionVariant::Inline && !extension.supports_inline() {
+            Some((
+                format!(
+                    "Extension {} does not support inline expressions",
+                    extension.name()
+                ),
+                "unsupported-inline",
+            ))
+        } else {
+            None
+        };
 
-        if variant == ExtensionVariant::Inline && !extension.supports_inline() {
-            self.errors.push(format!(
-                "Extension {} does not support inline expressions", extension.name()));
+        if let Some((message, code)) = problem {
+            self.add_diagnostic(
+                Diagnostic::error(&message)
+                    .with_origin(origin)
+                    .with_extension(&extension.name())
+                    .with_code(code),
+            );
             return None;
         }
 
@@ -156,7 +467,8 @@ impl<'a> DocumentState {
 
     /// Translate a block and return the translated text as an option
     fn translate_block(&mut self, block: Block) -> Option<String> {
-        match block {
+        let origin = block.get_origin().clone();
+        let output = match block {
             Block::Extension(symbol, args, origin) => {
                 self.translate_extension(&symbol, args, ExtensionVariant::Block, &origin)
             }
@@ -164,28 +476,198 @@ impl<'a> DocumentState {
                 self.add_metadata(symbol, value);
                 None
             }
+            // tables are numbered if they have a caption or a label, like in LaTeX
+            Block::Table(mut table, origin) => {
+                table.label = table.label.filter(|label| self.check_label(label, &origin));
+                if table.caption.is_some() || table.label.is_some() {
+                    let number = self.next_number(LabelKind::Table);
+                    if let Some(label) = &table.label {
+                        self.add_label(label, LabelKind::Table, &number.to_string(), &origin);
+                    }
+                }
+                self.translator.clone().block(self, Block::Table(table, origin))
+            }
             // The translation of all other blocks will be delegated
             // to the translator for the current output format
             _ => self.translator.clone().block(self, block),
+        };
+        // the lines of included files are not lines of the document
+        if !self.include_stack.is_empty() {
+            return output;
+        }
+        output.map(|output| self.translator.clone().annotate_block(output, &origin))
+    }
+
+    /// Translate the text of a heading and give it an id, the heading is
+    /// added to the headings of the document. The id is made from the text
+    /// of the heading, unless it is given explicitly as in `# Intro {#intro}`.
+    pub fn translate_heading(&mut self, block: &Block) -> Heading {
+        let (text, level, origin) = match block {
+            Block::Heading(text, level, origin) => (text, *level, origin),
+            _ => panic!("Can only translate headings"),
+        };
+
+        let (text, custom_id) = split_heading_id(text);
+        let custom_id = custom_id.filter(|id| self.check_label(id, origin));
+        let is_labelled = custom_id.is_some();
+        let content = self.translate_inlines(&text, origin);
+        let is_taken = |id: &str| self.headings.iter().any(|heading| heading.id == id);
+
+        let id = match custom_id {
+            Some(id) => {
+                if is_taken(&id) {
+                    let message = format!("There is already a heading with the id {}", id);
+                    self.add_diagnostic(
+                        Diagnostic::warning(&message)
+                            .with_origin(origin)
+                            .with_code("duplicate-id"),
+                    );
+                }
+                id
+            }
+            None => {
+                let slug = slug(&plain_text(&text));
+                let mut id = slug.clone();
+                let mut duplicates = 0;
+                while is_taken(&id) {
+                    duplicates += 1;
+                    id = format!("{}-{}", slug, duplicates);
+                }
+                id
+            }
+        };
+
+        // like LaTeX, a section resets the numbers of its subsections
+        let depth = level as usize;
+        let is_numbered = (1..=self.section_counters.len()).contains(&depth);
+        if is_numbered {
+            self.section_counters[depth - 1] += 1;
+            for counter in &mut self.section_counters[depth..] {
+                *counter = 0;
+            }
+        }
+        // deeper headings are referred to by the number of their section
+        let numbers: Vec<String> = self.section_counters[..depth.min(self.section_counters.len())]
+            .iter()
+            .map(|counter| counter.to_string())
+            .collect();
+        let number = numbers.join(".");
+
+        if is_labelled {
+            match self.labels.get_mut(&id) {
+                // already warned about as a duplicate heading id
+                Some(label) if label.kind == LabelKind::Section => {
+                    label.duplicates.push(origin.clone())
+                }
+                _ => self.add_label(&id, LabelKind::Section, &number, origin),
+            }
+        }
+        let number = Some(number).filter(|_| is_numbered);
+
+        let heading = Heading {
+            level,
+            id,
+            content,
+            text: plain_text(&text).trim().to_string(),
+            number,
+            origin: origin.clone(),
+        };
+        self.headings.push(heading.clone());
+        heading
+    }
+
+    /// The number of the next figure, equation or table. Everything that
+    /// LaTeX numbers is counted, so that the numbers are the same in all formats.
+    pub fn next_number(&mut self, kind: LabelKind) -> usize {
+        let counter = self.counters.entry(kind).or_insert(0);
+        *counter += 1;
+        *counter
+    }
+
+    /// If a custom id or label only has letters, digits, `:`, `_` and `-`. It is written
+    /// as it is in the output, like in `\label` in LaTeX, so others are warned about.
+    pub(crate) fn check_label(&mut self, label: &str, origin: &Origin) -> bool {
+        let is_valid = label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, ':' | '_' | '-'));
+        if !is_valid {
+            let message = format!("The label {} is not used", label);
+            self.add_diagnostic(
+                Diagnostic::warning(&message)
+                    .with_origin(origin)
+                    .with_code("invalid-label")
+                    .with_help("labels can only contain letters, digits, `:`, `_` and `-`"),
+            );
+        }
+        is_valid
+    }
+
+    /// Let a numbered element be referred to by a label, warns if the label is taken
+    pub fn add_label(&mut self, label: &str, kind: LabelKind, number: &str, origin: &Origin) {
+        if let Some(existing) = self.labels.get_mut(label) {
+            existing.duplicates.push(origin.clone());
+            self.add_diagnostic(
+                Diagnostic::warning(&format!("The label {} is already used", label))
+                    .with_origin(origin)
+                    .with_code("duplicate-label"),
+            );
+            return;
+        }
+
+        self.labels.insert(
+            label.to_string(),
+            Label {
+                kind,
+                number: number.to_string(),
+                origin: origin.clone(),
+                duplicates: vec![],
+            },
+        );
+    }
+
+    /// The file of the document itself, also when called from an included file.
+    /// It is the name the document was given, which is not a file if read from stdin.
+    pub(crate) fn document_file(&self, origin: &Origin) -> PathBuf {
+        match self.include_stack.first() {
+            Some((file, _)) => file.clone(),
+            None => match &origin.root().name {
+                OriginName::Filename(name) => PathBuf::from(name),
+                OriginName::Expansion(..) => PathBuf::new(),
+            },
         }
     }
 
+    /// If headings should be numbered in formats that do not do so on their own,
+    /// which is turned on with the metadata field `section_numbers = true`
+    pub fn section_numbers(&self) -> bool {
+        self.metadata
+            .get("section_numbers")
+            .is_some_and(|value| matches!(value.trim(), "true" | "yes" | "on"))
+    }
+
     fn translate_content(&mut self, block: &Block) -> String {
         let (text, origin) = match block {
             Block::Heading(text, _, origin) => (text, origin),
             Block::Paragraph(text, origin) => (text, origin),
             _ => panic!("Can not translate blocks without inline elements"),
         };
+        self.translate_inlines(text, origin)
+    }
+
+    /// Translate a sequence of inline elements, such as the text of a list item
+    fn translate_inlines(&mut self, text: &[Inline], origin: &Origin) -> String {
         text.iter()
             .map(|i| self.translate_inline(i, origin))
             .collect()
     }
 
     fn translate_inline(&mut self, inline: &Inline, origin: &Origin) -> String {
-        if let Inline::Extension(symbol, args) = inline {
+        if let Inline::Extension(symbol, args, span) = inline {
+            // point at the extension itself rather than the entire block
+            let origin = origin.narrow(*span);
             return self
-                .translate_extension(symbol, args.clone(), ExtensionVariant::Inline, origin)
-                .unwrap_or_else(|| "".to_string());
+                .translate_extension(symbol, args.clone(), ExtensionVariant::Inline, &origin)
+                .unwrap_or_default();
         }
 
         self.translator.inline(inline)
@@ -200,3 +682,59 @@ impl<'a> DocumentState {
         self.translator.escape_str(data)
     }
 }
+
+/// Split the explicit id from the end of a heading, as in `# Intro {#intro}`
+fn split_heading_id(text: &[Inline]) -> (Vec<Inline>, Option<String>) {
+    lazy_static! {
+        static ref HEADING_ID: Regex = Regex::new(r"\s*\{#([^\s}]+)\}\s*$").unwrap();
+    }
+
+    let mut text = text.to_vec();
+    let id = match text.last_mut() {
+        Some(Inline::Text(last, _)) => {
+            let captures = HEADING_ID.captures(last);
+            let found = captures.map(|c| (c.get(0).unwrap().start(), c[1].to_string()));
+            found.map(|(start, id)| {
+                last.truncate(start);
+                id
+            })
+        }
+        _ => None,
+    };
+
+    if matches!(text.last(), Some(Inline::Text(last, _)) if last.is_empty()) {
+        text.pop();
+    }
+    (text, id)
+}
+
+/// The text of some inline elements without any styling or extensions
+fn plain_text(text: &[Inline]) -> String {
+    text.iter()
+        .map(|inline| match inline {
+            Inline::Text(text, _) => text.clone(),
+            Inline::Escaped(character, _) => character.to_string(),
+            _ => String::new(),
+        })
+        .collect()
+}
+
+/// Turn the text of a heading into an id that can be used in urls,
+/// i.e. "Getting started" becomes "getting-started"
+fn slug(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-') && !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "section".to_string()
+    } else {
+        slug.to_string()
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Partials may include other partials, but only this deep, so that a partial
+/// that includes itself does not go on forever
+const MAX_PARTIAL_DEPTH: usize = 16;
+
+/// A parsed template, used by the translators to wrap the translated content
+/// in a complete document. The syntax is:
+/// {{content}}, {{top}}, {{bottom}} and {{imports}} inserts the parts of the document
+/// {{foo}} inserts the escaped value of the foo metadata field, or an empty string
+/// {{foo|default}} falls back to a default value, which is inserted as it is
+/// {{raw foo}} inserts the value without escaping it
+/// {{#if foo}} ... {{else}} ... {{/if}} is rendered if foo is set and not empty
+/// {{#each foo}} {{this}} {{else}} ... {{/each}} loops over a comma separated value
+/// {{> header.html}} includes another template file, a partial
+/// {{! a comment }} is left out of the output
+/// Anything between double braces that is not one of the above,
+/// such as `\textbf{{x}}` in LaTeX, is kept as it is.
+#[derive(Debug, Clone)]
+pub(crate) struct Template {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Value {
+        name: String,
+        default: Option<String>,
+        raw: bool,
+    },
+    If {
+        name: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Each {
+        name: String,
+        body: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Partial(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tag {
+    Value {
+        name: String,
+        default: Option<String>,
+        raw: bool,
+    },
+    If(String),
+    Each(String),
+    Else,
+    EndIf,
+    EndEach,
+    Partial(String),
+    Comment,
+}
+
+/// A problem with a template, such as an `{{#if}}` that is never closed
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateError {
+    pub message: String,
+    /// The line in the template, starting at 1, or 0 if it is not about a single line
+    pub line: usize,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            0 => write!(f, "{}", self.message),
+            line => write!(f, "line {}: {}", line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// The parts of a document that can be inserted into a template
+pub(crate) struct TemplateValues<'a> {
+    pub content: &'a str,
+    pub top: &'a str,
+    pub bottom: &'a str,
+    pub imports: &'a HashSet<String>,
+    pub metadata: &'a HashMap<String, String>,
+}
+
+/// A block that has been opened but not yet closed while parsing
+struct Frame {
+    tag: Tag,
+    line: usize,
+    nodes: Vec<Node>,
+    otherwise: Option<Vec<Node>>,
+}
+
+impl Frame {
+    fn current(&mut self) -> &mut Vec<Node> {
+        self.otherwise.as_mut().unwrap_or(&mut self.nodes)
+    }
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Template, TemplateError> {
+        let mut root = Vec::new();
+        let mut stack: Vec<Frame> = Vec::new();
+        let mut text = String::new();
+        let mut pos = 0;
+
+        while let Some(offset) = source[pos..].find("{{") {
+            let start = pos + offset;
+            let tag = source[start + 2..].find("}}").and_then(|length| {
+                let inner = &source[start + 2..start + 2 + length];
+                parse_tag(inner).map(|tag| (tag, start + 4 + length))
+            });
+
+            let (tag, mut end) = match tag {
+                Some(tag) => tag,
+                None => {
+                    // not a tag, keep the brace and look for a tag after it
+                    text.push_str(&source[pos..=start]);
+                    pos = start + 1;
+                    continue;
+                }
+            };
+
+            text.push_str(&source[pos..start]);
+
+            // a block tag on a line of its own does not leave an empty line behind
+            let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+            let rest_of_line = source[end..].find('\n').map_or(source.len(), |i| end + i);
+            let is_block_tag = !matches!(tag, Tag::Value { .. } | Tag::Partial(_));
+            let standalone = is_block_tag
+                && source[line_start..start].trim().is_empty()
+                && source[end..rest_of_line].trim().is_empty();
+            if standalone {
+                text.truncate(text.len() - (start - line_start));
+                end = (rest_of_line + 1).min(source.len());
+            }
+
+            let line = source[..start].matches('\n').count() + 1;
+            let nodes = stack.last_mut().map_or(&mut root, |frame| frame.current());
+            if !text.is_empty() {
+                nodes.push(Node::Text(std::mem::take(&mut text)));
+            }
+
+            match tag {
+                Tag::Value { name, default, raw } => nodes.push(Node::Value { name, default, raw }),
+                Tag::Partial(name) => nodes.push(Node::Partial(name)),
+                Tag::Comment => (),
+                Tag::If(_) | Tag::Each(_) => stack.push(Frame {
+                    tag,
+                    line,
+                    nodes: Vec::new(),
+                    otherwise: None,
+                }),
+                Tag::Else => match stack.last_mut() {
+                    Some(frame) if frame.otherwise.is_none() => frame.otherwise = Some(Vec::new()),
+                    _ => return Err(error("{{else}} outside of an {{#if}} or {{#each}}", line)),
+                },
+                Tag::EndIf | Tag::EndEach => {
+                    let frame = match stack.pop() {
+                        Some(frame) => frame,
+                        None => return Err(error("a block is closed but never opened", line)),
+                    };
+                    let otherwise = frame.otherwise.unwrap_or_default();
+                    let node = match (frame.tag, &tag) {
+                        (Tag::If(name), Tag::EndIf) => Node::If {
+                            name,
+                            then: frame.nodes,
+                            otherwise,
+                        },
+                        (Tag::Each(name), Tag::EndEach) => Node::Each {
+                            name,
+                            body: frame.nodes,
+                            otherwise,
+                        },
+                        (Tag::If(_), _) => return Err(error("expected {{/if}}", line)),
+                        _ => return Err(error("expected {{/each}}", line)),
+                    };
+                    stack
+                        .last_mut()
+                        .map_or(&mut root, |frame| frame.current())
+                        .push(node);
+                }
+            }
+
+            pos = end;
+        }
+
+        if let Some(frame) = stack.pop() {
+            let message = match frame.tag {
+                Tag::If(name) => format!("{{{{#if {}}}}} is never closed", name),
+                Tag::Each(name) => format!("{{{{#each {}}}}} is never closed", name),
+                _ => unreachable!(),
+            };
+            return Err(error(&message, frame.line));
+        }
+
+        text.push_str(&source[pos..]);
+        if !text.is_empty() {
+            root.push(Node::Text(text));
+        }
+
+        Ok(Template { nodes: root })
+    }
+
+    /// Render the template, metadata values are escaped with `escape` and
+    /// partials are loaded with `partial`. Partials that can not be loaded
+    /// are left out, use `check` to find them beforehand.
+    pub fn render(
+        &self,
+        values: &TemplateValues,
+        escape: &dyn Fn(&str) -> String,
+        partial: &dyn Fn(&str) -> Result<Template, TemplateError>,
+    ) -> String {
+        let renderer = Renderer {
+            values,
+            escape,
+            partial,
+        };
+        let mut output = String::new();
+        renderer.render(&self.nodes, None, 0, &mut output);
+        output
+    }
+
+    /// Make sure that all of the partials used by the template can be loaded
+    pub fn check(
+        &self,
+        partial: &dyn Fn(&str) -> Result<Template, TemplateError>,
+    ) -> Result<(), TemplateError> {
+        check_nodes(&self.nodes, partial, 0)
+    }
+}
+
+/// Render one of the templates that are built into the translators
+pub(crate) fn render_builtin(
+    source: &str,
+    values: &TemplateValues,
+    escape: &dyn Fn(&str) -> String,
+) -> String {
+    let template = Template::parse(source).expect("The built-in template is invalid");
+    template.render(values, escape, &|name| {
+        Err(error(&format!("the partial {} does not exist", name), 0))
+    })
+}
+
+/// Load a partial from a directory, or from the working directory
+pub(crate) fn load_partial(
+    directory: Option<&Path>,
+    name: &str,
+) -> Result<Template, TemplateError> {
+    let path = match directory {
+        Some(directory) => directory.join(name),
+        None => Path::new(name).to_path_buf(),
+    };
+
+    let source = fs::read_to_string(&path).map_err(|e| TemplateError {
+        message: format!("failed to read the partial {}: {}", path.display(), e),
+        line: 0,
+    })?;
+
+    Template::parse(&source).map_err(|e| TemplateError {
+        message: format!("in the partial {}: {}", name, e.message),
+        line: e.line,
+    })
+}
+
+fn error(message: &str, line: usize) -> TemplateError {
+    TemplateError {
+        message: message.to_string(),
+        line,
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Parse what is inside of a pair of double braces, if it is a tag
+fn parse_tag(inner: &str) -> Option<Tag> {
+    let trimmed = inner.trim();
+
+    if trimmed.starts_with('!') {
+        return Some(Tag::Comment);
+    }
+
+    if let Some(name) = trimmed.strip_prefix('>') {
+        let name = name.trim();
+        return (!name.is_empty() && !name.contains('{')).then(|| Tag::Partial(name.into()));
+    }
+
+    if let Some(block) = trimmed.strip_prefix('#') {
+        let (keyword, name) = block.split_once(char::is_whitespace)?;
+        let name = name.trim();
+        if !is_name(name) {
+            return None;
+        }
+        return match keyword {
+            "if" => Some(Tag::If(name.into())),
+            "each" => Some(Tag::Each(name.into())),
+            _ => None,
+        };
+    }
+
+    match trimmed {
+        "else" => return Some(Tag::Else),
+        "/if" => return Some(Tag::EndIf),
+        "/each" => return Some(Tag::EndEach),
+        _ => (),
+    }
+
+    let (name, default) = match inner.split_once('|') {
+        Some((name, default)) if !default.contains('{') => (name, Some(default.to_string())),
+        Some(_) => return None,
+        None => (inner, None),
+    };
+
+    let name = name.trim();
+    let (name, raw) = match name.strip_prefix("raw") {
+        Some(rest) if rest.starts_with(char::is_whitespace) => (rest.trim(), true),
+        _ => (name, false),
+    };
+
+    is_name(name).then(|| Tag::Value {
+        name: name.into(),
+        default,
+        raw,
+    })
+}
+
+fn check_nodes(
+    nodes: &[Node],
+    partial: &dyn Fn(&str) -> Result<Template, TemplateError>,
+    depth: usize,
+) -> Result<(), TemplateError> {
+    for node in nodes {
+        match node {
+            Node::If {
+                then, otherwise, ..
+            } => {
+                check_nodes(then, partial, depth)?;
+                check_nodes(otherwise, partial, depth)?;
+            }
+            Node::Each {
+                body, otherwise, ..
+            } => {
+                check_nodes(body, partial, depth)?;
+                check_nodes(otherwise, partial, depth)?;
+            }
+            Node::Partial(name) if depth >= MAX_PARTIAL_DEPTH => {
+                return Err(error(&format!("the partial {} includes itself", name), 0))
+            }
+            Node::Partial(name) => check_nodes(&partial(name)?.nodes, partial, depth + 1)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+struct Renderer<'a> {
+    values: &'a TemplateValues<'a>,
+    escape: &'a dyn Fn(&str) -> String,
+    partial: &'a dyn Fn(&str) -> Result<Template, TemplateError>,
+}
+
+impl<'a> Renderer<'a> {
+    fn render(&self, nodes: &[Node], item: Option<&str>, depth: usize, output: &mut String) {
+        for node in nodes {
+            match node {
+                Node::Text(text) => output.push_str(text),
+                Node::Value { name, default, raw } => match self.lookup(name, item) {
+                    Some((value, true)) => output.push_str(&value),
+                    Some((value, false)) if *raw => output.push_str(&value),
+                    Some((value, false)) => output.push_str(&(self.escape)(&value)),
+                    // the default is written in the template, so it is inserted as it is
+                    None => output.push_str(default.as_deref().unwrap_or_default()),
+                },
+                Node::If {
+                    name,
+                    then,
+                    otherwise,
+                } => {
+                    let is_set = self
+                        .lookup(name, item)
+                        .is_some_and(|(value, _)| !value.trim().is_empty());
+                    let branch = if is_set { then } else { otherwise };
+                    self.render(branch, item, depth, output);
+                }
+                Node::Each {
+                    name,
+                    body,
+                    otherwise,
+                } => {
+                    let value = self.lookup(name, item).map(|(value, _)| value);
+                    let items: Vec<&str> = value
+                        .as_deref()
+                        .unwrap_or_default()
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|item| !item.is_empty())
+                        .collect();
+
+                    if items.is_empty() {
+                        self.render(otherwise, item, depth, output);
+                    }
+                    for item in items {
+                        self.render(body, Some(item), depth, output);
+                    }
+                }
+                Node::Partial(name) if depth < MAX_PARTIAL_DEPTH => {
+                    if let Ok(template) = (self.partial)(name) {
+                        self.render(&template.nodes, item, depth + 1, output);
+                    }
+                }
+                Node::Partial(_) => (),
+            }
+        }
+    }
+
+    /// The value of a name, and whether it is already in the output format
+    fn lookup(&self, name: &str, item: Option<&str>) -> Option<(String, bool)> {
+        let values = self.values;
+        match name {
+            "this" => item.map(|item| (item.to_string(), false)),
+            "content" => Some((values.content.to_string(), true)),
+            "top" => Some((values.top.to_string(), true)),
+            "bottom" => Some((values.bottom.to_string(), true)),
+            "imports" => {
+                let mut imports: Vec<&String> = values.imports.iter().collect();
+                imports.sort();
+                Some((imports.iter().map(|i| format!("{}\n", i)).collect(), true))
+            }
+            field => values
+                .metadata
+                .get(field)
+                .map(|value| (value.clone(), false)),
+        }
+    }
+}
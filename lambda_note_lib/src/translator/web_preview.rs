@@ -1,11 +1,13 @@
+use super::template::{render_builtin, TemplateValues};
 use super::{Block, DocumentState, Html, Inline, OutputFormat, Translator};
+use crate::{Origin, OriginName};
 use std::collections::{HashMap, HashSet};
 
 /// A translator that transpiles into HTML code
 /// but also adds a message listener to reload the page as well as
-/// an anchor tag corresponding to each line in the source file.
+/// a `data-source-line` attribute on each block with its line in the source file.
 /// This makes it possible to "jump" from any given line in the source code and see how it will
-/// render as output.
+/// render as output, by posting `{ command: "scroll", line: 12 }` to the page.
 pub struct WebPreview {
     translator: Html,
 }
@@ -45,50 +47,94 @@ impl Translator for WebPreview {
         imports: &HashSet<String>,
         metadata: &HashMap<String, String>,
     ) -> String {
-        let mut import_str = String::new();
-        for import in imports {
-            import_str.push_str(import);
-            import_str.push('\n');
+        let values = TemplateValues {
+            content,
+            top,
+            bottom,
+            imports,
+            metadata,
+        };
+        render_builtin(TEMPLATE, &values, &|raw| self.escape_str(raw))
+    }
+
+    fn escape_str(&self, raw: &str) -> String {
+        self.translator.escape_str(raw)
+    }
+
+    fn annotate_block(&self, output: String, origin: &Origin) -> String {
+        annotate_source_line(output, origin)
+    }
+}
+
+/// Tag a block with its line in the source file
+pub(super) fn annotate_source_line(output: String, origin: &Origin) -> String {
+    // blocks from expansions are already inside of a block from the source
+    match origin.name {
+        OriginName::Filename(_) if origin.line_number > 0 => {
+            add_source_line(&output, origin.line_number)
         }
+        _ => output,
+    }
+}
+
+/// Add a `data-source-line` attribute to the first element of some HTML,
+/// or wrap it in a div if it does not start with an element
+fn add_source_line(html: &str, line: usize) -> String {
+    let attribute = format!(" data-source-line=\"{}\"", line);
+    let trimmed = html.trim_start();
+
+    let starts_with_element = trimmed.starts_with('<')
+        && trimmed[1..].starts_with(|c: char| c.is_ascii_alphabetic());
+
+    if starts_with_element {
+        let start = html.len() - trimmed.len();
+        let name_length = trimmed[1..]
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+            .unwrap_or(trimmed.len() - 1);
+        let end = start + 1 + name_length;
+        format!("{}{}{}", &html[..end], attribute, &html[end..])
+    } else {
+        format!("<div{}>{}</div>", attribute, html)
+    }
+}
 
-        format!(
-            r#"
+const TEMPLATE: &str = r#"
 <!DOCTYPE html>
-<html lang="{language}">
+<html lang="{{language|en}}">
 <head>
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    {imports}
+    {{imports}}
     <link rel="preconnect" href="https://fonts.googleapis.com">
     <link rel="preconnect" href="https://fonts.gstatic.com" crossorigin>
     <link href="https://fonts.googleapis.com/css2?family=Fira+Code&family=PT+Serif:ital,wght@0,400;0,700;1,400;1,700&family=Poppins:wght@700&display=swap" rel="stylesheet">
     <style>
-        html {{
+        html {
             scroll-behavior: smooth;
-        }}
+        }
         
-        *::-moz-selection, *::selection {{
+        *::-moz-selection, *::selection {
             background: #E2705B;
             color:white;
-        }}
+        }
 
-        h1, h2, h3, h4, h5, h6 {{
+        h1, h2, h3, h4, h5, h6 {
             font-family: 'Poppins', sans-serif;
-        }}
+        }
 
-        h1 {{
+        h1 {
             font-size: 1.7rem;
-        }}
+        }
 
-        h2 {{
+        h2 {
             font-size: 1.5rem;
-        }}
+        }
 
-        body, p {{
+        body, p {
             font-family: 'PT Serif', serif;
-        }}
+        }
 
-        .content {{
+        .content {
             box-sizing: border-box;
             padding:0.8rem;
             margin-top:2rem;
@@ -96,88 +142,94 @@ impl Translator for WebPreview {
             margin-left:auto;
             margin-right:auto;
             max-width:750px;
-        }}
+        }
 
-        img {{
+        img {
             max-width:100%;
-        }}
+        }
 
-        pre {{
+        pre {
             border-radius:0.3rem;
             padding:0.8rem;
             font-family: 'Fira Code', monospace;
             box-sizing:border-box;
             font-size:0.9rem;
             overflow-x:auto;
-        }}
+        }
+
+        .figure, .figcaption {
+            display:block;
+        }
 
-        hr {{
+        hr {
             margin-top:2rem;
             margin-bottom:2rem;
-        }}
+        }
 
-        @media print {{
-            hr {{ 
+        @media print {
+            hr { 
                 page-break-after: always;
                 visibility: hidden;
                 margin:0;
                 padding:0;
-            }}
+            }
 
-            .content {{
+            .content {
                 padding:0;
-            }}
-        }}
+            }
+        }
     </style>
     <script>
         // listen for an event to reload the window
-        window.addEventListener('message', event => {{ 
+        window.addEventListener('message', event => { 
                 // Data sent with postMessage is stored in event.data:
                 console.log(event.data); 
                 if (event.data === "reload")
                     window.location.reload()
-        }}); 
+                else if (event.data && event.data.command === "scroll")
+                    scrollToLine(event.data.line)
+        }); 
+
+        // scroll to the last block that starts at or before the given line
+        function scrollToLine(line) {
+            let target = null;
+            document.querySelectorAll('[data-source-line]').forEach(node => {
+                if (parseInt(node.dataset.sourceLine) <= line)
+                    target = node;
+            });
+            if (target)
+                target.scrollIntoView({ block: "start" });
+            else
+                window.scrollTo(0, 0);
+        }
     </script>
     <script>
         // any references to local files will be broken due to
-        // the browsers origin policy. To fix this, we host a server at
-        // localhost:5432, and use the following code updates all href and src
-        // tags in the page to point to the local server instead of directly to the local file.
-        document.addEventListener("DOMContentLoaded", function () {{
-            document.body.querySelectorAll('[href], [src]').forEach(node => {{
-                if (typeof node.href !== 'undefined') {{
-                    if (node.href.startsWith('http')) return;
-                    node.href = 'http://localhost:5432/' + node.href.replace("file:///", "");
-                }}
-
-                if (typeof node.src !== 'undefined') {{
-                    if (node.src.startsWith('http')) return;
-                    node.src = 'http://localhost:5432/' + node.src.replace("file:///", "");
-                }}
-            }});
-        }});
+        // the browsers origin policy. To fix this, the page is served by a local
+        // server, and the following code updates all href and src tags that point
+        // to a local file to point to the same server instead, whatever its port is.
+        document.addEventListener("DOMContentLoaded", function () {
+            document.body.querySelectorAll('[href], [src]').forEach(node => {
+                if (typeof node.href !== 'undefined') {
+                    if (!node.href.startsWith('file:')) return;
+                    node.href = '/' + node.href.replace("file:///", "");
+                }
+
+                if (typeof node.src !== 'undefined') {
+                    if (!node.src.startsWith('file:')) return;
+                    node.src = '/' + node.src.replace("file:///", "");
+                }
+            });
+        });
 
     </script>
-    <title>{title}</title>
+    <title>{{title|Document}}</title>
 </head>
 <body>
     <div class="content">
-{top}
-{content}
-{bottom}
+{{top}}
+{{content}}
+{{bottom}}
 </div>
 </body>
-</html>"#,
-            imports = import_str,
-            top = top,
-            bottom = bottom,
-            language = metadata.get("language").unwrap_or(&"en".to_string()),
-            title = metadata.get("title").unwrap_or(&"Document".to_string()),
-            content = content,
-        )
-    }
-
-    fn escape_str(&self, raw: &str) -> String {
-        self.translator.escape_str(raw)
-    }
-}
+</html>"#;